@@ -0,0 +1,171 @@
+// Bench builds a table over a key set with each backend and reports its build
+// time, size and query latency, so an algorithm can be picked for a workload
+// on its own keys:
+//
+//   cargo run --release --example bench -- keys.txt
+//   cargo run --release --example bench -- -n 1000000
+//
+// A key file holds one key per line. Without one, -n keys are generated,
+// default 1,000,000, of mixed lengths like identifiers or URLs.
+//
+// Latency is timed key by key over a shuffled pass over the keys, less the
+// cost of reading the clock, so the percentiles include cache misses as a
+// service looking up random keys would see them. The mean comes from
+// passes with the clock read only at either end, where the CPU overlaps
+// consecutive lookups.
+
+use std::env;
+use std::fs;
+use std::hint::black_box;
+use std::process;
+use std::time::{Duration, Instant};
+
+use mph_rs::{Algorithm, DynMph};
+
+const ALGORITHMS: [Algorithm; 5] = [
+    Algorithm::Displacement,
+    Algorithm::PTHash,
+    Algorithm::Bdz,
+    Algorithm::BBHash,
+    Algorithm::RecSplit,
+];
+
+// SAMPLES caps the lookups timed one at a time per backend.
+const SAMPLES: usize = 1_000_000;
+
+fn main() {
+    let keys = match keys() {
+        Ok(keys) => keys,
+        Err(msg) => {
+            eprintln!("bench: {}", msg);
+            eprintln!("usage: bench [keys.txt | -n count]");
+            process::exit(2);
+        }
+    };
+    let order = shuffled(keys.len());
+    println!("{} keys", keys.len());
+    println!(
+        "{:<14} {:>10} {:>9} {:>9} {:>9} {:>9}",
+        "algorithm", "build", "bits/key", "mean", "p50", "p99"
+    );
+
+    for algo in ALGORITHMS {
+        let start = Instant::now();
+        let t = algo.build(&keys);
+        let build = start.elapsed();
+
+        let mean = mean(&t, &keys, &order);
+        let mut lat = latencies(&t, &keys, &order);
+        lat.sort_unstable();
+        println!(
+            "{:<14} {:>10} {:>9.2} {:>9} {:>9} {:>9}",
+            format!("{:?}", algo),
+            format!("{:.1?}", build),
+            t.bits_per_key(),
+            ns(mean),
+            ns(percentile(&lat, 0.5)),
+            ns(percentile(&lat, 0.99)),
+        );
+    }
+}
+
+// keys reads the key file named on the command line, or generates keys.
+fn keys() -> Result<Vec<Vec<u8>>, String> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.as_slice() {
+        [] => Ok(generate(1_000_000)),
+        [flag, n] if flag == "-n" => match n.parse() {
+            Ok(n) if n > 0 => Ok(generate(n)),
+            _ => Err(format!("bad key count {:?}", n)),
+        },
+        [path] => {
+            let data = fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+            let mut keys: Vec<Vec<u8>> = data
+                .split(|&b| b == b'\n')
+                .filter(|k| !k.is_empty())
+                .map(|k| k.to_vec())
+                .collect();
+            keys.sort_unstable();
+            keys.dedup();
+            if keys.is_empty() {
+                return Err(format!("{}: no keys", path));
+            }
+            Ok(keys)
+        }
+        _ => Err("too many arguments".to_string()),
+    }
+}
+
+fn generate(n: usize) -> Vec<Vec<u8>> {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    (0..n)
+        .map(|i| {
+            let pad = "x".repeat(rng.next() as usize % 40);
+            format!("/item/{}/{}", i, pad).into_bytes()
+        })
+        .collect()
+}
+
+// shuffled returns 0..n in random order.
+fn shuffled(n: usize) -> Vec<usize> {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    let mut order: Vec<usize> = (0..n).collect();
+    for i in (1..n).rev() {
+        order.swap(i, rng.next() as usize % (i + 1));
+    }
+    order
+}
+
+// mean returns the time per lookup over the best of a few passes, which
+// leaves out most interruptions by other processes.
+fn mean(t: &DynMph, keys: &[Vec<u8>], order: &[usize]) -> Duration {
+    (0..3)
+        .map(|_| {
+            let start = Instant::now();
+            for &i in order {
+                black_box(t.query(&keys[i]));
+            }
+            start.elapsed() / order.len() as u32
+        })
+        .min()
+        .unwrap()
+}
+
+fn latencies(t: &DynMph, keys: &[Vec<u8>], order: &[usize]) -> Vec<Duration> {
+    let clock = (0..1000)
+        .map(|_| {
+            let start = Instant::now();
+            black_box(start.elapsed())
+        })
+        .min()
+        .unwrap();
+    order
+        .iter()
+        .take(SAMPLES)
+        .map(|&i| {
+            let start = Instant::now();
+            black_box(t.query(black_box(&keys[i])));
+            start.elapsed().saturating_sub(clock)
+        })
+        .collect()
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    sorted[((sorted.len() - 1) as f64 * p) as usize]
+}
+
+fn ns(d: Duration) -> String {
+    format!("{}ns", d.as_nanos())
+}
+
+// Rng is xorshift64, enough to shuffle and pad keys.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
@@ -0,0 +1,152 @@
+// Tables in an allocator of the caller's, with the allocator_api feature,
+// which needs a nightly compiler. An embedder can put a table's arrays in a
+// shared memory segment every process maps, or in an arena freed all at
+// once, rather than on the global heap.
+
+use std::alloc::Allocator;
+use std::hash::{BuildHasher, Hash};
+
+use crate::hash::{DefaultState, KeyHasher};
+use crate::rank::rank_by;
+use crate::{reduce, xorshift_mult64, Table};
+
+// TableIn is a table whose arrays live in an allocator A, made by
+// Table::clone_in. A may well be a reference, since &A is an Allocator for
+// any A that is, so that many tables can share one arena. Queries answer as
+// the table's did.
+pub struct TableIn<A: Allocator, S = DefaultState> {
+    hasher: KeyHasher<S>,
+    seeds: Vec<i32, A>,
+    values: Vec<i32, A>,
+    // in minimal tables, the occupied bits and their rank samples, laid out
+    // as in RankBits
+    occupied: Option<(Vec<u64, A>, Vec<u32, A>)>,
+    len: usize,
+    size: usize,
+}
+
+impl<S: Clone> Table<S> {
+    // CloneIn copies the table into alloc. Only its arrays are allocated
+    // there; the hash builder is cloned as it is.
+    pub fn clone_in<A: Allocator + Clone>(&self, alloc: A) -> TableIn<A, S> {
+        TableIn {
+            hasher: self.hasher.clone(),
+            seeds: copy_in(&self.seeds, &alloc),
+            values: copy_in(&self.values, &alloc),
+            occupied: self
+                .occupied
+                .as_ref()
+                .map(|bits| (copy_in(bits.words(), &alloc), copy_in(bits.ranks(), &alloc))),
+            len: self.len,
+            size: self.size,
+        }
+    }
+}
+
+fn copy_in<T: Copy, A: Allocator + Clone>(v: &[T], alloc: &A) -> Vec<T, A> {
+    let mut out = Vec::with_capacity_in(v.len(), alloc.clone());
+    out.extend_from_slice(v);
+    out
+}
+
+impl<A: Allocator, S> TableIn<A, S> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Allocator returns the allocator holding the arrays.
+    pub fn allocator(&self) -> &A {
+        self.seeds.allocator()
+    }
+}
+
+impl<A: Allocator, S: BuildHasher> TableIn<A, S> {
+    // Query looks up a key as Table::query does.
+    pub fn query<K: Hash + ?Sized>(&self, k: &K) -> usize {
+        let (hash, disp) = self.hasher.hash_pair(k);
+        self.lookup(hash, disp)
+    }
+
+    // QueryBytes looks up a binary key as Table::query_bytes does.
+    pub fn query_bytes<K: AsRef<[u8]> + ?Sized>(&self, k: &K) -> usize {
+        let (hash, disp) = self.hasher.hash_bytes_pair(k.as_ref());
+        self.lookup(hash, disp)
+    }
+
+    // QueryPrehashed looks up a digest as Table::query_prehashed does.
+    pub fn query_prehashed(&self, hash: u64) -> usize {
+        self.lookup(hash, hash)
+    }
+
+    // Lookup mirrors Table::lookup over the allocated arrays.
+    fn lookup(&self, hash: u64, disp: u64) -> usize {
+        let seed = self.seeds[reduce(hash, self.seeds.len())];
+        if seed < 0 {
+            let i = !seed as usize;
+            return if self.values.is_empty() {
+                i
+            } else {
+                self.values[i] as usize
+            };
+        }
+
+        let i = reduce(xorshift_mult64(disp.wrapping_add(seed as u64)), self.size);
+        match &self.occupied {
+            Some((words, ranks)) => rank_by(i, |b| ranks[b], |w| words[w]),
+            None if self.values.is_empty() => i,
+            None => self.values[i] as usize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::{AllocError, Allocator, Global, Layout};
+    use std::cell::Cell;
+    use std::ptr::NonNull;
+
+    use crate::TableBuilder;
+
+    // Counting allocates from the global heap, keeping count of the bytes
+    // it has out.
+    #[derive(Default)]
+    struct Counting(Cell<usize>);
+
+    unsafe impl Allocator for Counting {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.set(self.0.get() + layout.size());
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.0.set(self.0.get() - layout.size());
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    #[test]
+    fn clone_in() {
+        let keys: Vec<String> = (0..10000).map(|i| format!("k{}", i)).collect();
+        let arena = Counting::default();
+        for b in [
+            TableBuilder::new(),
+            TableBuilder::new().minimal(true),
+            TableBuilder::new().k(4),
+        ] {
+            let t = b.build(&keys);
+            let u = t.clone_in(&arena);
+            let rank = t.occupied.as_ref().map_or(0, |b| b.size_bits() / 8);
+            assert_eq!(arena.0.get(), (t.seeds.len() + t.values.len()) * 4 + rank);
+            assert_eq!(u.len(), keys.len());
+            for k in &keys {
+                assert_eq!(u.query(k), t.query(k));
+            }
+            drop(u);
+            assert_eq!(arena.0.get(), 0);
+        }
+    }
+}
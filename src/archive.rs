@@ -0,0 +1,189 @@
+// rkyv support. Table derives Archive, Serialize and Deserialize; the archived
+// form, ArchivedTable, answers queries in place, so a table stored inside a
+// larger archive is usable straight from the mapped bytes.
+
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+
+use rkyv::bytecheck::Verify;
+use rkyv::rancor::{Fallible, Source};
+use rkyv::with::{ArchiveWith, DeserializeWith, SerializeWith};
+use rkyv::{Archive, Place};
+
+use crate::hash::{ArchivedKeyHasher, KeyHasher, Normalizer};
+use crate::{reduce, xorshift_mult64, ArchivedTable};
+
+// ArchivedHasher is implemented by the archived hash builders, which rebuild
+// the hash builder they were archived from.
+pub trait ArchivedHasher {
+    type Hasher: BuildHasher;
+
+    fn hasher(&self) -> Self::Hasher;
+}
+
+impl<S: Archive> ArchivedTable<S>
+where
+    S::Archived: ArchivedHasher,
+{
+    // Query looks up a key as Table::query does.
+    pub fn query<K: Hash + ?Sized>(&self, k: &K) -> usize {
+        let (hash, disp) = self.key_hasher().hash_pair(k);
+        self.lookup(hash, disp)
+    }
+
+    // QueryBytes looks up a binary key as Table::query_bytes does.
+    pub fn query_bytes<K: AsRef<[u8]> + ?Sized>(&self, k: &K) -> usize {
+        let (hash, disp) = self.key_hasher().hash_bytes_pair(k.as_ref());
+        self.lookup(hash, disp)
+    }
+
+    // QueryPrehashed looks up a digest as Table::query_prehashed does.
+    pub fn query_prehashed(&self, hash: u64) -> usize {
+        self.lookup(hash, hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.to_native() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn key_hasher(&self) -> KeyHasher<<S::Archived as ArchivedHasher>::Hasher> {
+        let h = &self.hasher;
+        let mut hasher = KeyHasher::new(h.build.hasher());
+        hasher.seed = h.seed.to_native();
+        hasher.second_hash = h.second_hash;
+        hasher.fold_case = h.fold_case;
+        hasher.unicode_case = h.unicode_case;
+        hasher
+    }
+
+    // Lookup mirrors Table::lookup over the archived arrays.
+    fn lookup(&self, hash: u64, disp: u64) -> usize {
+        let seed = self.seeds[reduce(hash, self.seeds.len())].to_native();
+        if seed < 0 {
+            let i = (-seed - 1) as usize;
+            return if self.values.is_empty() {
+                i
+            } else {
+                self.values[i].to_native() as usize
+            };
+        }
+
+        let size = self.size.to_native() as usize;
+        let i = reduce(xorshift_mult64(disp.wrapping_add(seed as u64)), size);
+        match self.occupied.as_ref() {
+            Some(bits) => bits.rank(i),
+            None if self.values.is_empty() => i,
+            None => self.values[i].to_native() as usize,
+        }
+    }
+}
+
+// NoNormalizer archives a table's normalizer, or rather refuses to: it is a
+// function pointer, so a table using one fails to serialize instead of coming
+// back without it.
+pub(crate) struct NoNormalizer;
+
+#[derive(Debug)]
+struct NormalizerError;
+
+impl fmt::Display for NormalizerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "can't serialize a table with a normalizer")
+    }
+}
+
+impl Error for NormalizerError {}
+
+impl ArchiveWith<Option<Normalizer>> for NoNormalizer {
+    type Archived = ();
+    type Resolver = ();
+
+    fn resolve_with(_: &Option<Normalizer>, _: (), _: Place<()>) {}
+}
+
+impl<S: Fallible + ?Sized> SerializeWith<Option<Normalizer>, S> for NoNormalizer
+where
+    S::Error: Source,
+{
+    fn serialize_with(field: &Option<Normalizer>, _: &mut S) -> Result<(), S::Error> {
+        match field {
+            Some(_) => Err(S::Error::new(NormalizerError)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<D: Fallible + ?Sized> DeserializeWith<(), Option<Normalizer>, D> for NoNormalizer {
+    fn deserialize_with(_: &(), _: &mut D) -> Result<Option<Normalizer>, D::Error> {
+        Ok(None)
+    }
+}
+
+// An archived hasher that folds case with str::to_lowercase fails to check
+// in builds without the unicode-case feature, as such a table fails to load.
+#[derive(Debug)]
+struct UnicodeCaseError;
+
+impl fmt::Display for UnicodeCaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unicode case folding needs the unicode-case feature")
+    }
+}
+
+impl Error for UnicodeCaseError {}
+
+unsafe impl<S: Archive, C: Fallible + ?Sized> Verify<C> for ArchivedKeyHasher<S>
+where
+    C::Error: Source,
+{
+    fn verify(&self, _: &mut C) -> Result<(), C::Error> {
+        if self.unicode_case && !cfg!(feature = "unicode-case") {
+            return Err(C::Error::new(UnicodeCaseError));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rkyv::rancor::Error;
+
+    use crate::{ArchivedTable, Table, TableBuilder};
+
+    #[test]
+    fn archive() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("key{}", i)).collect();
+
+        for t in [
+            Table::new(&keys),
+            TableBuilder::new().minimal(true).seed(3).build(&keys),
+            TableBuilder::new().k(4).build(&keys),
+        ] {
+            let bytes = rkyv::to_bytes::<Error>(&t).unwrap();
+            let a = rkyv::access::<ArchivedTable, Error>(&bytes).unwrap();
+            assert_eq!(a.len(), keys.len());
+            for k in &keys {
+                assert_eq!(a.query(k), t.query(k));
+            }
+
+            let u: Table = rkyv::deserialize::<Table, Error>(a).unwrap();
+            assert_eq!(u.query("key9"), t.query("key9"));
+        }
+
+        let t = TableBuilder::new()
+            .normalize(|s| s.trim().into())
+            .build(&keys);
+        assert!(rkyv::to_bytes::<Error>(&t).is_err());
+
+        let mut t = TableBuilder::new().case_insensitive(true).build(&keys);
+        t.hasher.unicode_case = true;
+        let bytes = rkyv::to_bytes::<Error>(&t).unwrap();
+        let a = rkyv::access::<ArchivedTable, Error>(&bytes);
+        assert_eq!(a.is_ok(), cfg!(feature = "unicode-case"));
+    }
+}
@@ -0,0 +1,195 @@
+use std::hash::{BuildHasher, Hash};
+
+use crate::fmix64;
+use crate::hash::{DefaultState, KeyHasher};
+use crate::rank::RankBits;
+
+// Table is a BBHash-style minimal perfect hash: a cascade of bitmaps, one per
+// level. At each level the remaining keys hash into a bitmap of gamma bits
+// per key; keys that land alone set their bit and are done, the colliding ones
+// move down to the next level. A key's value is the rank of its bit across all
+// levels.
+//
+// With gamma = 1 this needs about 3 bits per key, far below Table's 64 bits
+// per slot, at the cost of a few more memory accesses per query. Each level
+// only depends on the keys left over by the one above it, so levels are easy
+// to build in parallel. As with BdzTable, query returns a unique value in
+// 0..n rather than the key's input index.
+pub struct Table<S = DefaultState> {
+    hasher: KeyHasher<S>,
+    // all levels' bitmaps, concatenated
+    bits: RankBits,
+    // bit offset of each level; the last entry is the total length
+    levels: Vec<usize>,
+    // hashes of keys still colliding after MAX_LEVELS, sorted
+    fallback: Vec<u64>,
+}
+
+// After this many levels the few keys left are kept in a sorted list.
+const MAX_LEVELS: usize = 32;
+
+// Builder configures a bbhash Table.
+#[derive(Clone, Debug)]
+pub struct Builder<S = DefaultState> {
+    build: S,
+    gamma: f64,
+}
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder {
+            build: DefaultState::default(),
+            gamma: 2.0,
+        }
+    }
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        Builder::default()
+    }
+}
+
+impl<S: BuildHasher + Clone> Builder<S> {
+    // Gamma sets the bitmap bits per remaining key at each level (default 2).
+    // Larger values mean fewer collisions, so fewer levels and faster builds
+    // and queries, but more space: about 3 bits per key at gamma = 1 and 3.7 at
+    // gamma = 2.
+    pub fn gamma(mut self, gamma: f64) -> Builder<S> {
+        assert!(gamma >= 1.0, "gamma must be at least 1");
+        self.gamma = gamma;
+        self
+    }
+
+    // WithHasher switches the hash function to the one built by hash_builder.
+    pub fn with_hasher<T: BuildHasher + Clone>(self, hash_builder: T) -> Builder<T> {
+        Builder {
+            build: hash_builder,
+            gamma: self.gamma,
+        }
+    }
+
+    // Build constructs a table over keys.
+    pub fn build<K: Hash>(&self, keys: &[K]) -> Table<S> {
+        let hasher = KeyHasher::new(self.build.clone());
+        let mut remaining: Vec<u64> = keys.iter().map(|k| hasher.hash(k)).collect();
+
+        let mut words: Vec<u64> = Vec::new();
+        let mut levels = vec![0];
+        for level in 0..MAX_LEVELS {
+            if remaining.is_empty() {
+                break;
+            }
+
+            let m = ((remaining.len() as f64 * self.gamma).ceil() as usize).div_ceil(64) * 64;
+            let mut seen = vec![0u64; m / 64];
+            let mut collide = vec![0u64; m / 64];
+            for &h in &remaining {
+                let pos = position(h, level, m);
+                let bit = 1 << (pos % 64);
+                if seen[pos / 64] & bit != 0 {
+                    collide[pos / 64] |= bit;
+                }
+                seen[pos / 64] |= bit;
+            }
+
+            remaining.retain(|&h| {
+                let pos = position(h, level, m);
+                collide[pos / 64] & (1 << (pos % 64)) != 0
+            });
+
+            for (s, c) in seen.iter().zip(&collide) {
+                words.push(s & !c);
+            }
+            levels.push(levels[level] + m);
+        }
+
+        remaining.sort_unstable();
+        let before = remaining.len();
+        remaining.dedup();
+        assert_eq!(before, remaining.len(), "bbhash: duplicate keys");
+
+        Table {
+            hasher,
+            bits: RankBits::new(words),
+            levels,
+            fallback: remaining,
+        }
+    }
+}
+
+impl Table {
+    pub fn new<K: Hash>(keys: &[K]) -> Table {
+        Builder::new().build(keys)
+    }
+}
+
+impl<S: BuildHasher> Table<S> {
+    // Query returns the value in 0..len() assigned to k. Keys that were not in
+    // the input get an arbitrary value.
+    pub fn query<K: Hash + ?Sized>(&self, k: &K) -> usize {
+        let h = self.hasher.hash(k);
+        for level in 0..self.levels.len() - 1 {
+            let m = self.levels[level + 1] - self.levels[level];
+            let pos = self.levels[level] + position(h, level, m);
+            if self.bits.get(pos) {
+                return self.bits.rank(pos);
+            }
+        }
+
+        let i = self.fallback.binary_search(&h).unwrap_or(0);
+        self.bits.ones() + i
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits.ones() + self.fallback.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // BitsPerKey reports the space used by the bitmaps and rank samples.
+    pub fn bits_per_key(&self) -> f64 {
+        let bits = self.bits.size_bits() + self.fallback.len() * 64;
+        bits as f64 / self.len().max(1) as f64
+    }
+}
+
+// Position picks h's bit in a level's bitmap of m bits.
+fn position(h: u64, level: usize, m: usize) -> usize {
+    let x = fmix64(h ^ (level as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    ((x as u128 * m as u128) >> 64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bbhash() {
+        for &gamma in &[1.0, 2.0, 5.0] {
+            let keys: Vec<String> = (0..10_000).map(|i| format!("key{}", i)).collect();
+
+            let t = Builder::new().gamma(gamma).build(&keys);
+            assert_eq!(t.len(), keys.len());
+
+            let mut seen = vec![false; keys.len()];
+            for k in &keys {
+                let v = t.query(k);
+                assert!(!seen[v], "duplicate value {}", v);
+                seen[v] = true;
+            }
+        }
+
+        let t = Table::new(&Vec::<u32>::new());
+        assert!(t.is_empty());
+    }
+
+    #[test]
+    fn bits_per_key() {
+        let keys: Vec<u64> = (0..100_000).collect();
+        let t = Builder::new().gamma(1.0).build(&keys);
+        assert!(t.bits_per_key() < 5.0, "{}", t.bits_per_key());
+    }
+}
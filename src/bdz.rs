@@ -0,0 +1,241 @@
+use std::hash::{BuildHasher, Hash};
+
+use crate::fmix64;
+use crate::hash::{DefaultState, KeyHasher};
+
+// BdzTable is a minimal perfect hash built by peeling a random 3-hypergraph
+// (the BDZ/MWHC construction). Each key becomes an edge between three
+// vertices; once every edge is peeled, storing two bits per vertex is enough
+// to send each key to a distinct vertex, and ranking the used vertices maps
+// that onto 0..n.
+//
+// Unlike Table, construction runs in linear time: a random seed either gives
+// a peelable graph or is thrown away, and each seed succeeds with high
+// probability. Note query returns a unique value in 0..n for each key, but not
+// the key's index in the input.
+pub struct BdzTable<S = DefaultState> {
+    hasher: KeyHasher<S>,
+    seed: u64,
+    // vertices per part; the graph has 3*r vertices
+    r: usize,
+    // 2-bit vertex values, 32 per word; 3 marks an unused vertex
+    g: Vec<u64>,
+    // used vertices before each RANK_WORDS-word block
+    ranks: Vec<u32>,
+    len: usize,
+}
+
+// Vertices per key: 1.23 is the peelability threshold for 3-hypergraphs.
+pub(crate) const C: f64 = 1.23;
+
+const RANK_WORDS: usize = 8;
+
+// MAX_ATTEMPTS bounds the seed search; failing this often means the input
+// has duplicate keys.
+pub(crate) const MAX_ATTEMPTS: u64 = 64;
+
+impl BdzTable {
+    pub fn new<K: Hash>(keys: &[K]) -> BdzTable {
+        BdzTable::with_hasher(keys, DefaultState::default())
+    }
+}
+
+impl<S: BuildHasher + Clone> BdzTable<S> {
+    // WithHasher builds a table over keys hashed with hash_builder.
+    pub fn with_hasher<K: Hash>(keys: &[K], hash_builder: S) -> BdzTable<S> {
+        let mut hasher = KeyHasher::new(hash_builder);
+
+        let n = keys.len();
+        let r = ((C * n as f64 / 3.0).ceil() as usize).max(2);
+
+        let mut hashes: Vec<u64> = keys.iter().map(|k| hasher.hash(k)).collect();
+
+        for seed in 1..=MAX_ATTEMPTS {
+            if let Some(g) = assign(&hashes, seed, r) {
+                let ranks = rank_blocks(&g);
+                return BdzTable {
+                    hasher,
+                    seed,
+                    r,
+                    g,
+                    ranks,
+                    len: n,
+                };
+            }
+
+            // a few failures in a row hint at two keys sharing a 64-bit hash;
+            // rehashing with a new seed separates them
+            if seed % 8 == 0 {
+                hasher.seed = seed;
+                hashes = keys.iter().map(|k| hasher.hash(k)).collect();
+            }
+        }
+
+        panic!("bdz: no peelable graph found; are the keys distinct?");
+    }
+}
+
+impl<S: BuildHasher> BdzTable<S> {
+    // Query returns the value in 0..len() assigned to k. Keys that were not in
+    // the input get an arbitrary value.
+    pub fn query<K: Hash + ?Sized>(&self, k: &K) -> usize {
+        let e = edge(self.hasher.hash(k), self.seed, self.r);
+        let j = (get(&self.g, e[0]) + get(&self.g, e[1]) + get(&self.g, e[2])) % 3;
+        self.rank(e[j as usize])
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // BitsPerKey reports the space used by the vertex values and rank samples.
+    pub fn bits_per_key(&self) -> f64 {
+        let bits = self.g.len() * 64 + self.ranks.len() * 32;
+        bits as f64 / self.len.max(1) as f64
+    }
+
+    // Rank counts the used vertices before v.
+    fn rank(&self, v: usize) -> usize {
+        let w = v / 32;
+        let block = w / RANK_WORDS;
+        let mut rank = self.ranks[block] as usize;
+        for &word in &self.g[block * RANK_WORDS..w] {
+            rank += used(word);
+        }
+        let bits = (v % 32) * 2;
+        if bits != 0 {
+            // pad the masked-off values with 3s so they don't count as used
+            let word = self.g[w] | (!0u64 << bits);
+            rank += used(word);
+        }
+        rank
+    }
+}
+
+// Edge derives the three vertices, one per part, that key hash h touches.
+pub(crate) fn edge(h: u64, seed: u64, r: usize) -> [usize; 3] {
+    let a = fmix64(h ^ seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    let b = fmix64(a ^ 0x2545_f491_4f6c_dd1d);
+    let c = fmix64(b ^ 0x2545_f491_4f6c_dd1d);
+    [reduce(a, r), r + reduce(b, r), 2 * r + reduce(c, r)]
+}
+
+// reduce maps x onto 0..n without a division.
+fn reduce(x: u64, n: usize) -> usize {
+    ((x as u128 * n as u128) >> 64) as usize
+}
+
+// Assign peels the graph for seed and, if every edge could be peeled, returns
+// the packed vertex values.
+fn assign(hashes: &[u64], seed: u64, r: usize) -> Option<Vec<u64>> {
+    let (edges, peeled) = peel(hashes, seed, r)?;
+
+    // walk back through the peeling order: each edge's free vertex is still
+    // unset, so it can be chosen to make the edge's sum select it
+    let mut g = vec![!0u64; (3 * r).div_ceil(32)];
+    for &(id, v) in peeled.iter().rev() {
+        let e = &edges[id];
+        let j = e.iter().position(|&u| u == v).unwrap() as u64;
+        let sum: u64 = e.iter().filter(|&&u| u != v).map(|&u| get(&g, u) % 3).sum();
+        set(&mut g, v, (j + 6 - sum) % 3);
+    }
+    Some(g)
+}
+
+// Peeling holds a graph's edges and its peeling order.
+pub(crate) type Peeling = (Vec<[usize; 3]>, Vec<(usize, usize)>);
+
+// Peel builds the 3-hypergraph of hashes for seed and removes edges with a
+// vertex of degree one until none are left. On success it returns the edges
+// and, in peeling order, each edge's id with the vertex it was peeled from;
+// walking that back, every edge has a vertex no later edge touches.
+pub(crate) fn peel(hashes: &[u64], seed: u64, r: usize) -> Option<Peeling> {
+    let m = 3 * r;
+    let edges: Vec<[usize; 3]> = hashes.iter().map(|&h| edge(h, seed, r)).collect();
+
+    // degree and xor of incident edge ids are enough to peel: a vertex of
+    // degree one has its only edge in xor
+    let mut degree = vec![0u32; m];
+    let mut xor = vec![0usize; m];
+    for (id, e) in edges.iter().enumerate() {
+        for &v in e {
+            degree[v] += 1;
+            xor[v] ^= id;
+        }
+    }
+
+    let mut queue: Vec<usize> = (0..m).filter(|&v| degree[v] == 1).collect();
+    let mut peeled: Vec<(usize, usize)> = Vec::with_capacity(edges.len());
+    while let Some(v) = queue.pop() {
+        if degree[v] != 1 {
+            continue;
+        }
+        let id = xor[v];
+        peeled.push((id, v));
+        for &u in &edges[id] {
+            degree[u] -= 1;
+            xor[u] ^= id;
+            if degree[u] == 1 {
+                queue.push(u);
+            }
+        }
+    }
+
+    if peeled.len() != edges.len() {
+        return None;
+    }
+    Some((edges, peeled))
+}
+
+fn get(g: &[u64], v: usize) -> u64 {
+    (g[v / 32] >> ((v % 32) * 2)) & 3
+}
+
+fn set(g: &mut [u64], v: usize, x: u64) {
+    let shift = (v % 32) * 2;
+    g[v / 32] = (g[v / 32] & !(3 << shift)) | (x << shift);
+}
+
+// used counts the values in word that aren't 3.
+fn used(word: u64) -> usize {
+    let unused = word & (word >> 1) & 0x5555_5555_5555_5555;
+    32 - unused.count_ones() as usize
+}
+
+fn rank_blocks(g: &[u64]) -> Vec<u32> {
+    let mut ranks = Vec::with_capacity(g.len() / RANK_WORDS + 1);
+    let mut rank = 0;
+    for block in g.chunks(RANK_WORDS) {
+        ranks.push(rank as u32);
+        rank += block.iter().map(|&w| used(w)).sum::<usize>();
+    }
+    ranks.push(rank as u32);
+    ranks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bdz() {
+        for &n in &[0, 1, 2, 3, 10, 100, 10_000] {
+            let keys: Vec<String> = (0..n).map(|i| format!("key{}", i)).collect();
+
+            let t = BdzTable::new(&keys);
+            assert_eq!(t.len(), n);
+
+            let mut seen = vec![false; n];
+            for k in &keys {
+                let v = t.query(k);
+                assert!(v < n);
+                assert!(!seen[v], "duplicate value {}", v);
+                seen[v] = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,1312 @@
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::io;
+use std::mem;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::external::Spill;
+use crate::hash::{self, DefaultState, HashAlgo, KeyHasher, Normalizer};
+use crate::partitioned::Shards;
+use crate::{Params, PartitionedTable, Run, Scratch, Table, REPORT_EVERY};
+
+// TableBuilder configures how a Table is constructed. The options that affect
+// hashing are recorded in the table so queries behave the same way.
+#[derive(Clone, Debug, Default)]
+pub struct TableBuilder<S = DefaultState> {
+    hasher: KeyHasher<S>,
+    random_seed: bool,
+    params: Params,
+    record_time: bool,
+    comment: String,
+    cancel: Option<CancellationToken>,
+    time_budget: Option<Duration>,
+    progress: Option<ProgressFn>,
+}
+
+// BuildStats reports how a build went, for callers that want to notice key
+// sets that are hard to place.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BuildStats {
+    // times a bucket ran out of seeds and the build started over on a
+    // bigger table
+    pub retries: usize,
+    // buckets displaced by others during the seed search
+    pub evictions: usize,
+    // seeds tried on multi-key buckets, each costing a hash per key, which
+    // makes it the bulk of a build's work
+    pub seeds_tried: usize,
+}
+
+// BuildError describes why a build stopped without a table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BuildError {
+    // The build's CancellationToken was cancelled.
+    Cancelled,
+    // The build ran past its time budget, with the work done until then.
+    TimedOut(BuildStats),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::Cancelled => write!(f, "build cancelled"),
+            BuildError::TimedOut(stats) => write!(
+                f,
+                "build ran out of time after {} retries and {} evictions",
+                stats.retries, stats.evictions
+            ),
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+// A build that reports io errors, such as build_external, reports a cancelled
+// one as Interrupted and one out of time as TimedOut, with the BuildError as
+// the source.
+impl From<BuildError> for io::Error {
+    fn from(err: BuildError) -> io::Error {
+        let kind = match err {
+            BuildError::Cancelled => io::ErrorKind::Interrupted,
+            BuildError::TimedOut(_) => io::ErrorKind::TimedOut,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+// CancellationToken stops builds from another thread. Clones share one flag:
+// hand a clone to TableBuilder::cancellation and call cancel on another,
+// e.g. when a newer key set makes the build pointless. The seed search
+// checks the flag before each bucket and every thousand or so seeds, so a
+// build stops soon after, with BuildError::Cancelled from try_build.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// Phase is a stage of a build, as reported to TableBuilder::on_progress.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Phase {
+    // Hashing the keys.
+    Hashing,
+    // Grouping the hashes into buckets.
+    Sorting,
+    // Searching for seeds for the buckets, largest first.
+    Placement,
+    // Putting the single-key buckets into the slots left free.
+    PatchUp,
+}
+
+// Progress is a snapshot of a running build.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Progress {
+    pub phase: Phase,
+    // keys in the build; while hashing, the keys hashed so far
+    pub keys: usize,
+    // non-empty buckets, 0 until placement starts
+    pub buckets: usize,
+    // buckets and keys with a place; evictions take them back
+    pub buckets_placed: usize,
+    pub keys_placed: usize,
+}
+
+impl Progress {
+    pub(crate) fn new(phase: Phase, keys: usize) -> Progress {
+        Progress {
+            phase,
+            keys,
+            buckets: 0,
+            buckets_placed: 0,
+            keys_placed: 0,
+        }
+    }
+}
+
+// ProgressFn is the callback set by on_progress, shared by the builder's
+// clones and the threads of a parallel build.
+#[derive(Clone)]
+pub(crate) struct ProgressFn(Arc<Mutex<dyn FnMut(Progress) + Send>>);
+
+impl ProgressFn {
+    pub(crate) fn call(&self, p: Progress) {
+        let mut f = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        (*f)(p)
+    }
+}
+
+impl fmt::Debug for ProgressFn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ProgressFn")
+    }
+}
+
+// TUNE_COST is the most work per key tune_from_sample accepts, in seeds
+// tried, about sixteen times what a build with the default options takes.
+const TUNE_COST: f64 = 16.0;
+
+// TUNE_MAX_SEED caps the seed search of each trial, so a hopeless point fails
+// fast rather than searching on.
+const TUNE_MAX_SEED: u64 = 1 << 14;
+
+// HashedKeys is the output of a build's hashing phase: every key's hash, and
+// its displacement hash with second_hash, along with the hasher that made
+// them. TableBuilder::build_hashed places them, as many times as needed,
+// without going back to the keys.
+#[derive(Clone, Debug)]
+pub struct HashedKeys<S = DefaultState> {
+    hasher: KeyHasher<S>,
+    hashes: Vec<u64>,
+    // empty unless second_hash is set
+    disp: Vec<u64>,
+}
+
+impl<S> HashedKeys<S> {
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    fn disp(&self) -> Option<&[u64]> {
+        if self.hasher.second_hash {
+            Some(&self.disp)
+        } else {
+            None
+        }
+    }
+}
+
+impl TableBuilder {
+    pub fn new() -> TableBuilder {
+        TableBuilder::default()
+    }
+}
+
+impl<S: BuildHasher + Clone> TableBuilder<S> {
+    // WithHasher switches the hash function to the one built by hash_builder.
+    // The table keeps a copy so queries hash the same way.
+    pub fn with_hasher<T: BuildHasher + Clone>(self, hash_builder: T) -> TableBuilder<T> {
+        TableBuilder {
+            hasher: KeyHasher {
+                build: hash_builder,
+                seed: self.hasher.seed,
+                second_hash: self.hasher.second_hash,
+                fold_case: self.hasher.fold_case,
+                unicode_case: self.hasher.unicode_case,
+                normalize: self.hasher.normalize,
+            },
+            random_seed: self.random_seed,
+            params: self.params,
+            record_time: self.record_time,
+            comment: self.comment,
+            cancel: self.cancel,
+            time_budget: self.time_budget,
+            progress: self.progress,
+        }
+    }
+
+    // HashAlgo selects one of the crate's own hash functions. The choice is
+    // stored in the table, so query always dispatches to the right one.
+    pub fn hash_algo(self, algo: HashAlgo) -> TableBuilder<HashAlgo> {
+        self.with_hasher(algo)
+    }
+
+    // Seed mixes a global seed into every key's hash. Tables built over the
+    // same keys with different seeds are independent of each other, while the
+    // same seed always reproduces the same table. The seed is stored in the
+    // table for queries.
+    pub fn seed(mut self, seed: u64) -> TableBuilder<S> {
+        self.hasher.seed = seed;
+        self
+    }
+
+    // RandomSeed draws a fresh seed from the OS for every build, overriding
+    // any fixed seed. Worst-case inputs then can't be replayed: a key set that
+    // was slow to place once is unlikely to be slow again. The seed drawn is
+    // stored in the table and reported by Table::seed.
+    pub fn random_seed(mut self, yes: bool) -> TableBuilder<S> {
+        self.random_seed = yes;
+        self
+    }
+
+    // SecondHash derives each key's displacement from an independent second
+    // hash instead of reusing the bucket hash. Keys that collide in a bucket
+    // then scatter independently, which helps on structured or adversarial
+    // key sets, at the cost of hashing every key twice on build and query.
+    pub fn second_hash(mut self, yes: bool) -> TableBuilder<S> {
+        self.hasher.second_hash = yes;
+        self
+    }
+
+    // Lambda sets the average number of keys per bucket (default 1). Each
+    // bucket stores one seed, so larger values shrink the table by up to a
+    // factor of lambda, while the seed search for the bigger buckets makes
+    // construction slower. Values between 3 and 5 are a good tradeoff.
+    pub fn lambda(mut self, lambda: f64) -> TableBuilder<S> {
+        assert!(lambda > 0.0, "lambda must be positive");
+        self.params.lambda = lambda;
+        self
+    }
+
+    // LoadFactor sets the ratio of keys to slots, in (0, 1] (default 1), before
+    // any rounding up to a power of two. Slack slots make the seed search for
+    // big buckets dramatically faster; fewer save memory. With fastrange,
+    // load_factor(0.95) gives a table of about 1.05 * n slots.
+    pub fn load_factor(mut self, load_factor: f64) -> TableBuilder<S> {
+        assert!(
+            load_factor > 0.0 && load_factor <= 1.0,
+            "load factor must be in (0, 1]"
+        );
+        self.params.load_factor = load_factor;
+        self
+    }
+
+    // Fastrange sizes the table to exactly n slots and n/lambda buckets
+    // instead of rounding both up to a power of two, which can nearly double
+    // memory for key counts just past one. Queries then reduce hashes with a
+    // multiply-shift in place of a mask, which costs slightly more.
+    pub fn fastrange(mut self, yes: bool) -> TableBuilder<S> {
+        self.params.fastrange = yes;
+        self
+    }
+
+    // ShuffleSeeds has each bucket try its seeds in a pseudo-random order
+    // derived from its keys, rather than counting up from 1, which can find
+    // seeds for large buckets in fewer attempts. Queries are unaffected, but
+    // the seeds stored are spread over the whole search range instead of
+    // mostly small, and the table differs from an unshuffled build.
+    pub fn shuffle_seeds(mut self, yes: bool) -> TableBuilder<S> {
+        self.params.shuffle = yes;
+        self
+    }
+
+    // CompressSeeds has buckets of one key search for a seed, as larger ones
+    // do, rather than take a free slot directly, which stores the slot in
+    // the seed; only those that find none among the first thirteen seeds,
+    // mostly the last to be placed, still do. Nearly all seeds then fit in
+    // four bits, and Table::compact stores them that way, in under 5 bits a
+    // bucket where it would take over 6. That takes slots to spare: in a
+    // table its keys nearly fill, minimal or with the slots rounded up to
+    // a power of two only just above the keys, too many buckets are still
+    // placed directly, and compact stores them as cheaply either way. It
+    // applies to tables with one key per slot, and shuffle_seeds, which
+    // spreads the seeds out, defeats it.
+    pub fn compress_seeds(mut self, yes: bool) -> TableBuilder<S> {
+        self.params.compress_seeds = yes;
+        self
+    }
+
+    // HugePages asks, on Linux, for the seeds and values to be backed by 2MB
+    // pages, which spares lookups in tables of gigabytes most of their TLB
+    // misses. It takes transparent huge pages enabled in the kernel, as they
+    // usually are, and has no effect on other systems or on the table built.
+    pub fn huge_pages(mut self, yes: bool) -> TableBuilder<S> {
+        self.params.huge_pages = yes;
+        self
+    }
+
+    // Minimal drops the values array and numbers the occupied slots with a
+    // rank structure instead, so the table costs a seed per bucket plus about
+    // 1.1 bits per slot. Query then returns a unique value in 0..n for each
+    // key, as BdzTable does, rather than the key's index in the input.
+    pub fn minimal(mut self, yes: bool) -> TableBuilder<S> {
+        self.params.minimal = yes;
+        self
+    }
+
+    // K lets up to k keys share a slot, making a k-perfect hash over
+    // ceil(n/k) slots (rounded up to a power of two unless fastrange is set).
+    // The seed search only has to avoid full slots, so construction is much
+    // faster. Query returns the key's slot rather than its index in the input;
+    // minimal has no effect.
+    pub fn k(mut self, k: usize) -> TableBuilder<S> {
+        assert!(k > 0, "k must be positive");
+        self.params.k = k;
+        self
+    }
+
+    // CaseInsensitive folds the ASCII case of string keys before hashing, at
+    // both build and query time. With the unicode-case feature, keys that are
+    // valid UTF-8 are lowercased with str::to_lowercase instead, and the
+    // table records it, so builds without the feature refuse to load it.
+    pub fn case_insensitive(mut self, yes: bool) -> TableBuilder<S> {
+        self.hasher.fold_case = yes;
+        self.hasher.unicode_case = yes && cfg!(feature = "unicode-case");
+        self
+    }
+
+    // Normalize applies f to string keys before hashing, at both build and
+    // query time, e.g. to trim whitespace or strip a common prefix. It runs
+    // before any case folding.
+    pub fn normalize(mut self, f: Normalizer) -> TableBuilder<S> {
+        self.hasher.normalize = Some(f);
+        self
+    }
+
+    // RecordTime stores the time of each build in the table's metadata. It is
+    // off by default, so that the same keys and options always serialize to
+    // the same bytes.
+    pub fn record_time(mut self, yes: bool) -> TableBuilder<S> {
+        self.record_time = yes;
+        self
+    }
+
+    // Comment stores text in the table's metadata, such as where its keys
+    // came from, for Table::metadata to report.
+    pub fn comment(mut self, text: &str) -> TableBuilder<S> {
+        assert!(text.len() <= u32::MAX as usize, "comment too long");
+        self.comment = text.to_string();
+        self
+    }
+
+    // Cancellation lets token stop the builds, which then return
+    // BuildError::Cancelled from try_build and the other try_ methods, an
+    // Interrupted io::Error from build_external, and panic from the other
+    // build methods.
+    pub fn cancellation(mut self, token: CancellationToken) -> TableBuilder<S> {
+        self.cancel = Some(token);
+        self
+    }
+
+    // TimeBudget bounds how long a build may take, from the start of hashing.
+    // A build past it stops as a cancelled one does, with
+    // BuildError::TimedOut from try_build, so a service can give up on a
+    // rebuild that would stall and keep its old table. A partitioned build
+    // has the one budget for all its partitions.
+    pub fn time_budget(mut self, budget: Duration) -> TableBuilder<S> {
+        self.time_budget = Some(budget);
+        self
+    }
+
+    // OnProgress calls f as builds go, at the start of each phase and every
+    // few thousand keys or buckets within one, so long builds can show how
+    // far along they are. A partitioned build reports on each partition in
+    // turn, or, built in parallel, on all of them at once.
+    pub fn on_progress<F>(mut self, f: F) -> TableBuilder<S>
+    where
+        F: FnMut(Progress) + Send + 'static,
+    {
+        self.progress = Some(ProgressFn(Arc::new(Mutex::new(f))));
+        self
+    }
+
+    // TuneFromSample picks lambda and load_factor for a build over keys like
+    // those in sample. It builds trial tables over the sample with the other
+    // options as set, for lambda from 1 to 6 and load factors from 0.8 to 1,
+    // and keeps the smallest whose build tried at most TUNE_COST seeds per
+    // key; fewer seeds tried means a faster build, and unlike timings, the
+    // count is the same on every run and machine. The trials size their
+    // arrays exactly, as fastrange does, so rounding doesn't hide the
+    // differences. A sample of ten thousand keys or so tunes in a fraction of
+    // a second; an empty one leaves the options as they are.
+    pub fn tune_from_sample<K: Hash>(mut self, sample: &[K]) -> TableBuilder<S> {
+        if sample.is_empty() {
+            return self;
+        }
+        let keys = self.hash_keys(sample);
+        let mut scratch = Scratch::new();
+        let mut best: Option<(f64, f64, f64)> = None;
+        for lambda in [1.0, 2.0, 3.0, 4.0, 5.0, 6.0] {
+            // from the easiest load factor up, until one costs too much
+            let mut fits = false;
+            for load_factor in [0.8, 0.9, 0.95, 0.99, 1.0] {
+                let params = Params {
+                    lambda,
+                    load_factor,
+                    fastrange: true,
+                    max_seed: TUNE_MAX_SEED,
+                    ..self.params
+                };
+                let (t, stats) = built(Table::build_with_stats(
+                    &keys.hashes,
+                    keys.disp(),
+                    keys.hasher.clone(),
+                    &params,
+                    &mut scratch,
+                    Run::default(),
+                ));
+                let cost = stats.seeds_tried as f64 / sample.len() as f64;
+                if stats.retries > 0 || cost > TUNE_COST {
+                    break;
+                }
+                fits = true;
+                let bits = t.bits_per_key();
+                if best.is_none_or(|(b, _, _)| bits < b) {
+                    best = Some((bits, lambda, load_factor));
+                }
+                scratch.recycle(t);
+            }
+            if !fits {
+                break;
+            }
+        }
+        if let Some((_, lambda, load_factor)) = best {
+            self.params.lambda = lambda;
+            self.params.load_factor = load_factor;
+        }
+        self
+    }
+
+    // Build constructs a table over keys with the configured options.
+    pub fn build<K: Hash>(&self, keys: &[K]) -> Table<S> {
+        self.build_iter(keys)
+    }
+
+    // TryBuild is build, returning an error instead of panicking if the
+    // build is cancelled or runs out of time.
+    pub fn try_build<K: Hash>(&self, keys: &[K]) -> Result<Table<S>, BuildError> {
+        let (t, _) = self.build_in(keys, &mut Scratch::new())?;
+        Ok(t)
+    }
+
+    // BuildWithStats is build, also reporting the work the build took. A
+    // table that had to grow during the build has more slots than the
+    // options ask for.
+    pub fn build_with_stats<K: Hash>(&self, keys: &[K]) -> (Table<S>, BuildStats) {
+        built(self.build_in(keys, &mut Scratch::new()))
+    }
+
+    // BuildReusing is build with its working memory borrowed from scratch
+    // rather than allocated afresh, for callers that rebuild tables of about
+    // the same size again and again. Once the scratch has grown to fit, and
+    // the last table has been recycled into it, a build allocates little but
+    // the new table's metadata.
+    pub fn build_reusing<K: Hash>(&self, keys: &[K], scratch: &mut Scratch) -> Table<S> {
+        built(self.build_in(keys, scratch)).0
+    }
+
+    // Scratch returns working memory for build_reusing sized for n keys with
+    // these options. A build over that many keys then allocates only the
+    // table's metadata, unless it has to grow the table, so a caller whose
+    // policy keeps allocation out of its hot paths can make the scratch ahead
+    // of time and reuse it, recycling each retired table, indefinitely.
+    pub fn scratch(&self, n: usize) -> Scratch {
+        Scratch::with_capacity(n, self.hasher.second_hash, &self.params)
+    }
+
+    // BuildByKey constructs a table over the keys extracted from records, so
+    // query results are indices into records.
+    pub fn build_by_key<T, K, F>(&self, records: &[T], key: F) -> Table<S>
+    where
+        K: Hash + ?Sized,
+        F: Fn(&T) -> &K,
+    {
+        self.build_iter(records.iter().map(key))
+    }
+
+    // BuildIter constructs a table from a stream of keys. Only the 8-byte hash
+    // of each key is buffered, so the keys themselves never need to be held in
+    // memory at once. Indices follow iteration order.
+    pub fn build_iter<I>(&self, keys: I) -> Table<S>
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        built(self.build_in(keys, &mut Scratch::new())).0
+    }
+
+    // BuildPartitioned splits the keys by hash into nparts partitions, rounded
+    // up to a power of two, and builds a table over each with the configured
+    // options. Only the hashes of all the keys are held at once; the buckets
+    // and scratch space are one partition's. See PartitionedTable for what
+    // query returns.
+    pub fn build_partitioned<K: Hash>(&self, keys: &[K], nparts: usize) -> PartitionedTable<S> {
+        built(self.try_build_partitioned(keys, nparts))
+    }
+
+    // TryBuildPartitioned is build_partitioned, returning an error instead of
+    // panicking if the build is cancelled or runs out of time.
+    pub fn try_build_partitioned<K: Hash>(
+        &self,
+        keys: &[K],
+        nparts: usize,
+    ) -> Result<PartitionedTable<S>, BuildError> {
+        let deadline = self.deadline();
+        let hasher = self.key_hasher();
+        let pairs: Vec<(u64, u64)> = keys.iter().map(|k| hasher.hash_pair(k)).collect();
+        let shards = Shards::new(&pairs, nparts, hasher.second_hash);
+        drop(pairs);
+        let mut scratch = Scratch::new();
+        let parts = (0..shards.len())
+            .map(|p| {
+                let (hashes, disp) = shards.get(p);
+                let hasher = hasher.clone();
+                let run = self.run_until(deadline);
+                let t = self.finish(hashes, disp, hasher, &self.params, &mut scratch, run)?;
+                Ok(t.0)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(PartitionedTable::new(hasher, shards.bits(), parts))
+    }
+
+    // BuildExternal builds a partitioned table over more keys than fit in
+    // memory. The keys are hashed as they stream in, and the hashes written
+    // to temporary files in a new directory under dir; then the partitions
+    // are read back and built one at a time, as few of them as keep each
+    // partition's build within about budget bytes. Only the finished
+    // sub-tables, and some 2MB of write buffers, are held beyond one
+    // partition's build. The directory is removed on return. A cancelled
+    // build, or one out of time, returns an Interrupted or TimedOut error
+    // wrapping the BuildError.
+    pub fn build_external<I>(
+        &self,
+        keys: I,
+        dir: &Path,
+        budget: usize,
+    ) -> io::Result<PartitionedTable<S>>
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        let deadline = self.deadline();
+        let hasher = self.key_hasher();
+        let mut spill = Spill::new(dir, hasher.second_hash)?;
+        for k in keys {
+            let (h, d) = hasher.hash_pair(&k);
+            spill.push(h, d)?;
+        }
+        spill.seal()?;
+
+        let nparts = spill.partitions(budget);
+        let mut scratch = Scratch::new();
+        let mut parts = Vec::with_capacity(nparts);
+        for p in 0..nparts {
+            let mut hashes = mem::take(&mut scratch.hashes);
+            let mut disp = mem::take(&mut scratch.disp);
+            spill.read(p, nparts, &mut hashes, &mut disp)?;
+            let d = hasher.second_hash.then_some(disp.as_slice());
+            let run = self.run_until(deadline);
+            let t = self.finish(&hashes, d, hasher.clone(), &self.params, &mut scratch, run);
+            parts.push(t?.0);
+            scratch.hashes = hashes;
+            scratch.disp = disp;
+        }
+        Ok(PartitionedTable::new(
+            hasher,
+            nparts.trailing_zeros(),
+            parts,
+        ))
+    }
+
+    // Run returns the state to start a build with.
+    fn run(&self) -> Run {
+        self.run_until(self.deadline())
+    }
+
+    // RunUntil returns the state to start a build with that must be done by
+    // deadline, for the partitions of a build that share one.
+    fn run_until(&self, deadline: Option<Instant>) -> Run {
+        Run {
+            cancel: self.cancel.clone(),
+            deadline,
+            progress: self.progress.clone(),
+            ..Run::default()
+        }
+    }
+
+    // Deadline returns when a build starting now runs out of time.
+    fn deadline(&self) -> Option<Instant> {
+        self.time_budget.and_then(|d| Instant::now().checked_add(d))
+    }
+
+    // KeyHasher returns the hasher for a build, with a fresh seed if
+    // random_seed is set.
+    fn key_hasher(&self) -> KeyHasher<S> {
+        let mut hasher = self.hasher.clone();
+        if self.random_seed {
+            hasher.seed = hash::random_seed();
+        }
+        hasher
+    }
+
+    // HashKeys runs only the hashing phase of a build, with the options that
+    // affect hashing: the hasher, seed, second_hash, case folding and
+    // normalizer. Build the table with build_hashed.
+    pub fn hash_keys<I>(&self, keys: I) -> HashedKeys<S>
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        let hasher = self.key_hasher();
+        let mut hashes = Vec::new();
+        let mut disp = Vec::new();
+        hash_into(&hasher, keys, &mut hashes, &mut disp, &self.run());
+        HashedKeys {
+            hasher,
+            hashes,
+            disp,
+        }
+    }
+
+    // BuildHashed runs the placement phase of a build over keys hashed by
+    // hash_keys, with this builder's placement options: lambda, load_factor,
+    // fastrange, shuffle_seeds, minimal and k. Its hashing options are
+    // ignored, since the table must hash queries as the keys were hashed; a
+    // random seed is the one hash_keys drew. Trying several placements over
+    // the same keys costs no more hashing.
+    pub fn build_hashed(&self, keys: &HashedKeys<S>) -> Table<S> {
+        built(self.try_build_hashed(keys))
+    }
+
+    // TryBuildHashed is build_hashed, returning an error instead of panicking
+    // if the build is cancelled or runs out of time.
+    pub fn try_build_hashed(&self, keys: &HashedKeys<S>) -> Result<Table<S>, BuildError> {
+        let hasher = keys.hasher.clone();
+        let scratch = &mut Scratch::new();
+        let run = self.run();
+        let (t, _) = self.finish(
+            &keys.hashes,
+            keys.disp(),
+            hasher,
+            &self.params,
+            scratch,
+            run,
+        )?;
+        Ok(t)
+    }
+
+    // BuildIn hashes the keys into scratch and builds the table there.
+    fn build_in<I>(
+        &self,
+        keys: I,
+        scratch: &mut Scratch,
+    ) -> Result<(Table<S>, BuildStats), BuildError>
+    where
+        I: IntoIterator,
+        I::Item: Hash,
+    {
+        let hasher = self.key_hasher();
+        // taken out of scratch for the build, which borrows the rest of it
+        let mut hashes = mem::take(&mut scratch.hashes);
+        let mut disp = mem::take(&mut scratch.disp);
+        let run = self.run();
+        hash_into(&hasher, keys, &mut hashes, &mut disp, &run);
+        let d = if hasher.second_hash {
+            Some(disp.as_slice())
+        } else {
+            None
+        };
+        let r = self.finish(&hashes, d, hasher, &self.params, scratch, run);
+        scratch.hashes = hashes;
+        scratch.disp = disp;
+        r
+    }
+
+    // Finish builds the table over the hashed keys and records the metadata.
+    fn finish(
+        &self,
+        hashes: &[u64],
+        disp: Option<&[u64]>,
+        hasher: KeyHasher<S>,
+        params: &Params,
+        scratch: &mut Scratch,
+        run: Run,
+    ) -> Result<(Table<S>, BuildStats), BuildError> {
+        let (mut t, stats) = Table::build_with_stats(hashes, disp, hasher, params, scratch, run)?;
+        if self.record_time {
+            t.info.built_at = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+        }
+        t.info.comment.clone_from(&self.comment);
+        Ok((t, stats))
+    }
+}
+
+// built unwraps the result of a build for the methods that can't return an
+// error, panicking if it was cancelled or ran out of time.
+fn built<T>(r: Result<T, BuildError>) -> T {
+    r.unwrap_or_else(|err| panic!("{}; use try_build to handle it", err))
+}
+
+// hash_into fills hashes, and with second_hash disp, with the hashes of keys,
+// replacing what they held.
+fn hash_into<S, I>(
+    hasher: &KeyHasher<S>,
+    keys: I,
+    hashes: &mut Vec<u64>,
+    disp: &mut Vec<u64>,
+    run: &Run,
+) where
+    S: BuildHasher,
+    I: IntoIterator,
+    I::Item: Hash,
+{
+    hashes.clear();
+    disp.clear();
+    for k in keys {
+        if hashes.len().is_multiple_of(REPORT_EVERY) {
+            run.report(Progress::new(Phase::Hashing, hashes.len()));
+        }
+        let (h, d) = hasher.hash_pair(&k);
+        hashes.push(h);
+        if hasher.second_hash {
+            disp.push(d);
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<S: BuildHasher + Clone + Sync> TableBuilder<S> {
+    // BuildPar is build spread over rayon's thread pool: the keys are hashed
+    // in parallel, and the seeds of the multi-key buckets are searched for a
+    // batch at a time across the threads. The table is the same one build
+    // makes, byte for byte, on any number of threads, so parallel builds are
+    // as reproducible as sequential ones.
+    pub fn build_par<K: Hash + Sync>(&self, keys: &[K]) -> Table<S> {
+        built(self.try_build_par(keys))
+    }
+
+    // TryBuildPar is build_par, returning an error instead of panicking if
+    // the build is cancelled or runs out of time.
+    pub fn try_build_par<K: Hash + Sync>(&self, keys: &[K]) -> Result<Table<S>, BuildError> {
+        use rayon::prelude::*;
+
+        let run = self.run();
+        let hasher = self.key_hasher();
+        let pairs: Vec<(u64, u64)> = keys.par_iter().map(|k| hasher.hash_pair(k)).collect();
+        let hashes: Vec<u64> = pairs.iter().map(|&(h, _)| h).collect();
+        let disp: Option<Vec<u64>> = hasher
+            .second_hash
+            .then(|| pairs.iter().map(|&(_, d)| d).collect());
+        let params = Params {
+            batch: rayon::current_num_threads() * 16,
+            ..self.params
+        };
+        let scratch = &mut Scratch::new();
+        let (t, _) = self.finish(&hashes, disp.as_deref(), hasher, &params, scratch, run)?;
+        Ok(t)
+    }
+
+    // BuildPartitionedPar is build_partitioned with the keys hashed, and the
+    // partitions built, in parallel on rayon's thread pool. The partitions
+    // are the ones build_partitioned makes, on any number of threads.
+    pub fn build_partitioned_par<K: Hash + Sync>(
+        &self,
+        keys: &[K],
+        nparts: usize,
+    ) -> PartitionedTable<S>
+    where
+        S: Send,
+    {
+        built(self.try_build_partitioned_par(keys, nparts))
+    }
+
+    // TryBuildPartitionedPar is build_partitioned_par, returning an error
+    // instead of panicking if the build is cancelled or runs out of time.
+    pub fn try_build_partitioned_par<K: Hash + Sync>(
+        &self,
+        keys: &[K],
+        nparts: usize,
+    ) -> Result<PartitionedTable<S>, BuildError>
+    where
+        S: Send,
+    {
+        use rayon::prelude::*;
+
+        let deadline = self.deadline();
+        let hasher = self.key_hasher();
+        let pairs: Vec<(u64, u64)> = keys.par_iter().map(|k| hasher.hash_pair(k)).collect();
+        let shards = Shards::new(&pairs, nparts, hasher.second_hash);
+        drop(pairs);
+        let parts = (0..shards.len())
+            .into_par_iter()
+            .map(|p| {
+                let (hashes, disp) = shards.get(p);
+                let hasher = hasher.clone();
+                let scratch = &mut Scratch::new();
+                let run = self.run_until(deadline);
+                let t = self.finish(hashes, disp, hasher, &self.params, scratch, run)?;
+                Ok(t.0)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(PartitionedTable::new(hasher, shards.bits(), parts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn case_insensitive() {
+        let keys = vec!["Content-Type", "accept", "X-FORWARDED-FOR", "Host"];
+
+        let t = TableBuilder::new().case_insensitive(true).build(&keys);
+
+        assert_eq!(t.query("content-type"), 0);
+        assert_eq!(t.query("CONTENT-TYPE"), 0);
+        assert_eq!(t.query("Accept"), 1);
+        assert_eq!(t.query("x-forwarded-for"), 2);
+        assert_eq!(t.query(&"hOST".to_string()), 3);
+        assert_eq!(t.query_bytes(b"HOST"), t.query_bytes(b"host"));
+    }
+
+    #[test]
+    fn hash_algo() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("k{}", i)).collect();
+
+        for &algo in &[HashAlgo::Sip13, HashAlgo::Fx] {
+            let t = TableBuilder::new().hash_algo(algo).build(&keys);
+            assert_eq!(*t.hasher(), algo);
+            for (i, k) in keys.iter().enumerate() {
+                assert_eq!(t.query(k), i);
+            }
+        }
+    }
+
+    #[test]
+    fn seed() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("k{}", i)).collect();
+
+        let a = TableBuilder::new().seed(1).build(&keys);
+        let b = TableBuilder::new().seed(2).build(&keys);
+        assert_eq!(a.seed(), 1);
+        assert_ne!(a.seeds, b.seeds);
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(a.query(k), i);
+            assert_eq!(b.query(k), i);
+        }
+
+        let c = TableBuilder::new().seed(1).build(&keys);
+        assert_eq!(a.seeds, c.seeds);
+        assert_eq!(a.values, c.values);
+    }
+
+    #[test]
+    fn random_seed() {
+        let keys: Vec<String> = (0..100).map(|i| format!("k{}", i)).collect();
+
+        let b = TableBuilder::new().random_seed(true);
+        let t = b.build(&keys);
+        let u = b.build(&keys);
+        assert_ne!(t.seed(), 0);
+        assert_ne!(t.seed(), u.seed());
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+            assert_eq!(u.query(k), i);
+        }
+    }
+
+    #[test]
+    fn second_hash() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("k{}", i)).collect();
+
+        let t = TableBuilder::new().second_hash(true).build(&keys);
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+        }
+
+        let t = TableBuilder::new().second_hash(true).build(&[b"ab", b"cd"]);
+        assert_eq!(t.query(b"cd"), 1);
+    }
+
+    #[test]
+    fn lambda() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("k{}", i)).collect();
+
+        for &lambda in &[0.5, 1.0, 2.0, 4.0] {
+            let t = TableBuilder::new().lambda(lambda).build(&keys);
+            assert_eq!(
+                t.seeds.len(),
+                ((1000.0 / lambda) as usize).next_power_of_two()
+            );
+            for (i, k) in keys.iter().enumerate() {
+                assert_eq!(t.query(k), i);
+            }
+        }
+
+        // every bucket holds several keys
+        let t = TableBuilder::new()
+            .lambda(10.0)
+            .build(&["a", "b", "c", "d", "e"]);
+        assert_eq!(t.seeds.len(), 1);
+        assert_eq!(t.query("e"), 4);
+    }
+
+    #[test]
+    fn fastrange() {
+        let keys: Vec<String> = (0..1100).map(|i| format!("k{}", i)).collect();
+
+        let t = TableBuilder::new().fastrange(true).build(&keys);
+        assert_eq!(t.values.len(), 1100);
+        assert_eq!(t.seeds.len(), 1100);
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+        }
+
+        let t = TableBuilder::new().fastrange(true).lambda(3.0).build(&keys);
+        assert_eq!(t.seeds.len(), 367);
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+        }
+    }
+
+    #[test]
+    fn load_factor() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("k{}", i)).collect();
+
+        let t = TableBuilder::new()
+            .fastrange(true)
+            .load_factor(0.95)
+            .build(&keys);
+        assert_eq!(t.values.len(), 1053);
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+        }
+
+        let t = TableBuilder::new().load_factor(0.5).build(&keys);
+        assert_eq!(t.values.len(), 2048);
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+        }
+    }
+
+    #[test]
+    fn minimal() {
+        for &(n, fastrange) in &[(1, false), (1000, false), (1100, true), (4096, false)] {
+            let keys: Vec<String> = (0..n).map(|i| format!("k{}", i)).collect();
+
+            let t = TableBuilder::new()
+                .minimal(true)
+                .fastrange(fastrange)
+                .lambda(2.0)
+                .build(&keys);
+            assert!(t.values.is_empty());
+
+            let mut seen = vec![false; n];
+            for k in &keys {
+                let v = t.query(k);
+                assert!(!seen[v], "duplicate value {}", v);
+                seen[v] = true;
+            }
+        }
+    }
+
+    #[test]
+    fn k_perfect() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("k{}", i)).collect();
+
+        for &(k, fastrange) in &[(2, false), (4, true), (8, false)] {
+            let t = TableBuilder::new().k(k).fastrange(fastrange).build(&keys);
+
+            let slots = (1000usize).div_ceil(k);
+            let slots = if fastrange {
+                slots
+            } else {
+                slots.next_power_of_two()
+            };
+            let mut load = vec![0; slots];
+            for key in &keys {
+                load[t.query(key)] += 1;
+            }
+            assert!(load.iter().all(|&l| l <= k));
+        }
+    }
+
+    #[test]
+    fn build_with_stats() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("k{}", i)).collect();
+
+        let b = TableBuilder::new().lambda(4.0);
+        let (t, stats) = b.build_with_stats(&keys);
+        assert_eq!(stats.retries, 0);
+        assert_eq!(t.seeds, b.build(&keys).seeds);
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+        }
+    }
+
+    #[test]
+    fn build_reusing() {
+        let b = TableBuilder::new().lambda(2.0);
+        let mut scratch = Scratch::new();
+
+        let mut last: Option<Table> = None;
+        for round in 0..4 {
+            let keys: Vec<String> = (0..1000).map(|i| format!("k{}.{}", round, i)).collect();
+            let t = b.build_reusing(&keys, &mut scratch);
+            let want = b.build(&keys);
+            assert_eq!(t.seeds, want.seeds);
+            assert_eq!(t.values, want.values);
+
+            // the new table lives in the recycled one's arrays
+            if let Some(u) = last.take() {
+                let (values, seeds) = (u.values.as_ptr(), u.seeds.as_ptr());
+                scratch.recycle(u);
+                let t = b.build_reusing(&keys, &mut scratch);
+                assert_eq!(t.values.as_ptr(), values);
+                assert_eq!(t.seeds.as_ptr(), seeds);
+                assert_eq!(t.values, want.values);
+            }
+            last = Some(t);
+        }
+    }
+
+    #[test]
+    fn scratch() {
+        let keys: Vec<String> = (0..5000).map(|i| format!("k{}", i)).collect();
+
+        for b in [
+            TableBuilder::new(),
+            TableBuilder::new().minimal(true).second_hash(true),
+            TableBuilder::new().k(4).lambda(3.0),
+        ] {
+            let mut scratch = b.scratch(keys.len());
+            let arrays = |s: &Scratch| {
+                [
+                    s.hashes.as_ptr() as usize,
+                    s.buckets.entries.as_ptr() as usize,
+                    s.buckets.offsets.as_ptr() as usize,
+                    s.order.as_ptr() as usize,
+                    s.work.queue.as_ptr() as usize,
+                    s.work.claimed.as_ptr() as usize,
+                    s.work.free.as_ptr() as usize,
+                    s.work.load.as_ptr() as usize,
+                ]
+            };
+            let before = arrays(&scratch);
+            let seeds = scratch.work.seeds.as_ptr();
+
+            // nothing in the scratch grew, and the table took its seeds
+            let t = b.build_reusing(&keys, &mut scratch);
+            assert_eq!(arrays(&scratch), before);
+            assert_eq!(t.seeds.as_ptr(), seeds);
+            assert_eq!(t.seeds, b.build(&keys).seeds);
+        }
+    }
+
+    #[test]
+    fn tune_from_sample() {
+        let sample: Vec<String> = (0..10000).map(|i| format!("k{}", i)).collect();
+
+        for b in [TableBuilder::new(), TableBuilder::new().minimal(true)] {
+            let tuned = b.clone().tune_from_sample(&sample);
+            assert!(tuned.params.lambda > 1.0);
+            let keys: Vec<String> = (0..50000).map(|i| format!("key{}", i)).collect();
+            let (t, stats) = tuned.build_with_stats(&keys);
+            assert!(t.bits_per_key() < b.fastrange(true).build(&keys).bits_per_key());
+            assert!(stats.seeds_tried < 2 * TUNE_COST as usize * keys.len());
+        }
+
+        let b = TableBuilder::new()
+            .lambda(2.0)
+            .tune_from_sample::<&str>(&[]);
+        assert_eq!(b.params.lambda, 2.0);
+    }
+
+    #[test]
+    fn build_hashed() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("k{}", i)).collect();
+
+        let hashed = TableBuilder::new()
+            .seed(5)
+            .second_hash(true)
+            .hash_keys(&keys);
+        assert_eq!(hashed.len(), keys.len());
+        for b in [
+            TableBuilder::new(),
+            TableBuilder::new()
+                .lambda(4.0)
+                .load_factor(0.9)
+                .fastrange(true),
+            TableBuilder::new().minimal(true),
+            TableBuilder::new().k(4),
+        ] {
+            let t = b.build_hashed(&hashed);
+            let want = b.seed(5).second_hash(true).build(&keys);
+            assert_eq!(t.seed(), 5);
+            assert_eq!(t.seeds, want.seeds);
+            assert_eq!(t.values, want.values);
+        }
+
+        // the placement reuses the seed drawn for the hashes
+        let hashed = TableBuilder::new().random_seed(true).hash_keys(&keys);
+        let t = TableBuilder::new().random_seed(true).build_hashed(&hashed);
+        assert_eq!(t.seed(), hashed.hasher.seed);
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+        }
+    }
+
+    #[test]
+    fn cancellation() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("k{}", i)).collect();
+
+        let token = CancellationToken::new();
+        let b = TableBuilder::new().cancellation(token.clone());
+        let t = b.try_build(&keys).unwrap();
+        assert_eq!(t.query("k7"), 7);
+
+        token.cancel();
+        assert!(token.is_cancelled());
+        assert_eq!(b.try_build(&keys).err(), Some(BuildError::Cancelled));
+        let b = b.k(4);
+        assert_eq!(b.try_build(&keys).err(), Some(BuildError::Cancelled));
+
+        let err = b.try_build_partitioned(&keys, 4).err();
+        assert_eq!(err, Some(BuildError::Cancelled));
+        let hashed = b.hash_keys(&keys);
+        assert_eq!(
+            b.try_build_hashed(&hashed).err(),
+            Some(BuildError::Cancelled)
+        );
+        #[cfg(feature = "rayon")]
+        {
+            assert_eq!(b.try_build_par(&keys).err(), Some(BuildError::Cancelled));
+            let err = b.try_build_partitioned_par(&keys, 4).err();
+            assert_eq!(err, Some(BuildError::Cancelled));
+        }
+
+        let dir = std::env::temp_dir();
+        let err = b.build_external(&keys, &dir, 1 << 20).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        assert_eq!(err.to_string(), "build cancelled");
+    }
+
+    #[test]
+    #[should_panic(expected = "build cancelled")]
+    fn cancelled_build() {
+        let token = CancellationToken::new();
+        token.cancel();
+        TableBuilder::new().cancellation(token).build(&["a", "b"]);
+    }
+
+    #[test]
+    fn time_budget() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("k{}", i)).collect();
+
+        let b = TableBuilder::new().time_budget(Duration::from_secs(3600));
+        assert_eq!(b.try_build(&keys).unwrap().query("k7"), 7);
+
+        let b = TableBuilder::new().time_budget(Duration::ZERO);
+        let err = b.try_build(&keys).err();
+        assert_eq!(err, Some(BuildError::TimedOut(BuildStats::default())));
+        assert_eq!(
+            err.unwrap().to_string(),
+            "build ran out of time after 0 retries and 0 evictions"
+        );
+
+        let dir = std::env::temp_dir();
+        let err = b.build_external(&keys, &dir, 1 << 20).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn on_progress() {
+        let keys: Vec<String> = (0..20000).map(|i| format!("k{}", i)).collect();
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        let t = TableBuilder::new()
+            .on_progress(move |p| log.lock().unwrap().push(p))
+            .build(&keys);
+        assert_eq!(t.query("k7"), 7);
+
+        let seen = seen.lock().unwrap();
+        let phases: Vec<Phase> = seen.iter().map(|p| p.phase).collect();
+        assert!(phases.windows(2).all(|w| w[0] as u8 <= w[1] as u8));
+        for phase in [
+            Phase::Hashing,
+            Phase::Sorting,
+            Phase::Placement,
+            Phase::PatchUp,
+        ] {
+            assert!(phases.contains(&phase));
+        }
+        let hashed = seen.iter().filter(|p| p.phase == Phase::Hashing).count();
+        assert_eq!(hashed, 20000usize.div_ceil(REPORT_EVERY));
+
+        let last = seen.last().unwrap();
+        assert_eq!(last.keys, 20000);
+        assert_eq!(last.keys_placed, 20000);
+        assert_eq!(last.buckets_placed, last.buckets);
+        assert!(seen
+            .iter()
+            .any(|p| p.phase == Phase::Placement && p.buckets_placed > 0));
+    }
+
+    #[test]
+    fn normalize() {
+        let keys = vec!["/api/users", "/api/items", "/api/orders"];
+
+        let t = TableBuilder::new()
+            .normalize(|s| s.trim().trim_start_matches("/api").into())
+            .build(&keys);
+
+        assert_eq!(t.query("/users"), 0);
+        assert_eq!(t.query("  /api/items "), 1);
+        assert_eq!(t.query("/orders"), 2);
+
+        let t = TableBuilder::new()
+            .normalize(|s| s.trim().into())
+            .case_insensitive(true)
+            .build(&keys);
+
+        assert_eq!(t.query(" /API/Users"), 0);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn build_par() {
+        let keys: Vec<String> = (0..20000).map(|i| format!("k{}", i)).collect();
+
+        for b in [
+            TableBuilder::new(),
+            TableBuilder::new().lambda(4.0).second_hash(true),
+            TableBuilder::new().minimal(true).seed(9),
+            TableBuilder::new().k(4),
+        ] {
+            let want = b.build(&keys);
+            let t = b.build_par(&keys);
+            assert_eq!(t.seeds, want.seeds);
+            assert_eq!(t.values, want.values);
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn build_par_threads() {
+        let keys: Vec<String> = (0..20000).map(|i| format!("k{}", i)).collect();
+
+        for b in [
+            TableBuilder::new().lambda(4.0),
+            TableBuilder::new()
+                .lambda(5.0)
+                .minimal(true)
+                .fastrange(true),
+        ] {
+            let want = b.build(&keys).to_bytes();
+            for threads in [1, 2, 3, 8] {
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .unwrap();
+                let t = pool.install(|| b.build_par(&keys));
+                assert_eq!(t.to_bytes(), want, "{} threads", threads);
+            }
+        }
+    }
+}
@@ -0,0 +1,464 @@
+use std::hash::{BuildHasher, Hash};
+
+use crate::hash::{DefaultState, KeyHasher};
+use crate::rank::RankBits;
+use crate::{reduce, xorshift_mult64, Table};
+
+// CompactTable is a Table with its seeds and values stored in the narrowest
+// of 8, 16 or 32 bits that holds each array, made by Table::compact. Values
+// are below the number of keys, and seeds, which also hold the slots of
+// buckets of one key, below a few times the number of slots, so tables of up
+// to about 32,000 keys take half the space or less and stay in faster caches.
+// Larger tables keep 32 bits where they need them. Queries answer as the
+// table's did, at the cost of a branch on the width per array read.
+//
+// Where it's smaller, the seeds are instead stored four or eight bits each,
+// but for the few too large for that, whatever the size of the table. Most
+// seeds are found a few tries in, so four bits hold all but a percent or
+// two. Buckets of one key, placed directly with negative seeds, get a code
+// of their own and their slots are kept in a bitmap rather than as 32-bit
+// offsets, see Direct, unless so few are left, as with
+// TableBuilder::compress_seeds, that the offsets take less.
+pub struct CompactTable<S = DefaultState> {
+    hasher: KeyHasher<S>,
+    seeds: Seeds,
+    values: Narrow,
+    occupied: Option<RankBits>,
+    len: usize,
+    size: usize,
+}
+
+// Narrow is an array of i32s stored as unsigned offsets from the smallest.
+struct Narrow {
+    ints: Ints,
+    min: i32,
+}
+
+enum Ints {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+// Seeds holds the seeds as a Narrow array or, if it's smaller, as Codes.
+enum Seeds {
+    Narrow(Narrow),
+    Codes(Codes),
+}
+
+// Codes holds the seeds below its direct code as codes of width bits, 4 or
+// 8, two to a byte or one, and the rest as exceptions, sorted by bucket, in
+// place of which the codes are escape. Negative seeds may instead be held by
+// direct, their codes the direct code. The two are the largest codes of the
+// width: 14 and 15, or 254 and 255.
+struct Codes {
+    codes: Vec<u8>,
+    width: u32,
+    len: usize,
+    exceptions: Vec<(u32, i32)>,
+    direct: Option<Direct>,
+}
+
+fn escape(width: u32) -> u8 {
+    (0xff >> (8 - width)) as u8
+}
+
+// Direct recovers the negative seeds of the buckets coded direct from two
+// bitmaps, one of the buckets and one of the slots (or, in minimal tables,
+// the values) that !seed gives them, in about two bits per key where 32-bit
+// offsets take 64. Place hands buckets of one key the free slots from the
+// top down in bucket order, so the j'th bucket from the first has the j'th
+// slot from the last: a rank in the one and a select in the other.
+struct Direct {
+    buckets: RankBits,
+    slots: RankBits,
+}
+
+impl Direct {
+    // New returns the Direct for the negative seeds in seeds, if their slots
+    // fall in bucket order as place leaves them, which they do unless slots
+    // hold more than one key.
+    fn new(seeds: &[i32]) -> Option<Direct> {
+        let mut buckets = vec![0u64; seeds.len().div_ceil(64)];
+        let mut last = usize::MAX;
+        let mut top = 0;
+        for (b, &s) in seeds.iter().enumerate().filter(|(_, &s)| s < 0) {
+            let slot = !s as usize;
+            if slot >= last {
+                return None;
+            }
+            last = slot;
+            top = top.max(slot + 1);
+            buckets[b / 64] |= 1 << (b % 64);
+        }
+        let mut slots = vec![0u64; top.div_ceil(64)];
+        for &s in seeds.iter().filter(|&&s| s < 0) {
+            slots[!s as usize / 64] |= 1 << (!s as usize % 64);
+        }
+        Some(Direct {
+            buckets: RankBits::new(buckets),
+            slots: RankBits::new(slots),
+        })
+    }
+
+    #[inline(always)]
+    fn seed(&self, b: usize) -> i32 {
+        let j = self.buckets.ones() - 1 - self.buckets.rank(b);
+        !(self.slots.select(j) as i32)
+    }
+
+    fn size_bits(&self) -> usize {
+        self.buckets.size_bits() + self.slots.size_bits()
+    }
+}
+
+impl Narrow {
+    fn new(v: &[i32]) -> Narrow {
+        let min = v.iter().copied().min().unwrap_or(0);
+        let max = v.iter().copied().max().unwrap_or(0);
+        let span = max.wrapping_sub(min) as u32;
+        let offsets = v.iter().map(|&x| x.wrapping_sub(min) as u32);
+        let ints = if span <= u8::MAX as u32 {
+            Ints::U8(offsets.map(|x| x as u8).collect())
+        } else if span <= u16::MAX as u32 {
+            Ints::U16(offsets.map(|x| x as u16).collect())
+        } else {
+            Ints::U32(offsets.collect())
+        };
+        Narrow { ints, min }
+    }
+
+    #[inline(always)]
+    fn get(&self, i: usize) -> i32 {
+        let x = match &self.ints {
+            Ints::U8(v) => v[i] as u32,
+            Ints::U16(v) => v[i] as u32,
+            Ints::U32(v) => v[i],
+        };
+        self.min.wrapping_add(x as i32)
+    }
+
+    fn len(&self) -> usize {
+        match &self.ints {
+            Ints::U8(v) => v.len(),
+            Ints::U16(v) => v.len(),
+            Ints::U32(v) => v.len(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Width returns the bits each integer takes.
+    fn width(&self) -> usize {
+        match self.ints {
+            Ints::U8(_) => 8,
+            Ints::U16(_) => 16,
+            Ints::U32(_) => 32,
+        }
+    }
+}
+
+impl Codes {
+    fn new(seeds: &[i32], width: u32, direct: Option<Direct>) -> Codes {
+        let escape = escape(width);
+        let mut c = Codes {
+            codes: vec![0; (seeds.len() * width as usize).div_ceil(8)],
+            width,
+            len: seeds.len(),
+            exceptions: Vec::new(),
+            direct,
+        };
+        for (b, &s) in seeds.iter().enumerate() {
+            let code = if (0..escape as i32 - 1).contains(&s) {
+                s as u8
+            } else if s < 0 && c.direct.is_some() {
+                escape - 1
+            } else {
+                c.exceptions.push((b as u32, s));
+                escape
+            };
+            let shift = b * width as usize % 8;
+            c.codes[b * width as usize / 8] |= code << shift;
+        }
+        c
+    }
+
+    // SizeBitsFor returns the space Codes::new would take for seeds with a
+    // given width and direct.
+    fn size_bits_for(seeds: &[i32], width: u32, direct: Option<&Direct>) -> usize {
+        let small = escape(width) as i32 - 1;
+        let exceptions = seeds
+            .iter()
+            .filter(|&&s| !((0..small).contains(&s) || s < 0 && direct.is_some()))
+            .count();
+        seeds.len() * width as usize + exceptions * 64 + direct.map_or(0, Direct::size_bits)
+    }
+
+    #[inline(always)]
+    fn get(&self, i: usize) -> i32 {
+        let top = 8 - self.width;
+        let bit = i * self.width as usize;
+        let code = self.codes[bit / 8] << (top as usize - bit % 8) >> top;
+        let escape = escape(self.width);
+        if code == escape {
+            let at = self.exceptions.binary_search_by_key(&(i as u32), |e| e.0);
+            self.exceptions[at.unwrap()].1
+        } else if code == escape - 1 {
+            self.direct.as_ref().unwrap().seed(i)
+        } else {
+            code as i32
+        }
+    }
+
+    fn size_bits(&self) -> usize {
+        self.len * self.width as usize
+            + self.exceptions.len() * 64
+            + self.direct.as_ref().map_or(0, Direct::size_bits)
+    }
+}
+
+impl Seeds {
+    // New stores seeds in the smallest of a Narrow array and Codes of 4 or 8
+    // bits, with or without Direct.
+    fn new(seeds: &[i32]) -> Seeds {
+        let narrow = Narrow::new(seeds);
+        let negative = seeds.iter().filter(|&&s| s < 0).count();
+        let direct = Direct::new(seeds).filter(|d| d.size_bits() < negative * 64);
+        let (width, bits) = [4, 8]
+            .iter()
+            .map(|&w| (w, Codes::size_bits_for(seeds, w, direct.as_ref())))
+            .min_by_key(|&(_, bits)| bits)
+            .unwrap();
+        if bits >= seeds.len() * narrow.width() {
+            return Seeds::Narrow(narrow);
+        }
+        Seeds::Codes(Codes::new(seeds, width, direct))
+    }
+
+    #[inline(always)]
+    fn get(&self, i: usize) -> i32 {
+        match self {
+            Seeds::Narrow(n) => n.get(i),
+            Seeds::Codes(c) => c.get(i),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Seeds::Narrow(n) => n.len(),
+            Seeds::Codes(c) => c.len,
+        }
+    }
+
+    fn size_bits(&self) -> usize {
+        match self {
+            Seeds::Narrow(n) => n.len() * n.width(),
+            Seeds::Codes(c) => c.size_bits(),
+        }
+    }
+}
+
+impl<S> Table<S> {
+    // Compact converts the table to the CompactTable layout.
+    pub fn compact(self) -> CompactTable<S> {
+        CompactTable {
+            seeds: Seeds::new(&self.seeds),
+            values: Narrow::new(&self.values),
+            hasher: self.hasher,
+            occupied: self.occupied,
+            len: self.len,
+            size: self.size,
+        }
+    }
+}
+
+impl<S> CompactTable<S> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // BitsPerKey reports the space used by the seeds and values (or the rank
+    // structure that replaces them).
+    pub fn bits_per_key(&self) -> f64 {
+        let bits = self.seeds.size_bits()
+            + self.values.len() * self.values.width()
+            + self.occupied.as_ref().map_or(0, RankBits::size_bits);
+        bits as f64 / self.len.max(1) as f64
+    }
+}
+
+impl<S: BuildHasher> CompactTable<S> {
+    // Query returns the value Table::query would for k.
+    pub fn query<K: Hash + ?Sized>(&self, k: &K) -> usize {
+        let (hash, disp) = self.hasher.hash_pair(k);
+        self.lookup(hash, disp)
+    }
+
+    // QueryBytes looks up a binary key in a table built with from_bytes_keys.
+    pub fn query_bytes<K: AsRef<[u8]> + ?Sized>(&self, k: &K) -> usize {
+        let (hash, disp) = self.hasher.hash_bytes_pair(k.as_ref());
+        self.lookup(hash, disp)
+    }
+
+    // QueryPrehashed looks up a digest in a table built with from_hashes.
+    pub fn query_prehashed(&self, hash: u64) -> usize {
+        self.lookup(hash, hash)
+    }
+
+    fn lookup(&self, hash: u64, disp: u64) -> usize {
+        let seed = self.seeds.get(reduce(hash, self.seeds.len()));
+        if seed < 0 {
+            // without values, singletons store their final value
+            let i = (-seed - 1) as usize;
+            return if self.values.is_empty() {
+                i
+            } else {
+                self.values.get(i) as usize
+            };
+        }
+
+        let i = reduce(xorshift_mult64(disp.wrapping_add(seed as u64)), self.size);
+        match &self.occupied {
+            Some(bits) => bits.rank(i),
+            None if self.values.is_empty() => i,
+            None => self.values.get(i) as usize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Codes, Narrow, Seeds};
+    use crate::{Table, TableBuilder};
+
+    #[test]
+    fn narrow() {
+        for (v, width) in [
+            (vec![], 8),
+            (vec![-5, 250], 8),
+            (vec![-5, 251], 16),
+            (vec![0, 65535], 16),
+            (vec![i32::MIN, i32::MAX], 32),
+        ] {
+            let n = Narrow::new(&v);
+            assert_eq!(n.width(), width);
+            for (i, &x) in v.iter().enumerate() {
+                assert_eq!(n.get(i), x);
+            }
+        }
+    }
+
+    #[test]
+    fn codes() {
+        let seeds = [0, 1, 13, 14, 15, 253, 254, 70000, -3];
+        for width in [4, 8] {
+            let c = Codes::new(&seeds, width, None);
+            assert_eq!(c.exceptions.len(), if width == 4 { 6 } else { 3 });
+            for (i, &s) in seeds.iter().enumerate() {
+                assert_eq!(c.get(i), s);
+            }
+            assert_eq!(c.size_bits(), Codes::size_bits_for(&seeds, width, None));
+        }
+
+        // most seeds of most tables fit in four bits
+        let keys: Vec<String> = (0..100000).map(|i| format!("k{}", i)).collect();
+        for b in [
+            TableBuilder::new(),
+            TableBuilder::new().compress_seeds(true),
+        ] {
+            let u = b.build(&keys).compact();
+            assert!(matches!(&u.seeds, Seeds::Codes(c) if c.width == 4));
+            assert!(u.seeds.size_bits() < u.seeds.len() * 7);
+        }
+    }
+
+    #[test]
+    fn compress_seeds() {
+        let keys: Vec<String> = (0..100000).map(|i| format!("k{}", i)).collect();
+        for b in [
+            TableBuilder::new(),
+            TableBuilder::new().minimal(true).fastrange(true),
+        ] {
+            let plain = b.clone().build(&keys);
+            let t = b.compress_seeds(true).build(&keys);
+            assert!(t.seeds.iter().filter(|&&s| s < 0).count() < keys.len() / 10);
+
+            let plain = plain.compact();
+            let bits = plain.bits_per_key();
+            let want: Vec<usize> = keys.iter().map(|k| t.query(k)).collect();
+            let u = t.compact();
+            assert!(matches!(u.seeds, Seeds::Codes(_)));
+            assert!(u.bits_per_key() <= bits);
+            assert!(u.seeds.size_bits() < u.seeds.len() * 7);
+            for (k, &v) in keys.iter().zip(&want) {
+                assert_eq!(u.query(k), v);
+            }
+        }
+    }
+
+    #[test]
+    fn direct() {
+        let keys: Vec<String> = (0..100000).map(|i| format!("k{}", i)).collect();
+        for (b, direct) in [
+            (TableBuilder::new(), true),
+            (TableBuilder::new().minimal(true).fastrange(true), true),
+            // too few negative seeds left to be worth the bitmaps
+            (TableBuilder::new().compress_seeds(true), false),
+            // slots shared by several buckets
+            (TableBuilder::new().k(4), false),
+        ] {
+            let t = b.build(&keys);
+            let want: Vec<usize> = keys.iter().map(|k| t.query(k)).collect();
+            let u = t.compact();
+            match &u.seeds {
+                Seeds::Codes(c) => assert_eq!(c.direct.is_some(), direct),
+                Seeds::Narrow(_) => assert!(!direct),
+            }
+            if direct {
+                assert!(u.seeds.size_bits() < u.seeds.len() * 11);
+            }
+            for (k, &v) in keys.iter().zip(&want) {
+                assert_eq!(u.query(k), v);
+            }
+        }
+    }
+
+    #[test]
+    fn compact() {
+        for n in [100, 5000, 100000] {
+            let keys: Vec<String> = (0..n).map(|i| format!("k{}", i)).collect();
+            for b in [
+                TableBuilder::new(),
+                TableBuilder::new().minimal(true),
+                TableBuilder::new().second_hash(true).lambda(4.0),
+                TableBuilder::new().k(4),
+            ] {
+                let t = b.build(&keys);
+                let want: Vec<usize> = keys.iter().map(|k| t.query(k)).collect();
+                let bits = t.bits_per_key();
+                // the rank structure of minimal tables stays as it was
+                let halved = n <= 5000 && t.occupied.is_none();
+                let u = t.compact();
+                assert_eq!(u.len(), keys.len());
+                assert!(u.bits_per_key() <= if halved { bits / 2.0 } else { bits });
+                for (k, &v) in keys.iter().zip(&want) {
+                    assert_eq!(u.query(k), v);
+                }
+            }
+        }
+
+        let hashes: Vec<u64> = (0..1000u64).map(crate::fmix64).collect();
+        let u = Table::from_hashes(&hashes).compact();
+        assert!(matches!(&u.seeds, Seeds::Codes(c) if c.direct.is_some()));
+        assert_eq!(u.values.width(), 16);
+        for (i, &h) in hashes.iter().enumerate() {
+            assert_eq!(u.query_prehashed(h), i);
+        }
+    }
+}
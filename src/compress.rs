@@ -0,0 +1,83 @@
+// zstd compression of serialized tables, with the compression feature. The
+// codec is ruzstd, in pure Rust, so the feature brings no C library along.
+
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use ruzstd::decoding::{FrameDecoder, StreamingDecoder};
+use ruzstd::encoding::{compress_to_vec, CompressionLevel};
+
+use crate::format::write_atomic;
+use crate::hash::StoredHasher;
+use crate::Table;
+
+// ZSTD_MAGIC starts every zstd frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+impl<S: StoredHasher> Table<S> {
+    // WriteCompressed writes the bytes write_to would as a zstd frame, for
+    // read_from to load. The table is serialized in memory first, since the
+    // encoder takes its input whole.
+    pub fn write_compressed<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let mut buf = Vec::with_capacity(self.serialized_len());
+        self.write_to(&mut buf)?;
+        w.write_all(&compress_to_vec(&buf[..], CompressionLevel::Fastest))
+    }
+
+    // SaveCompressed writes the table to path atomically, as save does, but
+    // compressed by write_compressed. Load reads it back.
+    pub fn save_compressed<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        write_atomic(path.as_ref(), |f| self.write_compressed(f))
+    }
+}
+
+// IsZstd reports whether a stream starting with magic is a zstd frame.
+pub(crate) fn is_zstd(magic: &[u8; 4]) -> bool {
+    *magic == ZSTD_MAGIC
+}
+
+// Decoder decompresses the zstd frame r as it is read.
+pub(crate) fn decoder<R: Read>(r: R) -> io::Result<StreamingDecoder<R, FrameDecoder>> {
+    StreamingDecoder::new(r).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use crate::{DefaultState, FormatError, Table, TableBuilder};
+
+    #[test]
+    fn compressed() {
+        let keys: Vec<String> = (0..10000).map(|i| format!("key{}", i)).collect();
+        for t in [
+            Table::new(&keys),
+            TableBuilder::new().minimal(true).build(&keys),
+        ] {
+            let mut buf = Vec::new();
+            t.write_compressed(&mut buf).unwrap();
+            assert!(buf.len() < t.to_bytes().len());
+            assert_eq!(
+                Table::<DefaultState>::from_bytes(&buf).err(),
+                Some(FormatError::BadMagic)
+            );
+            let u: Table = Table::read_from(&buf[..]).unwrap();
+            for k in &keys {
+                assert_eq!(u.query(k), t.query(k));
+            }
+        }
+
+        let path = std::env::temp_dir().join(format!("mph-rs-zstd-{}", process::id()));
+        let t = Table::new(&keys);
+        t.save_compressed(&path).unwrap();
+        let u: Table = Table::load(&path).unwrap();
+        assert_eq!(u.query("key9"), t.query("key9"));
+        fs::remove_file(&path).unwrap();
+
+        // a damaged frame
+        let mut buf = Vec::new();
+        t.write_compressed(&mut buf).unwrap();
+        buf.truncate(buf.len() / 2);
+        assert!(Table::<DefaultState>::read_from(&buf[..]).is_err());
+    }
+}
@@ -0,0 +1,226 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+use crate::format::{write_atomic, Crc32, FormatError, Reader};
+use crate::hash::{DefaultState, StoredHasher};
+use crate::Table;
+
+// An archive file holds named tables, all integers little-endian:
+//
+//   magic     4 bytes  "MPHA"
+//   version   u16
+//   reserved  u16      0
+//   count     u32      tables
+//   index     u32      length of the index
+//   entries   per table, sorted by name:
+//     name    u32 length, then UTF-8 bytes
+//     offset  u64      of the table from the start of the file
+//     len     u64      of the table
+//   checksum  u32      CRC-32 of the entries
+//   tables    each as written by Table::write_to
+const MAGIC: &[u8; 4] = b"MPHA";
+const VERSION: u16 = 1;
+const HEADER_LEN: usize = 4 + 2 + 2 + 4 + 4;
+
+// Archive is a file of named tables, such as one per locale. Opening it reads
+// only the index; each table is loaded the first time it is asked for, and
+// then kept.
+pub struct Archive<S = DefaultState> {
+    file: Mutex<File>,
+    entries: Vec<Entry>,
+    tables: Vec<OnceLock<Table<S>>>,
+}
+
+struct Entry {
+    name: String,
+    offset: u64,
+    len: u64,
+}
+
+impl<S: StoredHasher> Archive<S> {
+    // Save writes tables to path atomically, as Table::save does. Names must
+    // be distinct.
+    pub fn save<P: AsRef<Path>>(path: P, tables: &[(&str, &Table<S>)]) -> io::Result<()> {
+        let mut tables = tables.to_vec();
+        tables.sort_by_key(|&(name, _)| name);
+        if tables.windows(2).any(|w| w[0].0 == w[1].0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "duplicate table name",
+            ));
+        }
+
+        let index_len: usize = tables.iter().map(|(name, _)| 4 + name.len() + 16).sum();
+        let mut offset = (HEADER_LEN + index_len + 4) as u64;
+        let mut index = Vec::with_capacity(index_len);
+        for (name, t) in &tables {
+            let len = t.serialized_len() as u64;
+            index.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            index.extend_from_slice(name.as_bytes());
+            index.extend_from_slice(&offset.to_le_bytes());
+            index.extend_from_slice(&len.to_le_bytes());
+            offset += len;
+        }
+        let mut crc = Crc32::new();
+        crc.update(&index);
+
+        write_atomic(path.as_ref(), |f| {
+            let mut w = io::BufWriter::new(f);
+            w.write_all(MAGIC)?;
+            w.write_all(&VERSION.to_le_bytes())?;
+            w.write_all(&[0, 0])?;
+            w.write_all(&(tables.len() as u32).to_le_bytes())?;
+            w.write_all(&(index.len() as u32).to_le_bytes())?;
+            w.write_all(&index)?;
+            w.write_all(&crc.sum().to_le_bytes())?;
+            for (_, t) in &tables {
+                t.write_to(&mut w)?;
+            }
+            w.flush()
+        })
+    }
+
+    // Open reads the index of the archive at path.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Archive<S>> {
+        let mut file = File::open(path)?;
+        let mut header = [0u8; HEADER_LEN];
+        file.read_exact(&mut header)?;
+
+        let mut r = Reader { buf: &header };
+        if r.take(4)? != MAGIC {
+            return Err(FormatError::BadMagic.into());
+        }
+        let version = r.int(2)? as u16;
+        if version != VERSION {
+            return Err(FormatError::UnsupportedVersion(version).into());
+        }
+        r.int(2)?;
+        let count = r.int(4)? as usize;
+        let index_len = r.int(4)? as usize;
+
+        let mut index = Vec::new();
+        (&mut file)
+            .take(index_len as u64 + 4)
+            .read_to_end(&mut index)?;
+        if index.len() < index_len + 4 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let mut crc = Crc32::new();
+        crc.update(&index[..index_len]);
+        if crc.sum().to_le_bytes() != index[index_len..] {
+            return Err(FormatError::Corrupted.into());
+        }
+
+        let mut r = Reader {
+            buf: &index[..index_len],
+        };
+        let mut entries = Vec::with_capacity(count.min(index_len / 20));
+        for _ in 0..count {
+            let n = r.int(4)? as usize;
+            let name = std::str::from_utf8(r.take(n)?)
+                .map_err(|_| FormatError::Invalid("table name"))?
+                .to_string();
+            entries.push(Entry {
+                name,
+                offset: r.int(8)?,
+                len: r.int(8)?,
+            });
+        }
+        if !r.buf.is_empty() {
+            return Err(FormatError::Invalid("trailing index data").into());
+        }
+        if entries.windows(2).any(|w| w[0].name >= w[1].name) {
+            return Err(FormatError::Invalid("unsorted index").into());
+        }
+
+        Ok(Archive {
+            file: Mutex::new(file),
+            tables: entries.iter().map(|_| OnceLock::new()).collect(),
+            entries,
+        })
+    }
+
+    // Get returns the table called name, loading it on first use, or None if
+    // the archive has no such table.
+    pub fn get(&self, name: &str) -> io::Result<Option<&Table<S>>> {
+        let i = match self.entries.binary_search_by(|e| e.name.as_str().cmp(name)) {
+            Ok(i) => i,
+            Err(_) => return Ok(None),
+        };
+        if let Some(t) = self.tables[i].get() {
+            return Ok(Some(t));
+        }
+
+        let e = &self.entries[i];
+        let t = {
+            let mut file = self.file.lock().unwrap_or_else(|err| err.into_inner());
+            file.seek(SeekFrom::Start(e.offset))?;
+            Table::read_from((&mut *file).take(e.len))?
+        };
+        // another thread may have loaded it meanwhile; either copy will do
+        let _ = self.tables[i].set(t);
+        Ok(self.tables[i].get())
+    }
+
+    // Names returns the names of the tables, in sorted order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|e| e.name.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TableBuilder;
+    use std::fs;
+
+    #[test]
+    fn archive() {
+        let dir = std::env::temp_dir().join(format!("mph-rs-archive-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("locales.mpha");
+
+        let en: Vec<String> = (0..1000).map(|i| format!("hello{}", i)).collect();
+        let de: Vec<String> = (0..500).map(|i| format!("hallo{}", i)).collect();
+        let ten = Table::new(&en);
+        let tde = TableBuilder::new().minimal(true).build(&de);
+        let empty = Table::new(&Vec::<String>::new());
+        Archive::save(&path, &[("en-US", &ten), ("de-DE", &tde), ("xx", &empty)]).unwrap();
+
+        let a = Archive::<crate::hash::SipState>::open(&path).unwrap();
+        assert_eq!(a.len(), 3);
+        assert_eq!(a.names().collect::<Vec<_>>(), ["de-DE", "en-US", "xx"]);
+        assert!(a.get("fr-FR").unwrap().is_none());
+
+        let t = a.get("en-US").unwrap().unwrap();
+        for (i, k) in en.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+        }
+        let t = a.get("de-DE").unwrap().unwrap();
+        for k in &de {
+            assert_eq!(t.query(k), tde.query(k));
+        }
+        assert!(a.get("xx").unwrap().unwrap().is_empty());
+
+        assert!(Archive::save(&path, &[("a", &ten), ("a", &tde)]).is_err());
+
+        // a damaged index
+        let mut b = fs::read(&path).unwrap();
+        b[HEADER_LEN + 5] ^= 1;
+        fs::write(&path, &b).unwrap();
+        let err = Archive::<crate::hash::SipState>::open(&path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,177 @@
+use std::fmt::Write;
+
+use crate::Table;
+
+// Slot is how a slot came to hold what it does, as far as the table's arrays
+// tell.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    // a single-key bucket's negative seed points at it
+    Direct,
+    // a multi-key bucket's seed sent one of its keys here
+    Seeded,
+    Free,
+    // without the keys, can't be told apart from Seeded or Free
+    Unknown,
+}
+
+impl Slot {
+    fn name(self) -> &'static str {
+        match self {
+            Slot::Direct => "direct",
+            Slot::Seeded => "seeded",
+            Slot::Free => "free",
+            Slot::Unknown => "unknown",
+        }
+    }
+}
+
+impl<S> Table<S> {
+    // ToDebugJson dumps the table as JSON for diffing builds: its sizes, the
+    // raw seeds and values, and the provenance of each bucket ("seeded",
+    // "direct" or "empty") and slot ("seeded", "direct", "free" or
+    // "unknown").
+    //
+    // Value 0 doubles as "no key" in the slots of a table with values, so the
+    // slot of key 0 can only be told from the free ones when it is the only
+    // candidate. The slots of k-perfect tables hold up to k keys each and are
+    // unknown unless directly placed.
+    pub fn to_debug_json(&self) -> String {
+        let kind = if self.occupied.is_some() {
+            "minimal"
+        } else if self.values.is_empty() {
+            "shared"
+        } else {
+            "plain"
+        };
+
+        let mut out = String::new();
+        write!(
+            out,
+            "{{\"kind\":\"{}\",\"len\":{},\"size\":{},\"seed\":{}",
+            kind, self.len, self.size, self.hasher.seed
+        )
+        .unwrap();
+        list(&mut out, "seeds", self.seeds.iter());
+        list(&mut out, "values", self.values.iter());
+
+        let buckets = self.seeds.iter().map(|&s| match s {
+            0 => "\"empty\"",
+            s if s < 0 => "\"direct\"",
+            _ => "\"seeded\"",
+        });
+        list(&mut out, "buckets", buckets);
+
+        let slots = self.slot_kinds();
+        list(
+            &mut out,
+            "slots",
+            slots.iter().map(|s| format!("\"{}\"", s.name())),
+        );
+        out.push('}');
+        out
+    }
+
+    fn slot_kinds(&self) -> Vec<Slot> {
+        let mut slots = vec![Slot::Unknown; self.size];
+
+        if let Some(bits) = &self.occupied {
+            // direct seeds hold the rank of their slot
+            let mut by_rank = Vec::with_capacity(self.len);
+            for (i, s) in slots.iter_mut().enumerate() {
+                *s = if bits.get(i) {
+                    by_rank.push(i);
+                    Slot::Seeded
+                } else {
+                    Slot::Free
+                };
+            }
+            for &s in self.seeds.iter().filter(|&&s| s < 0) {
+                slots[by_rank[(-s - 1) as usize]] = Slot::Direct;
+            }
+            return slots;
+        }
+
+        for &s in self.seeds.iter().filter(|&&s| s < 0) {
+            slots[(-s - 1) as usize] = Slot::Direct;
+        }
+        if self.values.is_empty() {
+            return slots;
+        }
+
+        let zeros = (0..self.size)
+            .filter(|&i| slots[i] != Slot::Direct && self.values[i] == 0)
+            .count();
+        let zero_direct = (0..self.size).any(|i| slots[i] == Slot::Direct && self.values[i] == 0);
+        let zero = if zero_direct || self.len == 0 {
+            Slot::Free
+        } else if zeros == 1 {
+            Slot::Seeded
+        } else {
+            Slot::Unknown
+        };
+        for (s, &v) in slots.iter_mut().zip(&self.values) {
+            if *s != Slot::Direct {
+                *s = if v == 0 { zero } else { Slot::Seeded };
+            }
+        }
+        slots
+    }
+}
+
+// List appends ,"name":[items...] to out.
+fn list<T: std::fmt::Display>(out: &mut String, name: &str, items: impl Iterator<Item = T>) {
+    write!(out, ",\"{}\":[", name).unwrap();
+    for (i, x) in items.enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write!(out, "{}", x).unwrap();
+    }
+    out.push(']');
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Table, TableBuilder};
+
+    #[test]
+    fn debug_json() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("key{}", i)).collect();
+
+        for t in [
+            Table::new(&keys),
+            TableBuilder::new().minimal(true).build(&keys),
+            TableBuilder::new().k(4).build(&keys),
+        ] {
+            let v: serde_json::Value = serde_json::from_str(&t.to_debug_json()).unwrap();
+            let count = |field: &str, what: &str| {
+                v[field]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .filter(|s| *s == what)
+                    .count()
+            };
+
+            assert_eq!(v["len"], 1000);
+            assert_eq!(v["slots"].as_array().unwrap().len(), t.size);
+            assert_eq!(
+                count("buckets", "direct"),
+                t.seeds.iter().filter(|&&s| s < 0).count()
+            );
+            if v["kind"] != "shared" {
+                assert_eq!(count("slots", "direct"), count("buckets", "direct"));
+
+                // at most key 0's slot is hidden among the free ones
+                let unknown = count("slots", "unknown");
+                let hidden = (unknown > 0) as usize;
+                assert_eq!(count("slots", "free") + unknown, t.size - 1000 + hidden);
+                assert_eq!(
+                    count("slots", "seeded") + count("slots", "direct") + hidden,
+                    1000
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,161 @@
+// External-memory builds: the keys' hashes spilled to disk and built a
+// partition at a time.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::fmix64;
+use crate::partitioned::partition;
+
+// SPILL_BITS is log2 of the number of spill files. A partition is a run of
+// whole files, so it also caps the number of partitions.
+const SPILL_BITS: u32 = 8;
+
+// BYTES_PER_KEY is roughly what a build holds per key of a partition: its
+// hash and displacement, its bucket entry, and its share of the values,
+// seeds and scratch arrays.
+const BYTES_PER_KEY: usize = 64;
+
+static SPILLS: AtomicUsize = AtomicUsize::new(0);
+
+// Spill holds the hashes of a build's keys in files under a directory of its
+// own, one file per slice of the hash space, split by the top bits as
+// partitions are. The directory is removed when the Spill is dropped.
+pub(crate) struct Spill {
+    dir: PathBuf,
+    files: Vec<BufWriter<File>>,
+    // keys in each file
+    counts: Vec<usize>,
+    second_hash: bool,
+}
+
+impl Spill {
+    // New creates an empty spill in a new directory under parent.
+    pub(crate) fn new(parent: &Path, second_hash: bool) -> io::Result<Spill> {
+        let n = SPILLS.fetch_add(1, Ordering::Relaxed);
+        let dir = parent.join(format!("mph-rs-spill-{}-{}", process::id(), n));
+        fs::create_dir(&dir)?;
+        let mut spill = Spill {
+            dir,
+            files: Vec::with_capacity(1 << SPILL_BITS),
+            counts: vec![0; 1 << SPILL_BITS],
+            second_hash,
+        };
+        for f in 0..1 << SPILL_BITS {
+            let file = File::create(spill.dir.join(f.to_string()))?;
+            spill.files.push(BufWriter::new(file));
+        }
+        Ok(spill)
+    }
+
+    // Push appends a key's hash and, with second_hash, its displacement.
+    pub(crate) fn push(&mut self, h: u64, d: u64) -> io::Result<()> {
+        let f = partition(h, SPILL_BITS);
+        self.counts[f] += 1;
+        let w = &mut self.files[f];
+        w.write_all(&h.to_le_bytes())?;
+        if self.second_hash {
+            w.write_all(&d.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    // Seal flushes the files, after the last push.
+    pub(crate) fn seal(&mut self) -> io::Result<()> {
+        self.files.iter_mut().try_for_each(|w| w.flush())
+    }
+
+    // Partitions returns the fewest partitions, as a power of two, whose
+    // keys each fit in budget bytes on average, or as many as there are
+    // files.
+    pub(crate) fn partitions(&self, budget: usize) -> usize {
+        let n: usize = self.counts.iter().sum();
+        let per = (budget / BYTES_PER_KEY).max(1);
+        n.div_ceil(per).next_power_of_two().min(1 << SPILL_BITS)
+    }
+
+    // Read loads partition p of nparts into hashes and, with second_hash,
+    // disp, replacing what they held. The hashes are remixed as Shards
+    // remixes them.
+    pub(crate) fn read(
+        &self,
+        p: usize,
+        nparts: usize,
+        hashes: &mut Vec<u64>,
+        disp: &mut Vec<u64>,
+    ) -> io::Result<()> {
+        let per = (1 << SPILL_BITS) / nparts;
+        let files = p * per..(p + 1) * per;
+        let n: usize = self.counts[files.clone()].iter().sum();
+        hashes.clear();
+        hashes.reserve(n);
+        disp.clear();
+        if self.second_hash {
+            disp.reserve(n);
+        }
+
+        let mut word = [0u8; 8];
+        for f in files {
+            let mut r = BufReader::new(File::open(self.dir.join(f.to_string()))?);
+            for _ in 0..self.counts[f] {
+                r.read_exact(&mut word)?;
+                hashes.push(fmix64(u64::from_le_bytes(word)));
+                if self.second_hash {
+                    r.read_exact(&mut word)?;
+                    disp.push(u64::from_le_bytes(word));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        self.files.clear();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::process;
+
+    use crate::TableBuilder;
+
+    #[test]
+    fn build_external() {
+        let dir = std::env::temp_dir().join(format!("mph-rs-external-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let keys: Vec<String> = (0..20000).map(|i| format!("k{}", i)).collect();
+
+        for b in [
+            TableBuilder::new(),
+            TableBuilder::new().minimal(true).second_hash(true),
+        ] {
+            // about 5000 keys' worth of memory
+            let t = b.build_external(&keys, &dir, 5000 * 64).unwrap();
+            assert_eq!(t.partitions(), 4);
+            assert_eq!(t.len(), keys.len());
+            let mut seen = vec![false; keys.len()];
+            for k in &keys {
+                let v = t.query(k);
+                assert!(!seen[v], "duplicate value {}", v);
+                seen[v] = true;
+            }
+        }
+
+        let t = TableBuilder::new()
+            .build_external(&keys, &dir, 1 << 30)
+            .unwrap();
+        assert_eq!(t.partitions(), 1);
+
+        // the spill files are gone
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,113 @@
+use std::hash::Hash;
+
+use crate::hash::{DefaultState, KeyHasher};
+use crate::{place, xorshift_mult64, Buckets, Params, Run, Work, MAX_RETRIES};
+
+// StaticTable is a table of SIZE slots and SIZE buckets, SIZE being a power
+// of two fixed at compile time, for the small, fixed key sets of keyword and
+// route tables. Its masks are constants and its arrays inline, so a query
+// compiles to a hash, two loads and a handful of instructions with no bounds
+// checks left, and the table can live in a static once built.
+//
+// Query returns the key's index in the input, as Table::query does.
+pub struct StaticTable<const SIZE: usize> {
+    hasher: KeyHasher<DefaultState>,
+    seeds: [i32; SIZE],
+    values: [i32; SIZE],
+    len: usize,
+}
+
+impl<const SIZE: usize> StaticTable<SIZE> {
+    // MASK reduces hashes to 0..SIZE, and fails the build if SIZE isn't a
+    // power of two.
+    const MASK: usize = {
+        assert!(SIZE.is_power_of_two(), "SIZE must be a power of two");
+        SIZE - 1
+    };
+
+    // New builds a table over keys, of which there must be at most SIZE. A
+    // full table places about as readily as a Table of the same load does;
+    // should a placement fail, the keys are hashed again with another seed.
+    pub fn new<K: Hash>(keys: &[K]) -> StaticTable<SIZE> {
+        assert!(keys.len() <= SIZE, "more keys than slots");
+
+        let params = Params::default();
+        let mut hasher = KeyHasher::new(DefaultState::default());
+        let mut h = Buckets::default();
+        let mut order = Vec::new();
+        let mut w = Work::default();
+        for retry in 0..=MAX_RETRIES {
+            hasher.seed = retry as u64;
+            let hashes: Vec<u64> = keys.iter().map(|k| hasher.hash(k)).collect();
+            h.fill(&hashes, None, Self::MASK + 1);
+            h.by_size(&mut order);
+            let placed = place(
+                &hashes,
+                &h,
+                &order,
+                SIZE,
+                &params,
+                &mut Run::default(),
+                &mut w,
+            );
+            if let Ok(Some((values, seeds, _))) = placed {
+                let mut t = StaticTable {
+                    hasher,
+                    seeds: [0; SIZE],
+                    values: [0; SIZE],
+                    len: keys.len(),
+                };
+                t.seeds.copy_from_slice(&seeds);
+                t.values.copy_from_slice(&values);
+                return t;
+            }
+        }
+        panic!("no seed found; are the keys distinct?")
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Query looks up k and returns its index in the input. Keys that were
+    // not in the input get an arbitrary value below SIZE.
+    #[inline]
+    pub fn query<K: Hash + ?Sized>(&self, k: &K) -> usize {
+        let hash = self.hasher.hash(k);
+        let seed = self.seeds[hash as usize & Self::MASK];
+        // as in Table::locate
+        let direct = !seed as usize;
+        let slot = xorshift_mult64(hash.wrapping_add(seed as u64)) as usize;
+        let neg = (seed >> 31) as usize;
+        let i = (direct & neg) | (slot & !neg);
+        self.values[i & Self::MASK] as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StaticTable;
+
+    const KEYWORDS: [&str; 16] = [
+        "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn",
+        "for", "if", "impl", "in", "let", "loop",
+    ];
+
+    #[test]
+    fn static_table() {
+        let t = StaticTable::<16>::new(&KEYWORDS);
+        assert_eq!(t.len(), 16);
+        for (i, k) in KEYWORDS.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+        }
+        assert!(t.query("match") < 16);
+
+        let t = StaticTable::<32>::new(&KEYWORDS[..3]);
+        assert_eq!(t.query("const"), 2);
+        assert!(StaticTable::<1>::new::<&str>(&[]).is_empty());
+    }
+}
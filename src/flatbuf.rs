@@ -0,0 +1,212 @@
+// FlatBuffers support: Table to and from the layout in schema/table.fbs, for
+// consumers that can generate readers from the schema but not load the
+// crate's own format.
+//
+// The accessors below stand in for flatc's generated code, so the crate
+// doesn't need flatc to build. The slots are the schema's field ids, in
+// order.
+
+use flatbuffers::{
+    FlatBufferBuilder, Follow, ForwardsUOffset, InvalidFlatbuffer, VOffsetT, Vector, Verifiable,
+    Verifier, WIPOffset,
+};
+
+use crate::format::{assemble, count, FormatError, Header};
+use crate::hash::StoredHasher;
+use crate::metadata::BuildInfo;
+use crate::Table;
+
+const IDENTIFIER: &str = "MPHT";
+
+const ALGO: VOffsetT = 4;
+const FLAGS: VOffsetT = 6;
+const PARAM0: VOffsetT = 8;
+const PARAM1: VOffsetT = 10;
+const SEED: VOffsetT = 12;
+const LEN: VOffsetT = 14;
+const SIZE: VOffsetT = 16;
+const SEEDS: VOffsetT = 18;
+const VALUES: VOffsetT = 20;
+const OCCUPIED: VOffsetT = 22;
+const RANKS: VOffsetT = 24;
+const BUILDER: VOffsetT = 26;
+const BUILT_AT: VOffsetT = 28;
+const COMMENT: VOffsetT = 30;
+
+// FbTable is the schema's Table, read in place.
+struct FbTable<'a> {
+    tab: flatbuffers::Table<'a>,
+}
+
+impl<'a> Follow<'a> for FbTable<'a> {
+    type Inner = FbTable<'a>;
+
+    unsafe fn follow(buf: &'a [u8], loc: usize) -> FbTable<'a> {
+        FbTable {
+            tab: flatbuffers::Table::new(buf, loc),
+        }
+    }
+}
+
+impl Verifiable for FbTable<'_> {
+    fn run_verifier(v: &mut Verifier, pos: usize) -> Result<(), InvalidFlatbuffer> {
+        v.visit_table(pos)?
+            .visit_field::<u8>("algo", ALGO, false)?
+            .visit_field::<u8>("flags", FLAGS, false)?
+            .visit_field::<u64>("param0", PARAM0, false)?
+            .visit_field::<u64>("param1", PARAM1, false)?
+            .visit_field::<u64>("seed", SEED, false)?
+            .visit_field::<u64>("len", LEN, false)?
+            .visit_field::<u64>("size", SIZE, false)?
+            .visit_field::<ForwardsUOffset<Vector<i32>>>("seeds", SEEDS, false)?
+            .visit_field::<ForwardsUOffset<Vector<i32>>>("values", VALUES, false)?
+            .visit_field::<ForwardsUOffset<Vector<u64>>>("occupied", OCCUPIED, false)?
+            .visit_field::<ForwardsUOffset<Vector<u32>>>("ranks", RANKS, false)?
+            .visit_field::<ForwardsUOffset<&str>>("builder", BUILDER, false)?
+            .visit_field::<u64>("built_at", BUILT_AT, false)?
+            .visit_field::<ForwardsUOffset<&str>>("comment", COMMENT, false)?
+            .finish();
+        Ok(())
+    }
+}
+
+// The accessors only run on a table that root has verified, so every read is
+// in bounds.
+impl<'a> FbTable<'a> {
+    fn scalar<T: Follow<'a, Inner = T> + 'a>(&self, slot: VOffsetT, default: T) -> T {
+        unsafe { self.tab.get::<T>(slot, Some(default)).unwrap() }
+    }
+
+    fn vec<T: Follow<'a, Inner = T> + 'a>(&self, slot: VOffsetT) -> Vec<T> {
+        let v = unsafe { self.tab.get::<ForwardsUOffset<Vector<'a, T>>>(slot, None) };
+        v.map_or_else(Vec::new, |v| v.iter().collect())
+    }
+
+    fn text(&self, slot: VOffsetT) -> String {
+        let s = unsafe { self.tab.get::<ForwardsUOffset<&str>>(slot, None) };
+        s.unwrap_or_default().to_string()
+    }
+}
+
+impl<S: StoredHasher> Table<S> {
+    // ToFlatbuffer serializes the table as a FlatBuffer with the layout in
+    // schema/table.fbs, for services in other languages.
+    //
+    // A normalizer is a function pointer and can't be stored, so a table
+    // built with one panics here.
+    pub fn to_flatbuffer(&self) -> Vec<u8> {
+        assert!(
+            self.hasher.normalize.is_none(),
+            "can't serialize a table with a normalizer"
+        );
+
+        let (words, ranks) = self
+            .occupied
+            .as_ref()
+            .map_or((&[][..], &[][..]), |b| (b.words(), b.ranks()));
+        let mut fbb = FlatBufferBuilder::with_capacity(self.serialized_len());
+        let seeds = fbb.create_vector(&self.seeds);
+        let values = fbb.create_vector(&self.values);
+        let occupied = fbb.create_vector(words);
+        let ranks = fbb.create_vector(ranks);
+        let builder = fbb.create_string(&self.info.version);
+        let comment = fbb.create_string(&self.info.comment);
+
+        let [p0, p1] = self.hasher.build.params();
+        let start = fbb.start_table();
+        fbb.push_slot::<u64>(PARAM0, p0, 0);
+        fbb.push_slot::<u64>(PARAM1, p1, 0);
+        fbb.push_slot::<u64>(SEED, self.hasher.seed, 0);
+        fbb.push_slot::<u64>(LEN, self.len as u64, 0);
+        fbb.push_slot::<u64>(SIZE, self.size as u64, 0);
+        fbb.push_slot::<u64>(BUILT_AT, self.info.built_at, 0);
+        fbb.push_slot_always::<WIPOffset<_>>(SEEDS, seeds);
+        fbb.push_slot_always::<WIPOffset<_>>(VALUES, values);
+        fbb.push_slot_always::<WIPOffset<_>>(OCCUPIED, occupied);
+        fbb.push_slot_always::<WIPOffset<_>>(RANKS, ranks);
+        fbb.push_slot_always::<WIPOffset<_>>(BUILDER, builder);
+        fbb.push_slot_always::<WIPOffset<_>>(COMMENT, comment);
+        fbb.push_slot::<u8>(ALGO, self.hasher.build.algo_id(), 0);
+        fbb.push_slot::<u8>(FLAGS, self.flags(), 0);
+        let root = fbb.end_table(start);
+        fbb.finish(root, Some(IDENTIFIER));
+        fbb.finished_data().to_vec()
+    }
+
+    // FromFlatbuffer loads a table written by to_flatbuffer, or by another
+    // language from schema/table.fbs, checking it as from_bytes does.
+    pub fn from_flatbuffer(bytes: &[u8]) -> Result<Table<S>, FormatError> {
+        if bytes.len() < 8 || !flatbuffers::buffer_has_identifier(bytes, IDENTIFIER, false) {
+            return Err(FormatError::BadMagic);
+        }
+        let t = flatbuffers::root::<FbTable>(bytes)
+            .map_err(|_| FormatError::Invalid("malformed flatbuffer"))?;
+
+        let seeds = t.vec::<i32>(SEEDS);
+        let values = t.vec::<i32>(VALUES);
+        let header = Header {
+            algo: t.scalar(ALGO, 0u8),
+            flags: t.scalar(FLAGS, 0u8),
+            params: [t.scalar(PARAM0, 0u64), t.scalar(PARAM1, 0u64)],
+            seed: t.scalar(SEED, 0u64),
+            len: count(t.scalar(LEN, 0u64))?,
+            size: count(t.scalar(SIZE, 0u64))?,
+            nseeds: seeds.len(),
+            nvalues: values.len(),
+            checksum: None,
+            nmeta: 0,
+        }
+        .validate()?;
+
+        let info = BuildInfo {
+            version: t.text(BUILDER),
+            built_at: t.scalar(BUILT_AT, 0u64),
+            comment: t.text(COMMENT),
+        };
+        assemble(
+            &header,
+            seeds,
+            values,
+            t.vec::<u64>(OCCUPIED),
+            t.vec::<u32>(RANKS),
+            info,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hash::SipState;
+    use crate::{FormatError, Table, TableBuilder};
+
+    #[test]
+    fn flatbuffer() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("key{}", i)).collect();
+
+        for t in [
+            Table::new(&keys),
+            TableBuilder::new()
+                .minimal(true)
+                .seed(3)
+                .comment("minimal")
+                .build(&keys),
+            TableBuilder::new().k(4).second_hash(true).build(&keys),
+        ] {
+            let u = Table::<SipState>::from_flatbuffer(&t.to_flatbuffer()).unwrap();
+            assert_eq!(u.to_bytes(), t.to_bytes());
+            for k in &keys {
+                assert_eq!(u.query(k), t.query(k));
+            }
+        }
+
+        let b = Table::new(&keys).to_flatbuffer();
+        assert_eq!(
+            Table::<SipState>::from_flatbuffer(&b[..b.len() / 2]).err(),
+            Some(FormatError::Invalid("malformed flatbuffer"))
+        );
+        assert_eq!(
+            Table::<SipState>::from_flatbuffer(&Table::new(&keys).to_bytes()).err(),
+            Some(FormatError::BadMagic)
+        );
+    }
+}
@@ -0,0 +1,1315 @@
+use core::convert::TryInto;
+use core::fmt;
+use core::hash::{BuildHasher, Hash};
+#[cfg(feature = "std")]
+use std::error::Error;
+#[cfg(feature = "std")]
+use std::ffi::OsString;
+#[cfg(feature = "std")]
+use std::fs::{self, File, OpenOptions};
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+#[cfg(feature = "std")]
+use std::path::Path;
+#[cfg(feature = "std")]
+use std::process;
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::hash::{DefaultState, HashAlgo, KeyHasher, SipState, StoredHasher};
+#[cfg(feature = "std")]
+use crate::metadata::BuildInfo;
+#[cfg(feature = "std")]
+use crate::rank::RankBits;
+use crate::rank::{rank_by, BLOCK_WORDS};
+#[cfg(feature = "std")]
+use crate::Table;
+use crate::{reduce, xorshift_mult64};
+
+// The serialized form of a Table, all integers little-endian:
+//
+//   magic     4 bytes  "MPHT"
+//   version   u16
+//   algo      u8       hash algorithm id, as in HashAlgo::id
+//   flags     u8       see the FLAG_ constants
+//   params    2 x u64  hash builder parameters (keys, seed)
+//   seed      u64      global seed mixed into key hashes
+//   len       u64      keys
+//   size      u64      slots
+//   nseeds    u64
+//   nvalues   u64      size, or 0 for minimal and k-perfect tables
+//   checksum  u32      CRC-32 of the header before it and everything after
+//   nmeta     u32      length of the metadata
+//   metadata  nmeta bytes, as described at BuildInfo
+//   seeds     nseeds x i32
+//   values    nvalues x i32
+//   occupied  ceil(size / 64) x u64, minimal tables only
+//   ranks     ceil(size / 512) + 1 x u32, the occupied bits' rank samples,
+//             minimal tables only
+//
+// The seeds and values arrays mean what they do in go-mph, whose bucket and
+// slot arithmetic (power-of-two masks, xorshift_mult64 of seed plus hash)
+// Table shares. go-mph has no serialized form of its own; its arrays load
+// with Table::read_go_mph, and Table::go_mph builds tables it can query.
+//
+// Version 2 is the same without the metadata, and version 1 also without the
+// checksum. Their tables still load, with empty metadata and, for version 1,
+// checked for consistency but not against a checksum, and are written back as
+// the current version.
+const MAGIC: &[u8; 4] = b"MPHT";
+const VERSION: u16 = 3;
+const MIN_VERSION: u16 = 1;
+const V1_HEADER_LEN: usize = 4 + 2 + 1 + 1 + 8 * 7;
+const V2_HEADER_LEN: usize = V1_HEADER_LEN + 4;
+#[cfg(feature = "std")]
+const HEADER_LEN: usize = V2_HEADER_LEN + 4;
+
+const FLAG_SECOND_HASH: u8 = 1;
+const FLAG_FOLD_CASE: u8 = 2;
+const FLAG_MINIMAL: u8 = 4;
+const FLAG_SHARED: u8 = 8;
+// FLAG_UNICODE_CASE goes with FLAG_FOLD_CASE when keys were folded with
+// str::to_lowercase, under the unicode-case feature, rather than ASCII
+// lowercasing. Builds without the feature refuse such tables.
+const FLAG_UNICODE_CASE: u8 = 16;
+
+// FormatError describes why bytes could not be loaded as a table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FormatError {
+    // The data doesn't start with the table magic.
+    BadMagic,
+    // The data was written by a version of the format outside MIN_VERSION to
+    // VERSION, usually a newer one.
+    UnsupportedVersion(u16),
+    // The hash algorithm isn't the table's hash builder type, or its feature
+    // isn't enabled.
+    UnknownHashAlgo(u8),
+    // The data ends early.
+    Truncated,
+    // The header and arrays are inconsistent.
+    Invalid(&'static str),
+    // The data doesn't match its checksum.
+    Corrupted,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormatError::BadMagic => write!(f, "not a serialized table"),
+            FormatError::UnsupportedVersion(v) => write!(
+                f,
+                "unsupported format version {} (supported: {} to {})",
+                v, MIN_VERSION, VERSION
+            ),
+            FormatError::UnknownHashAlgo(id) => write!(f, "unknown hash algorithm {}", id),
+            FormatError::Truncated => write!(f, "truncated table"),
+            FormatError::Invalid(what) => write!(f, "invalid table: {}", what),
+            FormatError::Corrupted => write!(f, "table checksum mismatch"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for FormatError {}
+
+#[cfg(feature = "std")]
+impl<S: StoredHasher> Table<S> {
+    // ToBytes serializes the table in the crate's stable binary format, for
+    // from_bytes to load, possibly in another process or on another machine.
+    //
+    // A normalizer is a function pointer and can't be stored, so write_to
+    // refuses a table built with one, and to_bytes panics through its unwrap.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.serialized_len());
+        self.write_to(&mut buf).unwrap();
+        buf
+    }
+
+    // WriteTo streams the bytes to_bytes would return into w, a chunk at a
+    // time, so a table can go straight to a file, socket or compressor.
+    //
+    // With the compression feature, write_compressed writes the same bytes as
+    // a zstd frame, which read_from and load recognize. The seeds, mostly
+    // small numbers, shrink several times over; the values, a permutation of
+    // the key indices, compress only to about log2(len) bits each.
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        if self.hasher.normalize.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "can't serialize a table with a normalizer",
+            ));
+        }
+
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.push(self.hasher.build.algo_id());
+        header.push(self.flags());
+        let [p0, p1] = self.hasher.build.params();
+        for x in [
+            p0,
+            p1,
+            self.hasher.seed,
+            self.len as u64,
+            self.size as u64,
+            self.seeds.len() as u64,
+            self.values.len() as u64,
+        ] {
+            header.extend_from_slice(&x.to_le_bytes());
+        }
+
+        // the checksum goes before the arrays, so they're encoded twice
+        // rather than buffered
+        let meta = self.info.encode();
+        let mut crc = Crc32::new();
+        crc.update(&header);
+        self.payload(&meta, |b| {
+            crc.update(b);
+            Ok(())
+        })?;
+        header.extend_from_slice(&crc.sum().to_le_bytes());
+        w.write_all(&header)?;
+
+        let mut chunk = Chunk::new(w);
+        self.payload(&meta, |b| chunk.put(b))?;
+        chunk.flush()
+    }
+
+    // Flags returns the table's FLAG_ bits.
+    pub(crate) fn flags(&self) -> u8 {
+        let mut flags = 0;
+        if self.hasher.second_hash {
+            flags |= FLAG_SECOND_HASH;
+        }
+        if self.hasher.fold_case {
+            flags |= FLAG_FOLD_CASE;
+        }
+        if self.hasher.unicode_case {
+            flags |= FLAG_UNICODE_CASE;
+        }
+        if self.occupied.is_some() {
+            flags |= FLAG_MINIMAL;
+        } else if self.values.is_empty() {
+            flags |= FLAG_SHARED;
+        }
+        flags
+    }
+
+    // Payload passes everything after the checksum to put, in order: the
+    // encoded metadata meta, then the arrays.
+    fn payload<F>(&self, meta: &[u8], mut put: F) -> io::Result<()>
+    where
+        F: FnMut(&[u8]) -> io::Result<()>,
+    {
+        put(&(meta.len() as u32).to_le_bytes())?;
+        put(meta)?;
+        for x in self.seeds.iter().chain(&self.values) {
+            put(&x.to_le_bytes())?;
+        }
+        if let Some(bits) = &self.occupied {
+            for x in bits.words() {
+                put(&x.to_le_bytes())?;
+            }
+            for x in bits.ranks() {
+                put(&x.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    // FromBytes loads a table written by to_bytes, by this or an earlier
+    // version of the crate. The data is checked for consistency, so every
+    // query on the result stays in bounds: damaged or crafted bytes, such as
+    // a table from an untrusted peer, get an error rather than a panic.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Table<S>, FormatError> {
+        let p = parse::<S>(bytes)?;
+        let occupied = (p.header.flags & FLAG_MINIMAL != 0).then(|| {
+            RankBits::new(
+                (0..p.occupied.len() / 8)
+                    .map(|i| le_u64(p.occupied, i))
+                    .collect(),
+            )
+        });
+        Ok(Table {
+            values: (0..p.header.nvalues).map(|i| le_i32(p.values, i)).collect(),
+            seeds: (0..p.header.nseeds).map(|i| le_i32(p.seeds, i)).collect(),
+            hasher: p.header.hasher()?,
+            len: p.header.len,
+            size: p.header.size,
+            occupied,
+            info: BuildInfo::decode(p.meta)?,
+        })
+    }
+
+    // ReadFrom loads a table written by write_to or to_bytes from r, decoding
+    // the arrays a chunk at a time rather than buffering all the bytes. Bad
+    // data is reported as an InvalidData error wrapping a FormatError. With
+    // the compression feature, it also loads the zstd frames
+    // write_compressed writes, decompressing them as it goes.
+    pub fn read_from<R: Read>(mut r: R) -> io::Result<Table<S>> {
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        #[cfg(feature = "compression")]
+        {
+            if crate::compress::is_zstd(&magic) {
+                let r = crate::compress::decoder((&magic[..]).chain(r))?;
+                return Table::read_plain(r);
+            }
+        }
+        Table::read_plain((&magic[..]).chain(r))
+    }
+
+    // ReadPlain is read_from for uncompressed bytes.
+    fn read_plain<R: Read>(mut r: R) -> io::Result<Table<S>> {
+        // the header's length depends on its version
+        let mut buf = [0u8; HEADER_LEN];
+        r.read_exact(&mut buf[..V1_HEADER_LEN])?;
+        let n = header_len(u16::from_le_bytes([buf[4], buf[5]]));
+        r.read_exact(&mut buf[V1_HEADER_LEN..n])?;
+        let header = Header::parse(&mut Reader { buf: &buf[..n] })?;
+        header.hasher::<S>()?;
+
+        let mut chunk = Chunk::new(r);
+        chunk.crc.update(&buf[..V1_HEADER_LEN]);
+        chunk.crc.update(&buf[V2_HEADER_LEN.min(n)..n]);
+        let meta = chunk.read(header.nmeta, u8::from_le_bytes)?;
+        let seeds = chunk.read(header.nseeds, i32::from_le_bytes)?;
+        let values = chunk.read(header.nvalues, i32::from_le_bytes)?;
+        let words = chunk.read(header.words(), u64::from_le_bytes)?;
+        let ranks = chunk.read(header.samples(), u32::from_le_bytes)?;
+        if header.checksum.is_some_and(|sum| chunk.crc.sum() != sum) {
+            return Err(FormatError::Corrupted.into());
+        }
+        chunk.finish()?;
+
+        let info = BuildInfo::decode(&meta)?;
+        Ok(assemble(&header, seeds, values, words, ranks, info)?)
+    }
+
+    // Save writes the table to path atomically: the bytes go to a temporary
+    // file in the same directory, which is synced and then renamed over path.
+    // Readers see either the old file or the whole new one, even across a
+    // crash.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        write_atomic(path.as_ref(), |f| self.write_to(f))
+    }
+
+    // SerializedLen is the length of the bytes to_bytes returns.
+    pub(crate) fn serialized_len(&self) -> usize {
+        let (words, ranks) = self
+            .occupied
+            .as_ref()
+            .map_or((0, 0), |b| (b.words().len(), b.ranks().len()));
+        HEADER_LEN
+            + self.info.encoded_len()
+            + (self.seeds.len() + self.values.len() + ranks) * 4
+            + words * 8
+    }
+
+    // Load reads a table saved with save or save_compressed, or written by
+    // write_to, checking it as read_from does.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Table<S>> {
+        Table::read_from(File::open(path)?)
+    }
+}
+
+// Assemble checks decoded arrays against header and each other, and builds
+// the table they make up.
+#[cfg(feature = "std")]
+pub(crate) fn assemble<S: StoredHasher>(
+    header: &Header,
+    seeds: Vec<i32>,
+    values: Vec<i32>,
+    words: Vec<u64>,
+    ranks: Vec<u32>,
+    info: BuildInfo,
+) -> Result<Table<S>, FormatError> {
+    let hasher = header.hasher()?;
+    if seeds.len() != header.nseeds
+        || values.len() != header.nvalues
+        || words.len() != header.words()
+        || ranks.len() != header.samples()
+    {
+        return Err(FormatError::Invalid("array lengths"));
+    }
+    header.check(|i| seeds[i], |i| values[i], |i| words[i], |i| ranks[i])?;
+
+    Ok(Table {
+        values,
+        seeds,
+        hasher,
+        len: header.len,
+        size: header.size,
+        occupied: header.minimal().then(|| RankBits::new(words)),
+        info,
+    })
+}
+
+#[cfg(feature = "std")]
+static TEMPS: AtomicUsize = AtomicUsize::new(0);
+
+// WriteAtomic replaces path with the bytes write puts in a file: they go to a
+// temporary file in the same directory, which is synced and then renamed over
+// path. Each call has a temporary file of its own, so concurrent saves to one
+// path don't write into each other's; the last rename wins.
+#[cfg(feature = "std")]
+pub(crate) fn write_atomic<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut File) -> io::Result<()>,
+{
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no file name"))?;
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(name);
+    let n = TEMPS.fetch_add(1, Ordering::Relaxed);
+    tmp_name.push(format!(".tmp{}-{}", process::id(), n));
+    let tmp = path.with_file_name(tmp_name);
+
+    // a file already there is someone else's, so it is neither truncated nor
+    // removed
+    let mut f = OpenOptions::new().write(true).create_new(true).open(&tmp)?;
+    let written = write(&mut f).and_then(|_| f.sync_all());
+    drop(f);
+    if let Err(err) = written.and_then(|_| fs::rename(&tmp, path)) {
+        let _ = fs::remove_file(&tmp);
+        return Err(err);
+    }
+
+    // make the rename itself durable
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(d) if !d.as_os_str().is_empty() => d,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+// CHUNK is the buffer size write_to and read_from work in.
+#[cfg(feature = "std")]
+const CHUNK: usize = 1 << 16;
+
+// Chunk buffers array data between the table and a writer or reader. A
+// reader also sums the bytes it reads.
+#[cfg(feature = "std")]
+struct Chunk<T> {
+    io: T,
+    buf: Vec<u8>,
+    crc: Crc32,
+}
+
+#[cfg(feature = "std")]
+impl<T> Chunk<T> {
+    fn new(io: T) -> Chunk<T> {
+        Chunk {
+            io,
+            buf: Vec::with_capacity(CHUNK),
+            crc: Crc32::new(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> Chunk<W> {
+    fn put(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.buf.len() + bytes.len() > CHUNK {
+            self.io.write_all(&self.buf)?;
+            self.buf.clear();
+        }
+        self.buf.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn flush(mut self) -> io::Result<()> {
+        self.io.write_all(&self.buf)?;
+        self.io.flush()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Chunk<R> {
+    // Read decodes n little-endian integers of N bytes each.
+    fn read<X, const N: usize>(
+        &mut self,
+        n: usize,
+        decode: fn([u8; N]) -> X,
+    ) -> io::Result<Vec<X>> {
+        let mut out = Vec::with_capacity(n.min(CHUNK));
+        let mut left = n.checked_mul(N).ok_or(FormatError::Truncated)?;
+        while left > 0 {
+            let step = left.min(CHUNK / N * N);
+            self.buf.resize(step, 0);
+            self.io.read_exact(&mut self.buf)?;
+            self.crc.update(&self.buf);
+            out.extend(
+                self.buf
+                    .chunks_exact(N)
+                    .map(|b| decode(b.try_into().unwrap())),
+            );
+            left -= step;
+        }
+        Ok(out)
+    }
+
+    // Finish checks that the table is the end of the stream.
+    fn finish(mut self) -> io::Result<()> {
+        if self.io.read(&mut [0u8])? != 0 {
+            return Err(FormatError::Invalid("trailing data").into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<FormatError> for io::Error {
+    fn from(err: FormatError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+// TableRef is a table queried in place over bytes written by Table::to_bytes,
+// without copying; loading only validates the data. Together with
+// include_bytes! or a memory map, a table loads in one pass over its bytes.
+//
+// Neither loading nor queries allocate, unless the table folds case with the
+// unicode-case feature, and the format's counts are fixed-width u64s bounded
+// well within a 32-bit usize, so the bytes can sit in read-only memory and be
+// queried where they are, on 32-bit targets too. Without the std feature
+// TableRef and the hashers in hash are all the crate provides, and it builds
+// for no_std targets.
+pub struct TableRef<'a, S = DefaultState> {
+    hasher: KeyHasher<S>,
+    len: usize,
+    size: usize,
+    minimal: bool,
+    seeds: &'a [u8],
+    values: &'a [u8],
+    occupied: &'a [u8],
+    ranks: &'a [u8],
+}
+
+impl<'a, S: StoredHasher> TableRef<'a, S> {
+    // FromBytes checks bytes the way Table::from_bytes does and borrows them.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<TableRef<'a, S>, FormatError> {
+        TableRef::from_parts(parse::<S>(bytes)?)
+    }
+
+    // FromBytesUnverified checks only the header and the array lengths,
+    // skipping the checksum and the pass over the arrays, so loading costs
+    // the same for a table of any size. Over a memory map, as with
+    // Table::open_mmap, that keeps a large table's pages on disk until
+    // queries touch them, and shared between the processes that map it.
+    //
+    // Damaged arrays are not caught; they can only make queries return
+    // wrong values or panic.
+    pub fn from_bytes_unverified(bytes: &'a [u8]) -> Result<TableRef<'a, S>, FormatError> {
+        TableRef::from_parts(Parts::split(bytes)?)
+    }
+
+    fn from_parts(p: Parts<'a>) -> Result<TableRef<'a, S>, FormatError> {
+        Ok(TableRef {
+            hasher: p.header.hasher()?,
+            len: p.header.len,
+            size: p.header.size,
+            minimal: p.header.minimal(),
+            seeds: p.seeds,
+            values: p.values,
+            occupied: p.occupied,
+            ranks: p.ranks,
+        })
+    }
+}
+
+impl<'a> TableRef<'a> {
+    // New is from_bytes for const and static initializers, usually through
+    // include_table!. It panics, at compile time in a const, if the header is
+    // bad or the table wasn't built with the default SipState.
+    //
+    // The checksum and arrays are not verified, as that would make compile
+    // times grow with the table, so only embed bytes written by to_bytes.
+    // Damaged arrays can still only make queries return wrong values or panic.
+    pub const fn new(bytes: &'a [u8]) -> TableRef<'a> {
+        let p = match Parts::split(bytes) {
+            Ok(p) => p,
+            Err(_) => panic!("TableRef::new: not a valid serialized table"),
+        };
+        if p.header.algo != HashAlgo::Sip13.id() {
+            panic!("TableRef::new: table wasn't built with SipState");
+        }
+        let [k0, k1] = p.header.params;
+        TableRef {
+            hasher: p.header.key_hasher(SipState::new_with_keys(k0, k1)),
+            len: p.header.len,
+            size: p.header.size,
+            minimal: p.header.minimal(),
+            seeds: p.seeds,
+            values: p.values,
+            occupied: p.occupied,
+            ranks: p.ranks,
+        }
+    }
+}
+
+// include_table! embeds a table file written by Table::to_bytes or write_to
+// and loads it with TableRef::new, for use in a static:
+//
+//     static KEYWORDS: TableRef = mph_rs::include_table!("keywords.mph");
+//
+// The format is decoded a byte at a time, so the embedded bytes need no
+// particular alignment.
+#[macro_export]
+macro_rules! include_table {
+    ($path:expr) => {
+        $crate::TableRef::new(include_bytes!($path))
+    };
+}
+
+impl<'a, S: BuildHasher> TableRef<'a, S> {
+    // Query looks up a key as Table::query does.
+    pub fn query<K: Hash + ?Sized>(&self, k: &K) -> usize {
+        let (hash, disp) = self.hasher.hash_pair(k);
+        self.lookup(hash, disp)
+    }
+
+    // QueryBytes looks up a binary key as Table::query_bytes does.
+    pub fn query_bytes<K: AsRef<[u8]> + ?Sized>(&self, k: &K) -> usize {
+        let (hash, disp) = self.hasher.hash_bytes_pair(k.as_ref());
+        self.lookup(hash, disp)
+    }
+
+    // QueryPrehashed looks up a digest as Table::query_prehashed does.
+    pub fn query_prehashed(&self, hash: u64) -> usize {
+        self.lookup(hash, hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Lookup mirrors Table::lookup over the borrowed arrays.
+    fn lookup(&self, hash: u64, disp: u64) -> usize {
+        let seed = le_i32(self.seeds, reduce(hash, self.seeds.len() / 4));
+        if seed < 0 {
+            let i = (-seed - 1) as usize;
+            return if self.values.is_empty() {
+                i
+            } else {
+                le_i32(self.values, i) as usize
+            };
+        }
+
+        let i = reduce(xorshift_mult64(disp.wrapping_add(seed as u64)), self.size);
+        if self.minimal {
+            self.rank(i)
+        } else if self.values.is_empty() {
+            i
+        } else {
+            le_i32(self.values, i) as usize
+        }
+    }
+
+    // Rank counts the occupied slots before pos.
+    fn rank(&self, pos: usize) -> usize {
+        rank_by(pos, |b| le_u32(self.ranks, b), |w| le_u64(self.occupied, w))
+    }
+}
+
+fn le_i32(b: &[u8], i: usize) -> i32 {
+    i32::from_le_bytes(b[i * 4..][..4].try_into().unwrap())
+}
+
+fn le_u32(b: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes(b[i * 4..][..4].try_into().unwrap())
+}
+
+fn le_u64(b: &[u8], i: usize) -> u64 {
+    u64::from_le_bytes(b[i * 8..][..8].try_into().unwrap())
+}
+
+// tri is ? for const fns, where ?'s error conversion can't be called.
+macro_rules! tri {
+    ($e:expr) => {
+        match $e {
+            Ok(x) => x,
+            Err(err) => return Err(err),
+        }
+    };
+}
+
+// Crc32 is the CRC-32 (IEEE) of the bytes passed to update, as computed by
+// zlib and most file formats.
+pub(crate) struct Crc32(u32);
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb8_8320 ^ c >> 1
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+impl Crc32 {
+    pub(crate) fn new() -> Crc32 {
+        Crc32(!0)
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = CRC_TABLE[((self.0 ^ b as u32) & 0xff) as usize] ^ self.0 >> 8;
+        }
+    }
+
+    pub(crate) fn sum(&self) -> u32 {
+        !self.0
+    }
+}
+
+// Header is the fixed-size start of a serialized table.
+pub(crate) struct Header {
+    pub(crate) algo: u8,
+    pub(crate) flags: u8,
+    pub(crate) params: [u64; 2],
+    pub(crate) seed: u64,
+    pub(crate) len: usize,
+    pub(crate) size: usize,
+    pub(crate) nseeds: usize,
+    pub(crate) nvalues: usize,
+    // None for version 1, which had no checksum
+    pub(crate) checksum: Option<u32>,
+    // 0 before version 3, which added the metadata
+    pub(crate) nmeta: usize,
+}
+
+impl Header {
+    // Parse reads and checks a header. It is a const fn for TableRef::new.
+    const fn parse(r: &mut Reader) -> Result<Header, FormatError> {
+        let magic = tri!(r.take(4));
+        let mut i = 0;
+        while i < 4 {
+            if magic[i] != MAGIC[i] {
+                return Err(FormatError::BadMagic);
+            }
+            i += 1;
+        }
+        let version = tri!(r.int(2)) as u16;
+        if version < MIN_VERSION || version > VERSION {
+            return Err(FormatError::UnsupportedVersion(version));
+        }
+        let h = Header {
+            algo: tri!(r.int(1)) as u8,
+            flags: tri!(r.int(1)) as u8,
+            params: [tri!(r.int(8)), tri!(r.int(8))],
+            seed: tri!(r.int(8)),
+            len: tri!(r.len()),
+            size: tri!(r.len()),
+            nseeds: tri!(r.len()),
+            nvalues: tri!(r.len()),
+            checksum: if version > 1 {
+                Some(tri!(r.int(4)) as u32)
+            } else {
+                None
+            },
+            nmeta: if version > 2 {
+                tri!(r.int(4)) as usize
+            } else {
+                0
+            },
+        };
+        h.validate()
+    }
+
+    // Validate checks that the header's fields are consistent.
+    pub(crate) const fn validate(self) -> Result<Header, FormatError> {
+        let h = self;
+        let flags = h.flags;
+        let known =
+            FLAG_SECOND_HASH | FLAG_FOLD_CASE | FLAG_MINIMAL | FLAG_SHARED | FLAG_UNICODE_CASE;
+        if flags & !known != 0
+            || flags & FLAG_MINIMAL != 0 && flags & FLAG_SHARED != 0
+            || flags & FLAG_UNICODE_CASE != 0 && flags & FLAG_FOLD_CASE == 0
+        {
+            return Err(FormatError::Invalid("unknown flags"));
+        }
+        if flags & FLAG_UNICODE_CASE != 0 && !cfg!(feature = "unicode-case") {
+            return Err(FormatError::Invalid("unicode case folding"));
+        }
+        if h.size == 0 || h.nseeds == 0 {
+            return Err(FormatError::Invalid("empty arrays"));
+        }
+        let has_values = flags & (FLAG_MINIMAL | FLAG_SHARED) == 0;
+        if h.nvalues != if has_values { h.size } else { 0 } {
+            return Err(FormatError::Invalid("value count"));
+        }
+        if h.len > h.size && flags & FLAG_SHARED == 0 {
+            return Err(FormatError::Invalid("key count"));
+        }
+        Ok(h)
+    }
+
+    const fn minimal(&self) -> bool {
+        self.flags & FLAG_MINIMAL != 0
+    }
+
+    // Words is the length of the occupied bit vector.
+    const fn words(&self) -> usize {
+        if self.minimal() {
+            self.size.div_ceil(64)
+        } else {
+            0
+        }
+    }
+
+    // Samples is the number of rank samples for the occupied bits.
+    const fn samples(&self) -> usize {
+        if self.minimal() {
+            self.words().div_ceil(BLOCK_WORDS) + 1
+        } else {
+            0
+        }
+    }
+
+    fn hasher<S: StoredHasher>(&self) -> Result<KeyHasher<S>, FormatError> {
+        let build = S::from_stored(self.algo, self.params)
+            .ok_or(FormatError::UnknownHashAlgo(self.algo))?;
+        Ok(self.key_hasher(build))
+    }
+
+    // KeyHasher returns a KeyHasher using build with the header's options.
+    const fn key_hasher<S>(&self, build: S) -> KeyHasher<S> {
+        let mut hasher = KeyHasher::new(build);
+        hasher.seed = self.seed;
+        hasher.second_hash = self.flags & FLAG_SECOND_HASH != 0;
+        hasher.fold_case = self.flags & FLAG_FOLD_CASE != 0;
+        hasher.unicode_case = self.flags & FLAG_UNICODE_CASE != 0;
+        hasher
+    }
+
+    // Check verifies the arrays against the header and each other, reading
+    // them through accessors so both encoded and decoded arrays can be
+    // checked.
+    fn check<A, B, C, D>(&self, seed: A, value: B, word: C, sample: D) -> Result<(), FormatError>
+    where
+        A: Fn(usize) -> i32,
+        B: Fn(usize) -> i32,
+        C: Fn(usize) -> u64,
+        D: Fn(usize) -> u32,
+    {
+        // negative seeds index values, or are the final value when there are
+        // none; values are key indices
+        let direct = if self.minimal() { self.len } else { self.size };
+        if (0..self.nseeds).any(|i| {
+            let s = seed(i);
+            s < 0 && (-(s as i64) - 1) as usize >= direct
+        }) {
+            return Err(FormatError::Invalid("seed out of range"));
+        }
+        if (0..self.nvalues).any(|i| {
+            let v = value(i);
+            v < 0 || v as usize >= self.len.max(1)
+        }) {
+            return Err(FormatError::Invalid("value out of range"));
+        }
+
+        // the rank samples must be the running counts of the occupied bits
+        if self.minimal() {
+            let mut rank = 0u32;
+            for i in 0..self.words() {
+                if i % BLOCK_WORDS == 0 && sample(i / BLOCK_WORDS) != rank {
+                    return Err(FormatError::Invalid("rank samples"));
+                }
+                rank += word(i).count_ones();
+            }
+            if sample(self.samples() - 1) != rank || rank as usize != self.len {
+                return Err(FormatError::Invalid("occupied slots"));
+            }
+        }
+        Ok(())
+    }
+}
+
+// HeaderLen is the length of the header of the given version, or of the
+// current one for versions it doesn't know.
+#[cfg(feature = "std")]
+const fn header_len(version: u16) -> usize {
+    match version {
+        1 => V1_HEADER_LEN,
+        2 => V2_HEADER_LEN,
+        _ => HEADER_LEN,
+    }
+}
+
+// Parts is a serialized table split into its header, metadata and encoded
+// arrays.
+struct Parts<'a> {
+    header: Header,
+    // only Table decodes the metadata; TableRef skips it
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    meta: &'a [u8],
+    seeds: &'a [u8],
+    values: &'a [u8],
+    occupied: &'a [u8],
+    ranks: &'a [u8],
+}
+
+impl<'a> Parts<'a> {
+    // Split checks the header and splits off the arrays, without looking at
+    // what they hold.
+    const fn split(bytes: &'a [u8]) -> Result<Parts<'a>, FormatError> {
+        let mut r = Reader { buf: bytes };
+        let header = tri!(Header::parse(&mut r));
+
+        let meta = tri!(r.take(header.nmeta));
+        let seeds = tri!(r.array(header.nseeds, 4));
+        let values = tri!(r.array(header.nvalues, 4));
+        let occupied = tri!(r.array(header.words(), 8));
+        let ranks = tri!(r.array(header.samples(), 4));
+        if !r.buf.is_empty() {
+            return Err(FormatError::Invalid("trailing data"));
+        }
+        Ok(Parts {
+            header,
+            meta,
+            seeds,
+            values,
+            occupied,
+            ranks,
+        })
+    }
+}
+
+// Parse splits bytes into a table's parts and checks them, without
+// allocating.
+fn parse<S: StoredHasher>(bytes: &[u8]) -> Result<Parts<'_>, FormatError> {
+    let p = Parts::split(bytes)?;
+    p.header.hasher::<S>()?;
+
+    if let Some(sum) = p.header.checksum {
+        let mut crc = Crc32::new();
+        crc.update(&bytes[..V1_HEADER_LEN]);
+        crc.update(&bytes[V2_HEADER_LEN..]);
+        if crc.sum() != sum {
+            return Err(FormatError::Corrupted);
+        }
+    }
+
+    p.header.check(
+        |i| le_i32(p.seeds, i),
+        |i| le_i32(p.values, i),
+        |i| le_u64(p.occupied, i),
+        |i| le_u32(p.ranks, i),
+    )?;
+    Ok(p)
+}
+
+pub(crate) struct Reader<'a> {
+    pub(crate) buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) const fn take(&mut self, n: usize) -> Result<&'a [u8], FormatError> {
+        if self.buf.len() < n {
+            return Err(FormatError::Truncated);
+        }
+        let (head, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(head)
+    }
+
+    // Array takes n items of size bytes each. On 32-bit targets a count from
+    // the header can make more bytes than fit in a usize; no data holds that
+    // many, so it is truncated.
+    pub(crate) const fn array(&mut self, n: usize, size: usize) -> Result<&'a [u8], FormatError> {
+        match n.checked_mul(size) {
+            Some(len) => self.take(len),
+            None => Err(FormatError::Truncated),
+        }
+    }
+
+    // Int reads a little-endian integer of n <= 8 bytes.
+    pub(crate) const fn int(&mut self, n: usize) -> Result<u64, FormatError> {
+        let b = tri!(self.take(n));
+        let mut x = 0;
+        let mut i = n;
+        while i > 0 {
+            i -= 1;
+            x = x << 8 | b[i] as u64;
+        }
+        Ok(x)
+    }
+
+    // Len reads a count, as checked by count.
+    pub(crate) const fn len(&mut self) -> Result<usize, FormatError> {
+        count(tri!(self.int(8)))
+    }
+}
+
+// Count checks that n is small enough for the arrays to index with i32s.
+pub(crate) const fn count(n: u64) -> Result<usize, FormatError> {
+    if n > i32::MAX as u64 {
+        return Err(FormatError::Invalid("count too large"));
+    }
+    Ok(n as usize)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::TableBuilder;
+
+    #[test]
+    fn round_trip() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("key{}", i)).collect();
+
+        let tables: Vec<Table> = vec![
+            Table::new(&keys),
+            TableBuilder::new().seed(9).second_hash(true).build(&keys),
+            TableBuilder::new()
+                .minimal(true)
+                .fastrange(true)
+                .build(&keys),
+            TableBuilder::new().k(4).build(&keys),
+            Table::with_hasher(&keys, SipState::new_with_keys(1, 2)),
+        ];
+        for t in &tables {
+            let bytes = t.to_bytes();
+            let mut streamed = Vec::new();
+            t.write_to(&mut streamed).unwrap();
+            assert_eq!(streamed, bytes);
+            let s: Table = Table::read_from(&bytes[..]).unwrap();
+            assert_eq!(s.query("key1"), t.query("key1"));
+
+            let u: Table = Table::from_bytes(&bytes).unwrap();
+            let r: TableRef = TableRef::from_bytes(&bytes).unwrap();
+            for k in &keys {
+                assert_eq!(u.query(k), t.query(k));
+                assert_eq!(r.query(k), t.query(k));
+            }
+        }
+
+        let t = TableBuilder::new().hash_algo(HashAlgo::Fx).build(&keys);
+        let u = Table::<HashAlgo>::from_bytes(&t.to_bytes()).unwrap();
+        assert_eq!(u.query("key5"), 5);
+        assert_eq!(
+            Table::<SipState>::from_bytes(&t.to_bytes()).err(),
+            Some(FormatError::UnknownHashAlgo(1))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let t = Table::new(&["a", "b", "c"]);
+        let bytes = t.to_bytes();
+
+        let load = |b: &[u8]| Table::<SipState>::from_bytes(b).err();
+        let read = |b: &[u8]| Table::<SipState>::read_from(b).err().unwrap().kind();
+        assert_eq!(load(b"nope"), Some(FormatError::BadMagic));
+        assert_eq!(
+            load(&bytes[..bytes.len() - 1]),
+            Some(FormatError::Truncated)
+        );
+        assert_eq!(
+            read(&bytes[..bytes.len() - 1]),
+            io::ErrorKind::UnexpectedEof
+        );
+
+        for v in [0, 4, 9] {
+            let mut b = bytes.clone();
+            b[4] = v;
+            assert_eq!(load(&b), Some(FormatError::UnsupportedVersion(v as u16)));
+            assert_eq!(read(&b), io::ErrorKind::InvalidData);
+        }
+
+        let mut b = bytes.clone();
+        b.push(0);
+        assert_eq!(load(&b), Some(FormatError::Invalid("trailing data")));
+        assert_eq!(read(&b), io::ErrorKind::InvalidData);
+
+        // a flipped bit in the arrays
+        let mut b = bytes.clone();
+        b[HEADER_LEN] ^= 4;
+        assert_eq!(load(&b), Some(FormatError::Corrupted));
+        assert_eq!(read(&b), io::ErrorKind::InvalidData);
+
+        // a value pointing past the keys, with a fixed up checksum
+        let mut b = bytes.clone();
+        let n = b.len();
+        b[n - 4..].copy_from_slice(&7i32.to_le_bytes());
+        let mut crc = Crc32::new();
+        crc.update(&b[..V1_HEADER_LEN]);
+        crc.update(&b[V2_HEADER_LEN..]);
+        b[V1_HEADER_LEN..V2_HEADER_LEN].copy_from_slice(&crc.sum().to_le_bytes());
+        assert_eq!(load(&b), Some(FormatError::Invalid("value out of range")));
+
+        let t = TableBuilder::new()
+            .normalize(|s| s.trim().into())
+            .build(&["a", "b", "c"]);
+        let err = t.write_to(io::sink()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        // str::to_lowercase folding, which only builds with the unicode-case
+        // feature reproduce, and the flag for it without case folding
+        let mut b = TableBuilder::new()
+            .case_insensitive(true)
+            .build(&["a", "b", "c"])
+            .to_bytes();
+        assert_eq!(
+            b[7] & FLAG_UNICODE_CASE != 0,
+            cfg!(feature = "unicode-case")
+        );
+        b[7] |= FLAG_UNICODE_CASE;
+        assert_eq!(
+            load(&b),
+            (!cfg!(feature = "unicode-case"))
+                .then_some(FormatError::Invalid("unicode case folding"))
+        );
+        let mut b = bytes.clone();
+        b[7] |= FLAG_UNICODE_CASE;
+        assert_eq!(load(&b), Some(FormatError::Invalid("unknown flags")));
+    }
+
+    // GOLDEN_STR and GOLDEN_INT are tables over ["a", "b", "c"] and, minimal,
+    // over [10u32, 20, 30]. They were written on x86-64, and must load and
+    // query the same on any target.
+    const GOLDEN_STR: [u8; 122] = [
+        b'M', b'P', b'H', b'T', 3, 0, 0, 0, // magic, version, algo, flags
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // params
+        0, 0, 0, 0, 0, 0, 0, 0, // seed
+        3, 0, 0, 0, 0, 0, 0, 0, // len
+        4, 0, 0, 0, 0, 0, 0, 0, // size
+        4, 0, 0, 0, 0, 0, 0, 0, // nseeds
+        4, 0, 0, 0, 0, 0, 0, 0, // nvalues
+        95, 95, 144, 115, // checksum
+        18, 0, 0, 0, // nmeta
+        5, b'0', b'.', b'1', b'.', b'0', // builder
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // built_at, comment
+        252, 255, 255, 255, 0, 0, 0, 0, 253, 255, 255, 255, 254, 255, 255, 255, // seeds
+        0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, // values
+    ];
+
+    const GOLDEN_INT: [u8; 122] = [
+        b'M', b'P', b'H', b'T', 3, 0, 0, 4, // magic, version, algo, flags
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // params
+        0, 0, 0, 0, 0, 0, 0, 0, // seed
+        3, 0, 0, 0, 0, 0, 0, 0, // len
+        4, 0, 0, 0, 0, 0, 0, 0, // size
+        4, 0, 0, 0, 0, 0, 0, 0, // nseeds
+        0, 0, 0, 0, 0, 0, 0, 0, // nvalues
+        127, 149, 101, 33, // checksum
+        18, 0, 0, 0, // nmeta
+        5, b'0', b'.', b'1', b'.', b'0', // builder
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // built_at, comment
+        253, 255, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, // seeds
+        14, 0, 0, 0, 0, 0, 0, 0, // occupied
+        0, 0, 0, 0, 3, 0, 0, 0, // ranks
+    ];
+
+    // Older rewrites a golden table in an earlier version of the format.
+    fn older(golden: &[u8], version: u8) -> Vec<u8> {
+        let mut b = golden.to_vec();
+        b[4] = version;
+        b.drain(header_len(version as u16)..HEADER_LEN + 18);
+        if version == 2 {
+            let mut crc = Crc32::new();
+            crc.update(&b[..V1_HEADER_LEN]);
+            crc.update(&b[V2_HEADER_LEN..]);
+            b[V1_HEADER_LEN..V2_HEADER_LEN].copy_from_slice(&crc.sum().to_le_bytes());
+        }
+        b
+    }
+
+    #[test]
+    fn old_versions() {
+        for golden in [&GOLDEN_STR, &GOLDEN_INT] {
+            let want = Table::<SipState>::from_bytes(golden).unwrap();
+            for version in [1, 2] {
+                let mut b = older(golden, version);
+                let u = Table::<SipState>::from_bytes(&b).unwrap();
+                let v = Table::<SipState>::read_from(&b[..]).unwrap();
+                let r = TableRef::<SipState>::from_bytes(&b).unwrap();
+                assert_eq!(u.metadata().builder_version, None);
+                assert_eq!(u.to_bytes(), v.to_bytes());
+                assert_eq!(u.seeds, want.seeds);
+                assert_eq!(u.values, want.values);
+                assert_eq!(r.len(), 3);
+
+                // checked for consistency, even in version 1 without a
+                // checksum
+                let n = b.len();
+                b[n - 4..].copy_from_slice(&[0xff; 4]);
+                assert!(Table::<SipState>::from_bytes(&b).is_err());
+                assert!(Table::<SipState>::read_from(&b[..]).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn golden() {
+        // the builder version is pinned so releases don't change the bytes
+        let mut t = Table::new(&["a", "b", "c"]);
+        t.info.version = "0.1.0".to_string();
+        assert_eq!(t.to_bytes(), GOLDEN_STR);
+        let u = Table::<SipState>::from_bytes(&GOLDEN_STR).unwrap();
+        let r = TableRef::<SipState>::from_bytes(&GOLDEN_STR).unwrap();
+        for (i, k) in ["a", "b", "c"].iter().enumerate() {
+            assert_eq!(u.query(k), i);
+            assert_eq!(r.query(k), i);
+        }
+
+        let keys = [10u32, 20, 30];
+        let mut t = TableBuilder::new().minimal(true).build(&keys);
+        t.info.version = "0.1.0".to_string();
+        assert_eq!(t.to_bytes(), GOLDEN_INT);
+        let u = Table::<SipState>::from_bytes(&GOLDEN_INT).unwrap();
+        let r = TableRef::<SipState>::from_bytes(&GOLDEN_INT).unwrap();
+        for k in &keys {
+            assert_eq!(u.query(k), t.query(k));
+            assert_eq!(r.query(k), t.query(k));
+        }
+    }
+
+    #[test]
+    fn save_load() {
+        let dir = std::env::temp_dir().join(format!("mph-rs-save-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("t.mph");
+
+        let keys: Vec<String> = (0..1000).map(|i| format!("key{}", i)).collect();
+        Table::new(&keys[..10]).save(&path).unwrap();
+        let t = Table::new(&keys);
+        t.save(&path).unwrap();
+        assert_eq!(t.serialized_len(), t.to_bytes().len());
+
+        let u = Table::<SipState>::load(&path).unwrap();
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(u.query(k), i);
+        }
+        // the temporary file is gone
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::write(&path, [0u8; 100]).unwrap();
+        let err = Table::<SipState>::load(&path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(Table::new(&keys).save(dir.join("missing/t.mph")).is_err());
+
+        // concurrent saves each write a file of their own, and one of them
+        // ends up at path
+        let tables: Vec<Table> = (1..=8).map(|n| Table::new(&keys[..n * 100])).collect();
+        std::thread::scope(|s| {
+            for t in &tables {
+                let path = &path;
+                s.spawn(move || t.save(path).unwrap());
+            }
+        });
+        let u = Table::<SipState>::load(&path).unwrap();
+        assert!(tables.iter().any(|t| t.to_bytes() == u.to_bytes()));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Mutations loads every truncation of the golden tables, and every one
+    // with a byte changed, in each way that checks the data. None may panic,
+    // and neither may queries on those that load.
+    #[test]
+    fn mutations() {
+        let keys: Vec<String> = (0..100).map(|i| i.to_string()).collect();
+        let try_load = |b: &[u8]| {
+            if let Ok(t) = Table::<SipState>::from_bytes(b) {
+                keys.iter().for_each(|k| {
+                    t.query(k);
+                });
+            }
+            if let Ok(t) = Table::<SipState>::read_from(b) {
+                keys.iter().for_each(|k| {
+                    t.query(k);
+                });
+            }
+            if let Ok(r) = TableRef::<SipState>::from_bytes(b) {
+                keys.iter().for_each(|k| {
+                    r.query(k);
+                });
+            }
+        };
+
+        for golden in [&GOLDEN_STR[..], &GOLDEN_INT[..], &older(&GOLDEN_STR, 1)] {
+            for n in 0..golden.len() {
+                try_load(&golden[..n]);
+            }
+            for i in 0..golden.len() {
+                for x in [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0xff] {
+                    let mut b = golden.to_vec();
+                    b[i] ^= x;
+                    try_load(&b);
+                }
+            }
+        }
+
+        // counts whose byte lengths overflow a 32-bit usize
+        let mut b = GOLDEN_STR.to_vec();
+        for at in [40, 48, 56] {
+            // size, nseeds, nvalues
+            b[at..at + 8].copy_from_slice(&(i32::MAX as u64).to_le_bytes());
+        }
+        assert_eq!(
+            Table::<SipState>::from_bytes(&b).err(),
+            Some(FormatError::Truncated)
+        );
+        assert!(Table::<SipState>::read_from(&b[..]).is_err());
+    }
+
+    #[test]
+    fn unverified() {
+        let mut b = GOLDEN_STR;
+        let n = b.len();
+        b[n - 12] = 1;
+        assert_eq!(
+            TableRef::<SipState>::from_bytes(&b).err(),
+            Some(FormatError::Corrupted)
+        );
+        let r = TableRef::<SipState>::from_bytes_unverified(&b).unwrap();
+        assert_eq!(r.query("c"), 2);
+        assert_eq!(
+            TableRef::<SipState>::from_bytes_unverified(&b[..110]).err(),
+            Some(FormatError::Truncated)
+        );
+    }
+
+    #[test]
+    fn const_new() {
+        static T: TableRef = TableRef::new(&GOLDEN_STR);
+        static U: TableRef = crate::include_table!("testdata/abc.mph");
+        for (i, k) in ["a", "b", "c"].iter().enumerate() {
+            assert_eq!(T.query(k), i);
+            assert_eq!(U.query(k), i);
+        }
+    }
+
+    #[test]
+    fn crc32() {
+        let mut crc = Crc32::new();
+        crc.update(b"123456789");
+        assert_eq!(crc.sum(), 0xcbf4_3926);
+    }
+}
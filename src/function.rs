@@ -0,0 +1,161 @@
+use std::hash::BuildHasher;
+
+use crate::{bbhash, pthash, recsplit, BdzTable, Table};
+
+// MphFunction is the query side shared by every backend, so code holding one
+// needn't care which algorithm built it. Keys are hashed as [u8], the way
+// Vec<u8> and &[u8] keys are, so a table used through the trait must have
+// been built over byte keys; Algorithm::build takes care of that.
+//
+// Table returns the key's index in the input; the other backends return some
+// unique value in 0..len().
+pub trait MphFunction {
+    fn query(&self, key: &[u8]) -> u64;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // BitsPerKey reports the space the structure takes per key.
+    fn bits_per_key(&self) -> f64;
+}
+
+// DynMph is a table whose backend is chosen at run time.
+pub type DynMph = Box<dyn MphFunction + Send + Sync>;
+
+// Algorithm names a backend for DynMph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    // Table: hash and displace, fastest to query
+    Displacement,
+    // BdzTable
+    Bdz,
+    // bbhash::Table
+    BBHash,
+    // recsplit::Table, smallest
+    RecSplit,
+    // pthash::Table
+    PTHash,
+}
+
+// Preference steers Algorithm::choose between query speed and space.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Preference {
+    #[default]
+    Balanced,
+    SpeedOptimized,
+    SpaceOptimized,
+}
+
+// Below this many keys Table's larger footprint is cheap enough to spend on
+// its faster queries.
+const SMALL: usize = 100_000;
+
+impl Algorithm {
+    // Choose picks a backend for n keys. Table is the fastest to query but
+    // takes 32 bits per slot and seed, which is right for small sets or when
+    // speed is all that matters; past that PTHash keeps queries to a hash and
+    // one or two reads at about 4 bits per key. SpaceOptimized always picks
+    // RecSplit, at under 2.5 bits per key but slower queries.
+    pub fn choose(n: usize, pref: Preference) -> Algorithm {
+        match pref {
+            Preference::SpaceOptimized => Algorithm::RecSplit,
+            Preference::SpeedOptimized => Algorithm::Displacement,
+            Preference::Balanced if n < SMALL => Algorithm::Displacement,
+            Preference::Balanced => Algorithm::PTHash,
+        }
+    }
+
+    // Build constructs a table over byte keys with this algorithm's defaults.
+    pub fn build<K: AsRef<[u8]>>(self, keys: &[K]) -> DynMph {
+        let keys: Vec<&[u8]> = keys.iter().map(|k| k.as_ref()).collect();
+        match self {
+            Algorithm::Displacement => Box::new(Table::new(&keys)),
+            Algorithm::Bdz => Box::new(BdzTable::new(&keys)),
+            Algorithm::BBHash => Box::new(bbhash::Table::new(&keys)),
+            Algorithm::RecSplit => Box::new(recsplit::Table::new(&keys)),
+            Algorithm::PTHash => Box::new(pthash::Table::new(&keys)),
+        }
+    }
+}
+
+// forward implements MphFunction for a backend through its inherent methods.
+macro_rules! forward {
+    ($($t:ty),*) => {$(
+        impl<S: BuildHasher> MphFunction for $t {
+            fn query(&self, key: &[u8]) -> u64 {
+                <$t>::query(self, key) as u64
+            }
+
+            fn len(&self) -> usize {
+                <$t>::len(self)
+            }
+
+            fn bits_per_key(&self) -> f64 {
+                <$t>::bits_per_key(self)
+            }
+        }
+    )*};
+}
+
+forward!(
+    Table<S>,
+    BdzTable<S>,
+    bbhash::Table<S>,
+    recsplit::Table<S>,
+    pthash::Table<S>
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn algorithms() {
+        let keys: Vec<String> = (0..2000).map(|i| format!("key{}", i)).collect();
+
+        for &algo in &[
+            Algorithm::Displacement,
+            Algorithm::Bdz,
+            Algorithm::BBHash,
+            Algorithm::RecSplit,
+            Algorithm::PTHash,
+        ] {
+            let t = algo.build(&keys);
+            assert_eq!(t.len(), keys.len());
+            assert!(t.bits_per_key() > 0.0);
+
+            let mut seen = vec![false; keys.len()];
+            for k in &keys {
+                let v = t.query(k.as_bytes()) as usize;
+                assert!(!seen[v], "{:?}: duplicate value {}", algo, v);
+                seen[v] = true;
+            }
+        }
+
+        let t = Algorithm::Displacement.build(&keys);
+        assert_eq!(t.query(b"key7"), 7);
+    }
+
+    #[test]
+    fn auto() {
+        assert_eq!(
+            Algorithm::choose(1000, Preference::Balanced),
+            Algorithm::Displacement
+        );
+        assert_eq!(
+            Algorithm::choose(10_000_000, Preference::Balanced),
+            Algorithm::PTHash
+        );
+        assert_eq!(
+            Algorithm::choose(1000, Preference::SpaceOptimized),
+            Algorithm::RecSplit
+        );
+
+        let keys = ["a", "b", "c"];
+        let t = Table::auto(&keys, Preference::default());
+        assert_eq!(t.query(b"c"), 2);
+    }
+}
@@ -0,0 +1,211 @@
+// go-mph interop. A go-mph Table is two int32 arrays of the same power-of-two
+// length, values and seeds, over keys hashed with metro64 and seed 0. Its
+// bucket and slot arithmetic is Table's, so it loads as a Table with
+// HashAlgo::Metro64 and answers as go-mph's Query does, through query_bytes.
+//
+// go-mph has no file format of its own. The arrays travel as a Go program
+// writes them with binary.Write(w, binary.LittleEndian, values) and then the
+// same for seeds: two little-endian int32 arrays, back to back.
+
+use std::io::{self, Read, Write};
+
+use crate::format::{assemble, count, FormatError, Header};
+use crate::hash::{HashAlgo, KeyHasher};
+use crate::metadata::BuildInfo;
+use crate::{Params, Table};
+
+impl Table<HashAlgo> {
+    // GoMph builds a table over binary keys that go-mph can query, given the
+    // arrays write_go_mph writes. In the rare build that has to grow the
+    // table, the seeds no longer match the values in length and
+    // write_go_mph refuses it.
+    pub fn go_mph<K: AsRef<[u8]>>(keys: &[K]) -> Table<HashAlgo> {
+        let hasher = KeyHasher::new(HashAlgo::Metro64);
+        let hashes: Vec<u64> = keys.iter().map(|k| hasher.hash_bytes(k.as_ref())).collect();
+        Table::build(&hashes, None, hasher, &Params::default())
+    }
+
+    // FromGoMph loads go-mph's values and seeds arrays, checking them as
+    // from_bytes does. go-mph doesn't keep its key count, so it is taken
+    // from the largest value.
+    pub fn from_go_mph(values: Vec<i32>, seeds: Vec<i32>) -> Result<Table<HashAlgo>, FormatError> {
+        if !values.len().is_power_of_two() || seeds.len() != values.len() {
+            return Err(FormatError::Invalid("go-mph array lengths"));
+        }
+        let len = values.iter().max().map_or(0, |&v| v as i64 + 1);
+        let header = Header {
+            algo: HashAlgo::Metro64.id(),
+            flags: 0,
+            params: [0, 0],
+            seed: 0,
+            len: count(len.max(0) as u64)?,
+            size: values.len(),
+            nseeds: seeds.len(),
+            nvalues: values.len(),
+            checksum: None,
+            nmeta: 0,
+        }
+        .validate()?;
+        assemble(
+            &header,
+            seeds,
+            values,
+            Vec::new(),
+            Vec::new(),
+            BuildInfo::new(),
+        )
+    }
+
+    // ReadGoMph loads the values and seeds arrays a Go program wrote to r.
+    pub fn read_go_mph<R: Read>(mut r: R) -> io::Result<Table<HashAlgo>> {
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf)?;
+        if buf.len() % 8 != 0 {
+            return Err(FormatError::Truncated.into());
+        }
+        let ints: Vec<i32> = buf
+            .chunks_exact(4)
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        let (values, seeds) = ints.split_at(ints.len() / 2);
+        Ok(Table::from_go_mph(values.to_vec(), seeds.to_vec())?)
+    }
+
+    // WriteGoMph writes the values and seeds arrays in the order read_go_mph
+    // reads them, for a Go program to read into a go-mph Table. Only tables
+    // laid out as go-mph's are can be written: built by go_mph or loaded with
+    // from_go_mph.
+    pub fn write_go_mph<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let h = &self.hasher;
+        if h.build != HashAlgo::Metro64
+            || h.seed != 0
+            || h.second_hash
+            || h.fold_case
+            || h.normalize.is_some()
+            || self.occupied.is_some()
+            || self.values.len() != self.size
+            || self.seeds.len() != self.size
+            || !self.size.is_power_of_two()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "table isn't laid out as go-mph's",
+            ));
+        }
+        for x in self.values.iter().chain(&self.seeds) {
+            w.write_all(&x.to_le_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::{BuildHasher, Hasher};
+    use std::io;
+
+    use crate::hash::HashAlgo;
+    use crate::{xorshift_mult64, FormatError, Table, TableBuilder};
+
+    // GoNew is go-mph's New, line for line, returning its values and seeds.
+    fn go_new(keys: &[&str]) -> (Vec<i32>, Vec<i32>) {
+        let metro = |k: &str| {
+            let mut h = HashAlgo::Metro64.build_hasher();
+            h.write(k.as_bytes());
+            h.finish()
+        };
+        let size = keys.len().next_power_of_two() as u64;
+        let mut h: Vec<Vec<(i32, u64)>> = vec![Vec::new(); size as usize];
+        for (idx, k) in keys.iter().enumerate() {
+            let hash = metro(k);
+            h[(hash & (size - 1)) as usize].push((idx as i32, hash));
+        }
+        h.sort_by_key(|b| std::cmp::Reverse(b.len()));
+
+        let mut values = vec![0i32; size as usize];
+        let mut seeds = vec![0i32; size as usize];
+        let mut hidx = 0;
+        while hidx < h.len() && h[hidx].len() > 1 {
+            let subkeys = &h[hidx];
+            let mut seed = 0u64;
+            let mut entries: Vec<(usize, i32)> = Vec::new();
+            'newseed: loop {
+                seed += 1;
+                entries.clear();
+                for &(idx, hash) in subkeys {
+                    let i = (xorshift_mult64(hash + seed) & (size - 1)) as usize;
+                    if values[i] != 0 || entries.iter().any(|&(j, _)| j == i) {
+                        continue 'newseed;
+                    }
+                    entries.push((i, idx + 1));
+                }
+                break;
+            }
+            for &(i, v) in &entries {
+                values[i] = v;
+            }
+            seeds[(subkeys[0].1 & (size - 1)) as usize] = seed as i32;
+            hidx += 1;
+        }
+
+        let mut free = Vec::new();
+        for (i, v) in values.iter_mut().enumerate() {
+            if *v == 0 {
+                free.push(i);
+            } else {
+                *v -= 1;
+            }
+        }
+        let mut free = free.into_iter();
+        while hidx < h.len() && !h[hidx].is_empty() {
+            let (idx, hash) = h[hidx][0];
+            hidx += 1;
+            let dst = free.next().unwrap();
+            values[dst] = idx;
+            seeds[(hash & (size - 1)) as usize] = -(dst as i32 + 1);
+        }
+        (values, seeds)
+    }
+
+    #[test]
+    fn go_mph() {
+        let names: Vec<String> = (0..1000).map(|i| format!("key{}", i)).collect();
+        let keys: Vec<&str> = names.iter().map(|k| k.as_str()).collect();
+
+        // a table from go-mph, through the bytes a Go program would write
+        let (values, seeds) = go_new(&keys);
+        let mut bytes = Vec::new();
+        for x in values.iter().chain(&seeds) {
+            bytes.extend_from_slice(&x.to_le_bytes());
+        }
+        let t = Table::read_go_mph(&bytes[..]).unwrap();
+        assert_eq!(t.len(), keys.len());
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query_bytes(k), i);
+        }
+        let mut out = Vec::new();
+        t.write_go_mph(&mut out).unwrap();
+        assert_eq!(out, bytes);
+
+        // and one for go-mph, queried as its Query would
+        let t = Table::go_mph(&keys);
+        let mut out = Vec::new();
+        t.write_go_mph(&mut out).unwrap();
+        let u = Table::read_go_mph(&out[..]).unwrap();
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(u.query_bytes(k), i);
+        }
+
+        assert_eq!(
+            Table::from_go_mph(vec![0; 3], vec![0; 3]).err(),
+            Some(FormatError::Invalid("go-mph array lengths"))
+        );
+        assert_eq!(
+            Table::from_go_mph(vec![0, 1], vec![-3, 0]).err(),
+            Some(FormatError::Invalid("seed out of range"))
+        );
+        let fx = TableBuilder::new().hash_algo(HashAlgo::Fx).build(&keys);
+        let err = fx.write_go_mph(io::sink()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,1108 @@
+use core::convert::TryInto;
+use core::hash::{BuildHasher, Hash, Hasher};
+#[cfg(feature = "std")]
+use std::borrow::Cow;
+#[cfg(feature = "std")]
+use std::collections::hash_map::RandomState;
+#[cfg(feature = "std")]
+use std::str;
+
+// DefaultState builds the hasher tables use unless told otherwise: SipHash-1-3
+// with zero keys. This is what std's DefaultHasher::new() computes today, but
+// std makes no promise to keep it that way, so the crate carries its own copy
+// whose output will not change between releases. Tables that are persisted or
+// generated ahead of time stay valid across toolchain upgrades.
+//
+// Only the hash function is pinned: the byte stream a key feeds it comes from
+// the key's Hash impl. Byte-string keys (from_bytes_keys, query_bytes) are
+// fully specified by this crate, and integers are hashed as their
+// little-endian bytes; for str and other std types the stream is an
+// implementation detail of std, if a very stable one. schema/table.fbs spells
+// out the streams and the lookup byte for byte.
+pub type DefaultState = SipState;
+
+// Normalizer rewrites string keys before they are hashed.
+#[cfg(feature = "std")]
+pub type Normalizer = fn(&str) -> Cow<str>;
+
+// SipState builds keyed SipHash-1-3 hashers. With a secret random key the
+// hash values are unpredictable, so untrusted keys can't be chosen to pile up
+// in a few buckets and stall construction. The key is kept in the table, so
+// queries stay consistent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+pub struct SipState {
+    k0: u64,
+    k1: u64,
+}
+
+impl Default for SipState {
+    fn default() -> SipState {
+        SipState::new_with_keys(0, 0)
+    }
+}
+
+impl SipState {
+    pub const fn new_with_keys(k0: u64, k1: u64) -> SipState {
+        SipState { k0, k1 }
+    }
+
+    // Random draws a fresh 128-bit key from the same source as std's
+    // RandomState.
+    #[cfg(feature = "std")]
+    pub fn random() -> SipState {
+        let s = RandomState::new();
+        let k0 = s.hash_one(0u8);
+        let k1 = s.hash_one(1u8);
+        SipState { k0, k1 }
+    }
+
+    pub fn keys(&self) -> (u64, u64) {
+        (self.k0, self.k1)
+    }
+}
+
+// random_seed returns a fresh nonzero seed from std's OS-backed randomness.
+#[cfg(feature = "std")]
+pub(crate) fn random_seed() -> u64 {
+    loop {
+        let (k0, _) = SipState::random().keys();
+        if k0 != 0 {
+            return k0;
+        }
+    }
+}
+
+impl BuildHasher for SipState {
+    type Hasher = SipHasher13;
+
+    fn build_hasher(&self) -> SipHasher13 {
+        SipHasher13::new_with_keys(self.k0, self.k1)
+    }
+}
+
+// SipHasher13 is SipHash with one compression and three finalization rounds,
+// the variant std uses for HashMap.
+#[derive(Clone, Debug)]
+pub struct SipHasher13 {
+    v0: u64,
+    v1: u64,
+    v2: u64,
+    v3: u64,
+    tail: u64,
+    ntail: usize,
+    length: usize,
+}
+
+impl SipHasher13 {
+    pub fn new_with_keys(k0: u64, k1: u64) -> SipHasher13 {
+        SipHasher13 {
+            v0: k0 ^ 0x736f_6d65_7073_6575,
+            v1: k1 ^ 0x646f_7261_6e64_6f6d,
+            v2: k0 ^ 0x6c79_6765_6e65_7261,
+            v3: k1 ^ 0x7465_6462_7974_6573,
+            tail: 0,
+            ntail: 0,
+            length: 0,
+        }
+    }
+
+    fn round(&mut self) {
+        self.v0 = self.v0.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(13);
+        self.v1 ^= self.v0;
+        self.v0 = self.v0.rotate_left(32);
+        self.v2 = self.v2.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(16);
+        self.v3 ^= self.v2;
+        self.v0 = self.v0.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(21);
+        self.v3 ^= self.v0;
+        self.v2 = self.v2.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(17);
+        self.v1 ^= self.v2;
+        self.v2 = self.v2.rotate_left(32);
+    }
+
+    // FromState returns a hasher in the given state, as State for the simd
+    // lanes, with nothing written since the last word.
+    #[cfg(feature = "simd")]
+    pub(crate) fn from_state([v0, v1, v2, v3]: [u64; 4]) -> SipHasher13 {
+        SipHasher13 {
+            v0,
+            v1,
+            v2,
+            v3,
+            tail: 0,
+            ntail: 0,
+            length: 0,
+        }
+    }
+
+    #[cfg(feature = "simd")]
+    pub(crate) fn state(&self) -> [u64; 4] {
+        [self.v0, self.v1, self.v2, self.v3]
+    }
+
+    pub(crate) fn compress(&mut self, m: u64) {
+        self.v3 ^= m;
+        self.round();
+        self.v0 ^= m;
+    }
+}
+
+macro_rules! le_ints {
+    ($($name:ident: $ty:ty $(as $wide:ty)?),*) => {
+        $(fn $name(&mut self, i: $ty) {
+            self.write(&(i $(as $wide)?).to_le_bytes())
+        })*
+    };
+}
+
+impl Hasher for SipHasher13 {
+    fn write(&mut self, msg: &[u8]) {
+        self.length += msg.len();
+
+        let mut i = 0;
+        if self.ntail != 0 {
+            let fill = (8 - self.ntail).min(msg.len());
+            self.tail |= load_le(&msg[..fill]) << (8 * self.ntail);
+            if self.ntail + fill < 8 {
+                self.ntail += fill;
+                return;
+            }
+            let m = self.tail;
+            self.compress(m);
+            self.ntail = 0;
+            i = fill;
+        }
+
+        let mut words = msg[i..].chunks_exact(8);
+        for w in &mut words {
+            self.compress(load_le(w));
+        }
+
+        let rest = words.remainder();
+        self.tail = load_le(rest);
+        self.ntail = rest.len();
+    }
+
+    // Integers are hashed as their little-endian bytes, and usize and isize
+    // as 64 bits, so keys hash the same on every target.
+    le_ints!(
+        write_u16: u16, write_u32: u32, write_u64: u64, write_u128: u128,
+        write_i16: i16, write_i32: i32, write_i64: i64, write_i128: i128,
+        write_usize: usize as u64, write_isize: isize as i64
+    );
+
+    fn finish(&self) -> u64 {
+        let mut s = self.clone();
+        let b = ((s.length as u64 & 0xff) << 56) | s.tail;
+        s.compress(b);
+        s.v2 ^= 0xff;
+        s.round();
+        s.round();
+        s.round();
+        s.v0 ^ s.v1 ^ s.v2 ^ s.v3
+    }
+}
+
+// load_le reads up to 8 bytes as a little-endian integer.
+fn load_le(b: &[u8]) -> u64 {
+    let mut buf = [0u8; 8];
+    buf[..b.len()].copy_from_slice(b);
+    u64::from_le_bytes(buf)
+}
+
+// LittleEndian wraps one of the external hashers, which would write integers
+// in native byte order, so that integers go in as their little-endian bytes
+// and usize and isize as 64 bits, as with the crate's own hashers. Tables over
+// integer keys then hash the same on every target, and Xxh3State and WyState
+// can share their ids with HashAlgo::Xxh3 and HashAlgo::WyHash.
+#[cfg(any(feature = "xxhash", feature = "wyhash"))]
+#[derive(Clone, Debug, Default)]
+pub struct LittleEndian<H>(H);
+
+#[cfg(any(feature = "xxhash", feature = "wyhash"))]
+impl<H: Hasher> Hasher for LittleEndian<H> {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.write(bytes)
+    }
+
+    le_ints!(
+        write_u16: u16, write_u32: u32, write_u64: u64, write_u128: u128,
+        write_i16: i16, write_i32: i32, write_i64: i64, write_i128: i128,
+        write_usize: usize as u64, write_isize: isize as i64
+    );
+
+    fn finish(&self) -> u64 {
+        self.0.finish()
+    }
+}
+
+// Xxh3State builds xxh3 hashers, which are several times faster than SipHash
+// on long string keys but make no attempt to resist hash flooding.
+#[cfg(feature = "xxhash")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Xxh3State {
+    seed: u64,
+}
+
+#[cfg(feature = "xxhash")]
+impl Xxh3State {
+    pub fn with_seed(seed: u64) -> Xxh3State {
+        Xxh3State { seed }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+#[cfg(feature = "xxhash")]
+impl BuildHasher for Xxh3State {
+    type Hasher = LittleEndian<xxhash_rust::xxh3::Xxh3>;
+
+    fn build_hasher(&self) -> LittleEndian<xxhash_rust::xxh3::Xxh3> {
+        LittleEndian(xxhash_rust::xxh3::Xxh3::with_seed(self.seed))
+    }
+}
+
+// WyState builds wyhash hashers. For the short keys typical of keyword and
+// enum-like tables, wyhash is roughly twice as fast as SipHash.
+#[cfg(feature = "wyhash")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WyState {
+    seed: u64,
+}
+
+#[cfg(feature = "wyhash")]
+impl WyState {
+    pub fn with_seed(seed: u64) -> WyState {
+        WyState { seed }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+#[cfg(feature = "wyhash")]
+impl BuildHasher for WyState {
+    type Hasher = LittleEndian<wyhash::WyHash>;
+
+    fn build_hasher(&self) -> LittleEndian<wyhash::WyHash> {
+        LittleEndian(wyhash::WyHash::with_seed(self.seed))
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl crate::archive::ArchivedHasher for ArchivedSipState {
+    type Hasher = SipState;
+
+    fn hasher(&self) -> SipState {
+        SipState::new_with_keys(self.k0.to_native(), self.k1.to_native())
+    }
+}
+
+#[cfg(feature = "rkyv")]
+impl crate::archive::ArchivedHasher for ArchivedHashAlgo {
+    type Hasher = HashAlgo;
+
+    fn hasher(&self) -> HashAlgo {
+        match self {
+            ArchivedHashAlgo::Sip13 => HashAlgo::Sip13,
+            ArchivedHashAlgo::Fx => HashAlgo::Fx,
+            #[cfg(feature = "xxhash")]
+            ArchivedHashAlgo::Xxh3 => HashAlgo::Xxh3,
+            #[cfg(feature = "wyhash")]
+            ArchivedHashAlgo::WyHash => HashAlgo::WyHash,
+            ArchivedHashAlgo::Metro64 => HashAlgo::Metro64,
+        }
+    }
+}
+
+// StoredHasher is implemented by the hash builders a serialized table can
+// record: an algorithm id, as in HashAlgo::id, and up to two 64-bit
+// parameters such as keys or a seed.
+pub trait StoredHasher: BuildHasher + Sized {
+    fn algo_id(&self) -> u8;
+
+    fn params(&self) -> [u64; 2];
+
+    // FromStored rebuilds the hash builder, or returns None if id and params
+    // don't describe one of this type.
+    fn from_stored(id: u8, params: [u64; 2]) -> Option<Self>;
+}
+
+impl StoredHasher for SipState {
+    fn algo_id(&self) -> u8 {
+        HashAlgo::Sip13.id()
+    }
+
+    fn params(&self) -> [u64; 2] {
+        [self.k0, self.k1]
+    }
+
+    fn from_stored(id: u8, params: [u64; 2]) -> Option<SipState> {
+        (id == HashAlgo::Sip13.id()).then(|| SipState::new_with_keys(params[0], params[1]))
+    }
+}
+
+#[cfg(feature = "xxhash")]
+impl StoredHasher for Xxh3State {
+    fn algo_id(&self) -> u8 {
+        HashAlgo::Xxh3.id()
+    }
+
+    fn params(&self) -> [u64; 2] {
+        [self.seed, 0]
+    }
+
+    fn from_stored(id: u8, params: [u64; 2]) -> Option<Xxh3State> {
+        (id == HashAlgo::Xxh3.id() && params[1] == 0).then(|| Xxh3State::with_seed(params[0]))
+    }
+}
+
+#[cfg(feature = "wyhash")]
+impl StoredHasher for WyState {
+    fn algo_id(&self) -> u8 {
+        HashAlgo::WyHash.id()
+    }
+
+    fn params(&self) -> [u64; 2] {
+        [self.seed, 0]
+    }
+
+    fn from_stored(id: u8, params: [u64; 2]) -> Option<WyState> {
+        (id == HashAlgo::WyHash.id() && params[1] == 0).then(|| WyState::with_seed(params[0]))
+    }
+}
+
+// FxHasher is the word-at-a-time multiplicative hash rustc uses internally.
+// It is very fast on short keys but trivially attackable: only use it for
+// trusted key sets.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FxHasher {
+    hash: u64,
+}
+
+const FX_K: u64 = 0x517c_c1b7_2722_0a95;
+
+impl FxHasher {
+    fn add(&mut self, w: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ w).wrapping_mul(FX_K);
+    }
+}
+
+impl Hasher for FxHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        let mut words = bytes.chunks_exact(8);
+        for w in &mut words {
+            self.add(load_le(w));
+        }
+
+        // tag the tail with its length so "ab" and "ab\0" differ
+        let rest = words.remainder();
+        if !rest.is_empty() {
+            self.add(load_le(rest) | (rest.len() as u64) << 56);
+        }
+    }
+
+    fn write_u8(&mut self, i: u8) {
+        self.add(i as u64);
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.add(i as u64);
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.add(i as u64);
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.add(i);
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.add(i as u64);
+        self.add((i >> 64) as u64);
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.add(i as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        // the multiply leaves the best bits at the top; buckets are picked
+        // from the bottom
+        self.hash.rotate_left(26)
+    }
+}
+
+// MetroHasher computes go-metro's Hash64 with seed 0, the hash go-mph keys
+// its tables with, fed in pieces. Integers are written as their
+// little-endian bytes. Go hashes a string's bytes alone, while a Rust str's
+// Hash impl appends a 0xff, so keys of a go-mph table are looked up with
+// query_bytes.
+#[derive(Clone, Copy, Debug)]
+pub struct MetroHasher {
+    hash: u64,
+    v: [u64; 4],
+    // bytes not yet taken into v, and how many of them there are
+    buf: [u8; 32],
+    nbuf: usize,
+    len: usize,
+}
+
+const METRO_K0: u64 = 0xd6d0_18f5;
+const METRO_K1: u64 = 0xa2aa_033b;
+const METRO_K2: u64 = 0x6299_2fc1;
+const METRO_K3: u64 = 0x30bc_5b29;
+
+impl Default for MetroHasher {
+    fn default() -> MetroHasher {
+        let hash = METRO_K2.wrapping_mul(METRO_K0);
+        MetroHasher {
+            hash,
+            v: [hash; 4],
+            buf: [0; 32],
+            nbuf: 0,
+            len: 0,
+        }
+    }
+}
+
+impl MetroHasher {
+    // Block mixes 32 bytes into the lanes.
+    fn block(&mut self, b: &[u8]) {
+        let w = |i: usize| u64::from_le_bytes(b[i * 8..i * 8 + 8].try_into().unwrap());
+        let v = &mut self.v;
+        v[0] = v[0].wrapping_add(w(0).wrapping_mul(METRO_K0));
+        v[0] = v[0].rotate_right(29).wrapping_add(v[2]);
+        v[1] = v[1].wrapping_add(w(1).wrapping_mul(METRO_K1));
+        v[1] = v[1].rotate_right(29).wrapping_add(v[3]);
+        v[2] = v[2].wrapping_add(w(2).wrapping_mul(METRO_K2));
+        v[2] = v[2].rotate_right(29).wrapping_add(v[0]);
+        v[3] = v[3].wrapping_add(w(3).wrapping_mul(METRO_K3));
+        v[3] = v[3].rotate_right(29).wrapping_add(v[1]);
+    }
+}
+
+impl Hasher for MetroHasher {
+    fn write(&mut self, mut bytes: &[u8]) {
+        self.len += bytes.len();
+        while !bytes.is_empty() {
+            let n = (32 - self.nbuf).min(bytes.len());
+            self.buf[self.nbuf..self.nbuf + n].copy_from_slice(&bytes[..n]);
+            self.nbuf += n;
+            bytes = &bytes[n..];
+            if self.nbuf == 32 {
+                let buf = self.buf;
+                self.block(&buf);
+                self.nbuf = 0;
+            }
+        }
+    }
+
+    le_ints!(
+        write_u16: u16, write_u32: u32, write_u64: u64, write_u128: u128,
+        write_i16: i16, write_i32: i32, write_i64: i64, write_i128: i128,
+        write_usize: usize as u64, write_isize: isize as i64
+    );
+
+    fn finish(&self) -> u64 {
+        let (k0, k1, k2, k3) = (METRO_K0, METRO_K1, METRO_K2, METRO_K3);
+        let mut hash = self.hash;
+        if self.len >= 32 {
+            let mut v = self.v;
+            v[2] ^= (v[0].wrapping_add(v[3]).wrapping_mul(k0).wrapping_add(v[1]))
+                .rotate_right(37)
+                .wrapping_mul(k1);
+            v[3] ^= (v[1].wrapping_add(v[2]).wrapping_mul(k1).wrapping_add(v[0]))
+                .rotate_right(37)
+                .wrapping_mul(k0);
+            v[0] ^= (v[0].wrapping_add(v[2]).wrapping_mul(k0).wrapping_add(v[3]))
+                .rotate_right(37)
+                .wrapping_mul(k1);
+            v[1] ^= (v[1].wrapping_add(v[3]).wrapping_mul(k1).wrapping_add(v[2]))
+                .rotate_right(37)
+                .wrapping_mul(k0);
+            hash = hash.wrapping_add(v[0] ^ v[1]);
+        }
+
+        let mut b = &self.buf[..self.nbuf];
+        if b.len() >= 16 {
+            let mut v0 = hash.wrapping_add(load_le(&b[..8]).wrapping_mul(k2));
+            v0 = v0.rotate_right(29).wrapping_mul(k3);
+            let mut v1 = hash.wrapping_add(load_le(&b[8..16]).wrapping_mul(k2));
+            v1 = v1.rotate_right(29).wrapping_mul(k3);
+            v0 ^= v0.wrapping_mul(k0).rotate_right(21).wrapping_add(v1);
+            v1 ^= v1.wrapping_mul(k3).rotate_right(21).wrapping_add(v0);
+            hash = hash.wrapping_add(v1);
+            b = &b[16..];
+        }
+        // the tail goes in in pieces of 8, 4, 2 and 1 bytes
+        for (n, r) in [(8, 55), (4, 26), (2, 48), (1, 37)] {
+            if b.len() >= n {
+                hash = hash.wrapping_add(load_le(&b[..n]).wrapping_mul(k3));
+                hash ^= hash.rotate_right(r).wrapping_mul(k1);
+                b = &b[n..];
+            }
+        }
+        hash ^= hash.rotate_right(28);
+        hash = hash.wrapping_mul(k0);
+        hash ^ hash.rotate_right(29)
+    }
+}
+
+// HashAlgo selects one of the crate's hash functions at runtime. Its id is
+// recorded with the table, so a table loaded from elsewhere can be queried
+// with the function it was built with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+pub enum HashAlgo {
+    // SipHash-1-3 with zero keys, the same as DefaultState.
+    #[default]
+    Sip13,
+    Fx,
+    #[cfg(feature = "xxhash")]
+    Xxh3,
+    #[cfg(feature = "wyhash")]
+    WyHash,
+    // go-metro's Hash64 with seed 0, as go-mph uses; see MetroHasher.
+    Metro64,
+}
+
+impl HashAlgo {
+    // Id returns the algorithm's stable numeric identifier.
+    pub const fn id(self) -> u8 {
+        match self {
+            HashAlgo::Sip13 => 0,
+            HashAlgo::Fx => 1,
+            #[cfg(feature = "xxhash")]
+            HashAlgo::Xxh3 => 2,
+            #[cfg(feature = "wyhash")]
+            HashAlgo::WyHash => 3,
+            HashAlgo::Metro64 => 4,
+        }
+    }
+
+    // FromId maps an identifier back to an algorithm. It returns None for
+    // unknown ids and for algorithms whose feature is not enabled.
+    pub fn from_id(id: u8) -> Option<HashAlgo> {
+        match id {
+            0 => Some(HashAlgo::Sip13),
+            1 => Some(HashAlgo::Fx),
+            #[cfg(feature = "xxhash")]
+            2 => Some(HashAlgo::Xxh3),
+            #[cfg(feature = "wyhash")]
+            3 => Some(HashAlgo::WyHash),
+            4 => Some(HashAlgo::Metro64),
+            _ => None,
+        }
+    }
+}
+
+impl StoredHasher for HashAlgo {
+    fn algo_id(&self) -> u8 {
+        self.id()
+    }
+
+    fn params(&self) -> [u64; 2] {
+        [0, 0]
+    }
+
+    fn from_stored(id: u8, params: [u64; 2]) -> Option<HashAlgo> {
+        if params != [0, 0] {
+            return None;
+        }
+        HashAlgo::from_id(id)
+    }
+}
+
+impl BuildHasher for HashAlgo {
+    type Hasher = AlgoHasher;
+
+    fn build_hasher(&self) -> AlgoHasher {
+        match self {
+            HashAlgo::Sip13 => AlgoHasher::Sip13(SipState::default().build_hasher()),
+            HashAlgo::Fx => AlgoHasher::Fx(FxHasher::default()),
+            #[cfg(feature = "xxhash")]
+            HashAlgo::Xxh3 => AlgoHasher::Xxh3(Box::new(Xxh3State::default().build_hasher())),
+            #[cfg(feature = "wyhash")]
+            HashAlgo::WyHash => AlgoHasher::WyHash(WyState::default().build_hasher()),
+            HashAlgo::Metro64 => AlgoHasher::Metro64(MetroHasher::default()),
+        }
+    }
+}
+
+// AlgoHasher is the hasher HashAlgo builds.
+#[derive(Clone)]
+pub enum AlgoHasher {
+    Sip13(SipHasher13),
+    Fx(FxHasher),
+    #[cfg(feature = "xxhash")]
+    Xxh3(Box<LittleEndian<xxhash_rust::xxh3::Xxh3>>),
+    #[cfg(feature = "wyhash")]
+    WyHash(LittleEndian<wyhash::WyHash>),
+    Metro64(MetroHasher),
+}
+
+// dispatch forwards integer writes to the selected hasher, all of which
+// write integers little-endian.
+macro_rules! dispatch {
+    ($($name:ident: $ty:ty),*) => {
+        $(fn $name(&mut self, i: $ty) {
+            match self {
+                AlgoHasher::Sip13(h) => h.$name(i),
+                AlgoHasher::Fx(h) => h.$name(i),
+                AlgoHasher::Metro64(h) => h.$name(i),
+                #[cfg(feature = "xxhash")]
+                AlgoHasher::Xxh3(h) => h.$name(i),
+                #[cfg(feature = "wyhash")]
+                AlgoHasher::WyHash(h) => h.$name(i),
+            }
+        })*
+    };
+}
+
+impl Hasher for AlgoHasher {
+    fn finish(&self) -> u64 {
+        match self {
+            AlgoHasher::Sip13(h) => h.finish(),
+            AlgoHasher::Fx(h) => h.finish(),
+            AlgoHasher::Metro64(h) => h.finish(),
+            #[cfg(feature = "xxhash")]
+            AlgoHasher::Xxh3(h) => h.finish(),
+            #[cfg(feature = "wyhash")]
+            AlgoHasher::WyHash(h) => h.finish(),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        match self {
+            AlgoHasher::Sip13(h) => h.write(bytes),
+            AlgoHasher::Fx(h) => h.write(bytes),
+            AlgoHasher::Metro64(h) => h.write(bytes),
+            #[cfg(feature = "xxhash")]
+            AlgoHasher::Xxh3(h) => h.write(bytes),
+            #[cfg(feature = "wyhash")]
+            AlgoHasher::WyHash(h) => h.write(bytes),
+        }
+    }
+
+    dispatch!(
+        write_u8: u8, write_u16: u16, write_u32: u32, write_u64: u64, write_u128: u128,
+        write_usize: usize
+    );
+}
+
+// SECOND_HASH is mixed into the seed prefix to derive the displacement hash,
+// keeping it independent of the bucket hash even when the seed is zero.
+const SECOND_HASH: u64 = 0x9e37_79b9_7f4a_7c15;
+
+// KeyHasher records how keys are turned into 64-bit hashes, so queries hash
+// exactly the way the table was built.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    rkyv(bytecheck(verify))
+)]
+pub(crate) struct KeyHasher<S = DefaultState> {
+    pub(crate) build: S,
+    pub(crate) seed: u64,
+    pub(crate) second_hash: bool,
+    pub(crate) fold_case: bool,
+    // unicode_case is set with fold_case by builds with the unicode-case
+    // feature, which fold with str::to_lowercase
+    pub(crate) unicode_case: bool,
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "rkyv", rkyv(with = crate::archive::NoNormalizer))]
+    pub(crate) normalize: Option<Normalizer>,
+}
+
+impl<S> KeyHasher<S> {
+    // New returns a KeyHasher using build with all options off.
+    pub(crate) const fn new(build: S) -> KeyHasher<S> {
+        KeyHasher {
+            build,
+            seed: 0,
+            second_hash: false,
+            fold_case: false,
+            unicode_case: false,
+            #[cfg(feature = "std")]
+            normalize: None,
+        }
+    }
+}
+
+// A normalizer is a function pointer, which can't be written out, so tables
+// using one refuse to serialize rather than come back without it.
+#[cfg(feature = "serde")]
+impl<S: serde::Serialize> serde::Serialize for KeyHasher<S> {
+    fn serialize<T: serde::Serializer>(&self, serializer: T) -> Result<T::Ok, T::Error> {
+        use serde::ser::Error;
+
+        if self.normalize.is_some() {
+            return Err(T::Error::custom(
+                "can't serialize a table with a normalizer",
+            ));
+        }
+        let options = (self.second_hash, self.fold_case, self.unicode_case);
+        (&self.build, self.seed, options).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, S: serde::Deserialize<'de>> serde::Deserialize<'de> for KeyHasher<S> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let (build, seed, (second_hash, fold_case, unicode_case)) =
+            serde::Deserialize::deserialize(deserializer)?;
+        if unicode_case && !cfg!(feature = "unicode-case") {
+            return Err(D::Error::custom(
+                "unicode case folding needs the unicode-case feature",
+            ));
+        }
+        Ok(KeyHasher {
+            build,
+            seed,
+            second_hash,
+            fold_case,
+            unicode_case,
+            normalize: None,
+        })
+    }
+}
+
+impl<S: BuildHasher> KeyHasher<S> {
+    pub(crate) fn hash<T: Hash + ?Sized>(&self, t: &T) -> u64 {
+        let mut s = self.hasher();
+        t.hash(&mut s);
+        s.finish()
+    }
+
+    pub(crate) fn hash_bytes(&self, b: &[u8]) -> u64 {
+        let mut s = self.hasher();
+        s.write(b);
+        s.finish()
+    }
+
+    // HashPair returns the bucket hash and the displacement hash of t. They
+    // are the same value unless second_hash is set.
+    pub(crate) fn hash_pair<T: Hash + ?Sized>(&self, t: &T) -> (u64, u64) {
+        let h = self.hash(t);
+        if !self.second_hash {
+            return (h, h);
+        }
+        let mut s = self.prefixed(Some(self.seed ^ SECOND_HASH));
+        t.hash(&mut s);
+        (h, s.finish())
+    }
+
+    pub(crate) fn hash_bytes_pair(&self, b: &[u8]) -> (u64, u64) {
+        let h = self.hash_bytes(b);
+        if !self.second_hash {
+            return (h, h);
+        }
+        let mut s = self.prefixed(Some(self.seed ^ SECOND_HASH));
+        s.write(b);
+        (h, s.finish())
+    }
+
+    fn hasher(&self) -> Folding<S::Hasher> {
+        // zero means unseeded, so default tables hash keys exactly as before
+        self.prefixed(if self.seed != 0 {
+            Some(self.seed)
+        } else {
+            None
+        })
+    }
+
+    fn prefixed(&self, prefix: Option<u64>) -> Folding<S::Hasher> {
+        let mut inner = self.build.build_hasher();
+        if let Some(p) = prefix {
+            inner.write(&p.to_le_bytes());
+        }
+        Folding {
+            inner,
+            fold_case: self.fold_case,
+            #[cfg(feature = "unicode-case")]
+            unicode_case: self.unicode_case,
+            #[cfg(feature = "std")]
+            normalize: self.normalize,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl KeyHasher<SipState> {
+    // HashBytesAll returns hash_bytes of each key. With the simd feature they
+    // are hashed four at a time in vector lanes, unless they would be folded
+    // or normalized first.
+    pub(crate) fn hash_bytes_all<'a, I>(&self, keys: I) -> Vec<u64>
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+    {
+        let keys = keys.into_iter();
+        #[cfg(feature = "simd")]
+        {
+            if !self.fold_case && self.normalize.is_none() {
+                let (k0, k1) = self.build.keys();
+                let prefix = if self.seed != 0 {
+                    Some(self.seed)
+                } else {
+                    None
+                };
+                let mut hashes = Vec::with_capacity(keys.size_hint().0);
+                crate::simd::sip13(k0, k1, prefix, keys, &mut hashes);
+                return hashes;
+            }
+        }
+        keys.map(|k| self.hash_bytes(k)).collect()
+    }
+}
+
+// Folding forwards to an inner hasher, optionally normalizing and lowercasing
+// byte strings on the way. Integers are passed through untouched, so only
+// string data (str, [u8] and the like) is affected.
+struct Folding<H> {
+    inner: H,
+    fold_case: bool,
+    // without the feature, tables with unicode_case set don't load
+    #[cfg(feature = "unicode-case")]
+    unicode_case: bool,
+    #[cfg(feature = "std")]
+    normalize: Option<Normalizer>,
+}
+
+macro_rules! forward {
+    ($($name:ident: $ty:ty),*) => {
+        $(fn $name(&mut self, i: $ty) {
+            self.inner.$name(i)
+        })*
+    };
+}
+
+impl<H: Hasher> Hasher for Folding<H> {
+    fn finish(&self) -> u64 {
+        self.inner.finish()
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        #[cfg(feature = "std")]
+        if let Some(normalize) = self.normalize {
+            if let Ok(s) = str::from_utf8(bytes) {
+                return self.write_folded(normalize(s).as_bytes());
+            }
+        }
+        self.write_folded(bytes)
+    }
+
+    forward!(
+        write_u8: u8, write_u16: u16, write_u32: u32, write_u64: u64, write_u128: u128,
+        write_usize: usize, write_i8: i8, write_i16: i16, write_i32: i32, write_i64: i64,
+        write_i128: i128, write_isize: isize
+    );
+}
+
+impl<H: Hasher> Folding<H> {
+    fn write_folded(&mut self, bytes: &[u8]) {
+        if !self.fold_case {
+            return self.inner.write(bytes);
+        }
+
+        #[cfg(feature = "unicode-case")]
+        {
+            if self.unicode_case {
+                if let Ok(s) = str::from_utf8(bytes) {
+                    return self.inner.write(s.to_lowercase().as_bytes());
+                }
+            }
+        }
+
+        // fold through a small buffer so lookups don't allocate
+        let mut buf = [0u8; 64];
+        for chunk in bytes.chunks(buf.len()) {
+            let buf = &mut buf[..chunk.len()];
+            buf.copy_from_slice(chunk);
+            buf.make_ascii_lowercase();
+            self.inner.write(buf);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn siphash_matches_std() {
+        // DefaultHasher::new() is currently SipHash-1-3 with zero keys
+        let sip = SipState::new_with_keys(0, 0);
+        let std =
+            std::hash::BuildHasherDefault::<std::collections::hash_map::DefaultHasher>::default();
+        for n in 0..40 {
+            let key: Vec<u8> = (0..n).collect();
+            assert_eq!(sip.hash_one(&key), std.hash_one(&key));
+
+            // split writes must hash like one write
+            let mut h = sip.build_hasher();
+            for chunk in key.chunks(3) {
+                h.write(chunk);
+            }
+            let mut g = std.build_hasher();
+            g.write(&key);
+            assert_eq!(h.finish(), g.finish());
+        }
+    }
+
+    #[test]
+    fn default_state_is_stable() {
+        // golden values: these must never change, or persisted tables break
+        let s = DefaultState::default();
+        let mut h = s.build_hasher();
+        h.write(b"");
+        assert_eq!(h.finish(), GOLDEN[0]);
+        let mut h = s.build_hasher();
+        h.write(b"hello, world");
+        assert_eq!(h.finish(), GOLDEN[1]);
+        let mut h = s.build_hasher();
+        h.write(b"The quick brown fox jumps over the lazy dog");
+        assert_eq!(h.finish(), GOLDEN[2]);
+    }
+
+    const GOLDEN: [u64; 3] = [
+        15_130_871_412_783_076_140,
+        15_533_755_107_840_727_033,
+        10_229_494_255_719_302_430,
+    ];
+
+    #[test]
+    fn integers_are_little_endian() {
+        // golden values, as computed on a little-endian target
+        let s = DefaultState::default();
+        let (mut a, mut b) = (s.build_hasher(), s.build_hasher());
+        a.write_u32(0x0102_0304);
+        b.write(&[4, 3, 2, 1]);
+        assert_eq!(a.finish(), b.finish());
+        assert_eq!(s.hash_one(7usize), s.hash_one(7u64));
+        assert_eq!(s.hash_one(42u64), 8_880_661_182_590_738_257);
+        assert_eq!(HashAlgo::Sip13.hash_one(42u64), s.hash_one(42u64));
+
+        let x = 0x0102_0304_0506_0708_090a_0b0c_0d0e_0f10u128;
+        let (mut a, mut b) = (s.build_hasher(), s.build_hasher());
+        a.write_u128(x);
+        b.write(&x.to_le_bytes());
+        assert_eq!(a.finish(), b.finish());
+        assert_eq!(HashAlgo::Sip13.hash_one(x), s.hash_one(x));
+        let fx = HashAlgo::Fx;
+        let (mut a, mut b) = (fx.build_hasher(), fx.build_hasher());
+        a.write_u128(x);
+        b.write_u64(x as u64);
+        b.write_u64((x >> 64) as u64);
+        assert_eq!(a.finish(), b.finish());
+    }
+
+    #[test]
+    fn metro64() {
+        // go-metro's test vector, whole and in pieces
+        let key = b"012345678901234567890123456789012345678901234567890123456789012";
+        for n in [key.len(), 1, 7, 31, 32, 40] {
+            let mut h = HashAlgo::Metro64.build_hasher();
+            for piece in key.chunks(n) {
+                h.write(piece);
+            }
+            assert_eq!(h.finish(), 0xad4b_7006_ae3d_756b);
+        }
+        let (mut a, mut b) = (MetroHasher::default(), MetroHasher::default());
+        a.write_u32(0x0102_0304);
+        b.write(&[4, 3, 2, 1]);
+        assert_eq!(a.finish(), b.finish());
+    }
+
+    #[test]
+    fn hash_algo() {
+        for id in 0..=255 {
+            if let Some(algo) = HashAlgo::from_id(id) {
+                assert_eq!(algo.id(), id);
+            }
+        }
+
+        let fx = HashAlgo::Fx;
+        let plain = std::hash::BuildHasherDefault::<FxHasher>::default();
+        assert_eq!(fx.hash_one("abc"), plain.hash_one("abc"));
+        assert_eq!(fx.hash_one(42u32), plain.hash_one(42u32));
+
+        let (mut a, mut b) = (fx.build_hasher(), fx.build_hasher());
+        a.write(b"ab");
+        b.write(b"ab\0");
+        assert_ne!(a.finish(), b.finish());
+
+        let sip = HashAlgo::Sip13;
+        assert_eq!(sip.hash_one("abc"), DefaultState::default().hash_one("abc"));
+    }
+
+    #[cfg(feature = "xxhash")]
+    #[test]
+    fn xxh3_state() {
+        let s = Xxh3State::default();
+        let mut h = s.build_hasher();
+        h.write(b"hello world");
+        assert_eq!(h.finish(), xxhash_rust::xxh3::xxh3_64(b"hello world"));
+
+        // integers go in little-endian, whatever the target
+        let mut h = s.build_hasher();
+        h.write_u32(0x0403_0201);
+        h.write_usize(5);
+        assert_eq!(
+            h.finish(),
+            xxhash_rust::xxh3::xxh3_64(&[1, 2, 3, 4, 5, 0, 0, 0, 0, 0, 0, 0])
+        );
+
+        let keys: Vec<String> = (0..1000).map(|i| format!("key{}", i)).collect();
+        let t = crate::Table::with_hasher(&keys, Xxh3State::with_seed(7));
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+        }
+    }
+
+    #[cfg(feature = "wyhash")]
+    #[test]
+    fn wyhash_state() {
+        let s = WyState::with_seed(3);
+        let mut h = s.build_hasher();
+        h.write(b"short");
+        assert_eq!(h.finish(), wyhash::wyhash(b"short", 3));
+
+        let mut h = s.build_hasher();
+        h.write_u32(0x0403_0201);
+        h.write_usize(5);
+        assert_eq!(
+            h.finish(),
+            wyhash::wyhash(&[1, 2, 3, 4, 5, 0, 0, 0, 0, 0, 0, 0], 3)
+        );
+
+        let keys: Vec<String> = (0..1000).map(|i| format!("kw{}", i)).collect();
+        let t = crate::TableBuilder::new()
+            .hash_algo(HashAlgo::WyHash)
+            .build(&keys);
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+        }
+    }
+}
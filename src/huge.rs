@@ -0,0 +1,68 @@
+// Huge pages for the arrays of large tables. A table whose seeds and values
+// span gigabytes takes a TLB miss on nearly every random lookup with 4KB
+// pages; with 2MB pages the same TLB covers 512 times as much. Linux backs
+// memory with transparent huge pages either everywhere or, in its common
+// madvise mode, only where a program asks, which is what this does. Other
+// systems get ordinary pages.
+
+#[cfg(target_os = "linux")]
+use std::mem;
+#[cfg(target_os = "linux")]
+use std::os::raw::{c_int, c_void};
+
+#[cfg(target_os = "linux")]
+const HUGE_PAGE: usize = 2 << 20;
+
+#[cfg(target_os = "linux")]
+const MADV_HUGEPAGE: c_int = 14;
+
+#[cfg(target_os = "linux")]
+extern "C" {
+    fn madvise(addr: *mut c_void, len: usize, advice: c_int) -> c_int;
+}
+
+// Reserve empties v and makes room for n elements, asking for the room to be
+// backed by huge pages before anything is written to it.
+pub(crate) fn reserve<T>(v: &mut Vec<T>, n: usize) {
+    v.clear();
+    v.reserve(n);
+    #[cfg(target_os = "linux")]
+    advise(v.as_mut_ptr() as usize, v.capacity() * mem::size_of::<T>());
+}
+
+// advise asks for huge pages for the whole ones within len bytes at start.
+// A failure, say on a kernel without transparent huge pages, leaves the
+// memory as it was.
+#[cfg(target_os = "linux")]
+fn advise(start: usize, len: usize) {
+    let lo = (start + HUGE_PAGE - 1) & !(HUGE_PAGE - 1);
+    let hi = (start + len) & !(HUGE_PAGE - 1);
+    if lo < hi {
+        // safe: the range is memory the caller owns, and the advice changes
+        // how it's backed, not what it holds
+        unsafe { madvise(lo as *mut c_void, hi - lo, MADV_HUGEPAGE) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::TableBuilder;
+
+    #[test]
+    fn huge_pages() {
+        let mut v = vec![1u64; 10];
+        super::reserve(&mut v, 1 << 20);
+        assert!(v.is_empty() && v.capacity() >= 1 << 20);
+        v.resize(1 << 20, 7);
+        assert!(v.iter().all(|&x| x == 7));
+
+        // the table is the same either way
+        let keys: Vec<u64> = (0..100000).collect();
+        for b in [TableBuilder::new(), TableBuilder::new().k(4)] {
+            let want = b.clone().build(&keys);
+            let t = b.huge_pages(true).build(&keys);
+            assert_eq!(t.seeds, want.seeds);
+            assert_eq!(t.values, want.values);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::hash::{BuildHasher, Hash};
+
+use crate::hash::{DefaultState, KeyHasher};
+use crate::rank::RankBits;
+use crate::{reduce, xorshift_mult64, Table};
+
+// InterleavedTable is a Table laid out for lookups in tables too big for the
+// CPU's caches, made by Table::interleave. A query on a Table reads the key's
+// seed and then, in another part of memory, its value: two cache misses, the
+// second waiting on the first. Here each bucket's seed shares a 64-bit word
+// with the value of its key when it has only one, so those keys, about a
+// third of them at the default lambda, are answered from a single cache line.
+// Keys in larger buckets still read the values (or, for minimal tables, the
+// occupied bits) as a Table does.
+//
+// The extra half word per bucket costs 32 bits per key at lambda 1, for
+// lookups about a tenth faster on tables of millions of keys, and nothing at
+// higher lambdas, where buckets of one key are rare. Queries answer as the
+// table's did.
+pub struct InterleavedTable<S = DefaultState> {
+    hasher: KeyHasher<S>,
+    // per bucket, the seed in the low half and, for a bucket of one key, that
+    // key's value in the high half
+    buckets: Vec<u64>,
+    values: Vec<i32>,
+    occupied: Option<RankBits>,
+    len: usize,
+    size: usize,
+}
+
+impl<S> Table<S> {
+    // Interleave converts the table to the InterleavedTable layout.
+    pub fn interleave(self) -> InterleavedTable<S> {
+        let buckets = self
+            .seeds
+            .iter()
+            .map(|&seed| {
+                let value = if seed >= 0 {
+                    0
+                } else if self.values.is_empty() {
+                    // singletons without values store their final value
+                    -seed - 1
+                } else {
+                    self.values[(-seed - 1) as usize]
+                };
+                (seed as u32 as u64) | ((value as u32 as u64) << 32)
+            })
+            .collect();
+        InterleavedTable {
+            hasher: self.hasher,
+            buckets,
+            values: self.values,
+            occupied: self.occupied,
+            len: self.len,
+            size: self.size,
+        }
+    }
+}
+
+impl<S> InterleavedTable<S> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // BitsPerKey reports the space used by the buckets and values (or the
+    // rank structure that replaces them).
+    pub fn bits_per_key(&self) -> f64 {
+        let bits = self.buckets.len() * 64
+            + self.values.len() * 32
+            + self.occupied.as_ref().map_or(0, RankBits::size_bits);
+        bits as f64 / self.len.max(1) as f64
+    }
+}
+
+impl<S: BuildHasher> InterleavedTable<S> {
+    // Query returns the value Table::query would for k.
+    pub fn query<K: Hash + ?Sized>(&self, k: &K) -> usize {
+        let (hash, disp) = self.hasher.hash_pair(k);
+        self.lookup(hash, disp)
+    }
+
+    // QueryBytes looks up a binary key in a table built with from_bytes_keys.
+    pub fn query_bytes<K: AsRef<[u8]> + ?Sized>(&self, k: &K) -> usize {
+        let (hash, disp) = self.hasher.hash_bytes_pair(k.as_ref());
+        self.lookup(hash, disp)
+    }
+
+    // QueryPrehashed looks up a digest in a table built with from_hashes.
+    pub fn query_prehashed(&self, hash: u64) -> usize {
+        self.lookup(hash, hash)
+    }
+
+    fn lookup(&self, hash: u64, disp: u64) -> usize {
+        let word = self.buckets[reduce(hash, self.buckets.len())];
+        let seed = word as u32 as i32;
+        if seed < 0 {
+            return (word >> 32) as u32 as usize;
+        }
+
+        let i = reduce(xorshift_mult64(disp.wrapping_add(seed as u64)), self.size);
+        match &self.occupied {
+            Some(bits) => bits.rank(i),
+            None if self.values.is_empty() => i,
+            None => self.values[i] as usize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Table, TableBuilder};
+
+    #[test]
+    fn interleave() {
+        let keys: Vec<String> = (0..5000).map(|i| format!("k{}", i)).collect();
+
+        for b in [
+            TableBuilder::new(),
+            TableBuilder::new().minimal(true),
+            TableBuilder::new()
+                .second_hash(true)
+                .fastrange(true)
+                .lambda(4.0),
+            TableBuilder::new().k(4),
+        ] {
+            let t = b.build(&keys);
+            let want: Vec<usize> = keys.iter().map(|k| t.query(k)).collect();
+            let bits = t.bits_per_key();
+            let u = t.interleave();
+            assert_eq!(u.len(), keys.len());
+            assert!(u.bits_per_key() > bits);
+            for (k, &v) in keys.iter().zip(&want) {
+                assert_eq!(u.query(k), v);
+            }
+        }
+
+        let hashes: Vec<u64> = (0..1000u64).map(crate::fmix64).collect();
+        let u = Table::from_hashes(&hashes).interleave();
+        for (i, &h) in hashes.iter().enumerate() {
+            assert_eq!(u.query_prehashed(h), i);
+        }
+    }
+}
@@ -1,126 +1,1371 @@
-use std::vec;
+#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
 
-use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
-use std::hash::{Hash, Hasher};
+#[cfg(feature = "std")]
+use std::ffi::{CStr, OsStr};
+#[cfg(feature = "std")]
+use std::hash::{BuildHasher, Hash};
+#[cfg(feature = "std")]
+use std::iter::FromIterator;
+#[cfg(feature = "std")]
+use std::mem;
+#[cfg(feature = "std")]
+use std::time::Instant;
 
-pub struct Table {
+#[cfg(feature = "allocator_api")]
+mod allocator;
+#[cfg(feature = "rkyv")]
+mod archive;
+#[cfg(feature = "std")]
+pub mod bbhash;
+#[cfg(feature = "std")]
+mod bdz;
+#[cfg(feature = "std")]
+mod builder;
+#[cfg(feature = "std")]
+mod compact;
+#[cfg(feature = "compression")]
+mod compress;
+#[cfg(feature = "std")]
+mod container;
+#[cfg(feature = "std")]
+mod debug;
+#[cfg(feature = "std")]
+mod external;
+#[cfg(feature = "std")]
+mod fixed;
+#[cfg(feature = "flatbuffers")]
+mod flatbuf;
+mod format;
+#[cfg(feature = "std")]
+mod function;
+#[cfg(feature = "std")]
+mod gomph;
+pub mod hash;
+#[cfg(feature = "std")]
+mod huge;
+#[cfg(feature = "std")]
+mod interleaved;
+#[cfg(feature = "std")]
+mod metadata;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "std")]
+mod monotone;
+#[cfg(all(feature = "numa", target_os = "linux"))]
+mod numa;
+#[cfg(feature = "std")]
+mod packed;
+#[cfg(feature = "std")]
+mod partitioned;
+#[cfg(feature = "std")]
+pub mod pthash;
+mod rank;
+#[cfg(feature = "std")]
+pub mod recsplit;
+#[cfg(feature = "std")]
+mod retrieval;
+#[cfg(feature = "simd")]
+mod simd;
+#[cfg(feature = "std")]
+mod small;
+#[cfg(feature = "std")]
+mod swap;
+
+#[cfg(feature = "allocator_api")]
+pub use allocator::TableIn;
+#[cfg(feature = "rkyv")]
+pub use archive::ArchivedHasher;
+#[cfg(feature = "std")]
+pub use bdz::BdzTable;
+#[cfg(feature = "std")]
+pub use builder::{
+    BuildError, BuildStats, CancellationToken, HashedKeys, Phase, Progress, TableBuilder,
+};
+#[cfg(feature = "std")]
+pub use compact::CompactTable;
+#[cfg(feature = "std")]
+pub use container::Archive;
+#[cfg(feature = "std")]
+pub use fixed::StaticTable;
+pub use format::{FormatError, TableRef};
+#[cfg(feature = "std")]
+pub use function::{Algorithm, DynMph, MphFunction, Preference};
+pub use hash::DefaultState;
+#[cfg(feature = "std")]
+pub use hash::Normalizer;
+#[cfg(feature = "std")]
+pub use interleaved::InterleavedTable;
+#[cfg(feature = "std")]
+pub use metadata::Metadata;
+#[cfg(feature = "mmap")]
+pub use mmap::{HybridTable, MappedTable};
+#[cfg(feature = "std")]
+pub use monotone::MonotoneTable;
+#[cfg(all(feature = "numa", target_os = "linux"))]
+pub use numa::NumaTable;
+#[cfg(feature = "std")]
+pub use partitioned::PartitionedTable;
+#[cfg(feature = "std")]
+pub use retrieval::{ApproxMap, Retrieval};
+#[cfg(feature = "std")]
+pub use small::SmallTable;
+#[cfg(feature = "std")]
+pub use swap::{SwappableTable, TableReader};
+
+#[cfg(feature = "std")]
+use builder::ProgressFn;
+#[cfg(feature = "std")]
+use hash::KeyHasher;
+#[cfg(feature = "std")]
+use metadata::BuildInfo;
+#[cfg(feature = "std")]
+use rank::RankBits;
+
+#[cfg(feature = "std")]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+pub struct Table<S = DefaultState> {
     values: Vec<i32>,
     seeds: Vec<i32>,
+    hasher: KeyHasher<S>,
+    // keys
+    len: usize,
+    // slots; values is empty for minimal tables
+    size: usize,
+    // for minimal tables, the slots holding a key
+    occupied: Option<RankBits>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    info: BuildInfo,
 }
 
-struct Entry {
-    idx: i32,
+// KeyHash is a key hashed the way a table hashes it, from Table::hash_key.
+// Callers that look the same keys up again and again can keep it and skip
+// the hashing with query_hash. It holds for any table built with the same
+// hasher, seed and second_hash setting, so it survives a rebuild over a new
+// key set with the same options.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeyHash {
     hash: u64,
+    disp: u64,
+}
+
+// Params holds the construction knobs set through TableBuilder.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug)]
+struct Params {
+    // average keys per bucket; each bucket costs one seed
+    lambda: f64,
+    // size the arrays exactly rather than to powers of two
+    fastrange: bool,
+    // rank slots instead of storing values
+    minimal: bool,
+    // keys allowed per slot
+    k: usize,
+    // seeds to try on a bucket before evicting others
+    evict_after: u64,
+    // seeds to try on a bucket before growing the table and starting over
+    max_seed: u64,
+    // keys per slot, before rounding
+    load_factor: f64,
+    // buckets whose seeds are guessed at once, in parallel with rayon
+    batch: usize,
+    // try each bucket's seeds in an order of its own, see Probe
+    shuffle: bool,
+    // back the seeds and values with huge pages, see huge
+    huge_pages: bool,
+    // seed singletons too where a small seed fits, see SMALL_SEEDS
+    compress_seeds: bool,
+}
+
+#[cfg(feature = "std")]
+impl Default for Params {
+    fn default() -> Params {
+        Params {
+            lambda: 1.0,
+            fastrange: false,
+            minimal: false,
+            k: 1,
+            evict_after: EVICT_AFTER,
+            max_seed: MAX_SEED,
+            load_factor: 1.0,
+            batch: 1,
+            shuffle: false,
+            huge_pages: false,
+            compress_seeds: false,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Params {
+    // Buckets returns the number of buckets (seeds) for n keys, rounded up to
+    // a power of two so queries can mask unless fastrange is set.
+    fn buckets(&self, n: usize) -> usize {
+        self.round(((n as f64 / self.lambda).ceil() as usize).max(1))
+    }
+
+    // Slots returns the number of value slots for n keys.
+    fn slots(&self, n: usize) -> usize {
+        let slots = (n.div_ceil(self.k) as f64 / self.load_factor).ceil() as usize;
+        self.round(slots.max(1))
+    }
+
+    // Grow returns the slot count to retry with after size failed: twice as
+    // many, or an eighth more with fastrange.
+    fn grow(&self, size: usize) -> usize {
+        if self.fastrange {
+            size + size.div_ceil(8)
+        } else {
+            size * 2
+        }
+    }
+
+    fn round(&self, n: usize) -> usize {
+        if self.fastrange {
+            n
+        } else {
+            n.next_power_of_two()
+        }
+    }
+}
+
+// EVICT_AFTER bounds the plain seed search for a bucket; past it the bucket
+// may displace already placed ones, which keeps adversarial key sets from
+// stalling construction.
+#[cfg(feature = "std")]
+const EVICT_AFTER: u64 = 1 << 16;
+
+// MAX_SEED bounds the whole seed search for a bucket. Once the evictions run
+// out a bucket can still fail to place, with keys that hash alike or an
+// unlucky table; rather than search on, the build grows the table, which
+// rarely fails twice, and starts over. MAX_RETRIES such restarts mean the
+// keys almost certainly repeat.
+#[cfg(feature = "std")]
+const MAX_SEED: u64 = 1 << 20;
+#[cfg(feature = "std")]
+const MAX_RETRIES: usize = 8;
+
+// SMALL_SEEDS is how many seeds a bucket of one key tries with
+// compress_seeds before it is placed directly, which takes a negative seed.
+// Seeds up to it fit in four bits of CompactTable's seed array.
+#[cfg(feature = "std")]
+const SMALL_SEEDS: u64 = 13;
+
+// CHECK_EVERY is how many seeds a bucket's search tries between checks on
+// whether the build should stop, besides the check before each bucket.
+#[cfg(feature = "std")]
+const CHECK_EVERY: u64 = 1 << 10;
+
+// Run is the state of one build beyond its arrays: what it has done so far,
+// and what may stop it early.
+#[cfg(feature = "std")]
+#[derive(Default)]
+struct Run {
+    stats: BuildStats,
+    cancel: Option<CancellationToken>,
+    deadline: Option<Instant>,
+    progress: Option<ProgressFn>,
+}
+
+#[cfg(feature = "std")]
+impl Run {
+    // Check returns the error to stop the build with, if it should stop.
+    fn check(&self) -> Result<(), BuildError> {
+        match &self.cancel {
+            Some(c) if c.is_cancelled() => return Err(BuildError::Cancelled),
+            _ => {}
+        }
+        match self.deadline {
+            Some(d) if Instant::now() >= d => Err(BuildError::TimedOut(self.stats.clone())),
+            _ => Ok(()),
+        }
+    }
+
+    fn report(&self, p: Progress) {
+        if let Some(f) = &self.progress {
+            f.call(p);
+        }
+    }
+}
+
+// REPORT_EVERY is how many buckets, or keys while hashing, go by between
+// progress reports within a phase.
+#[cfg(feature = "std")]
+const REPORT_EVERY: usize = 1 << 12;
+
+#[cfg(feature = "std")]
+#[derive(Clone, Copy)]
+struct Entry {
+    idx: u32,
+    disp: u64,
+}
+
+// Probe maps a bucket's attempts 1, 2, 3... to the seeds it tries. Plainly
+// they are the same numbers; with shuffle set they are a permutation of
+// 1..=max_seed keyed by the bucket's first key, so that consecutive attempts
+// feed the mixer unrelated values and buckets don't all walk the seeds in
+// one order. Either way a bucket tries each seed at most once, and the seed
+// stored is the one tried, so queries don't care which order found it.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy)]
+struct Probe {
+    // zero when not shuffling
+    key: u64,
+    bits: u32,
+    max: u64,
+}
+
+#[cfg(feature = "std")]
+impl Probe {
+    fn new(params: &Params, subkeys: &[Entry]) -> Probe {
+        if !params.shuffle {
+            return Probe {
+                key: 0,
+                bits: 0,
+                max: 0,
+            };
+        }
+        let max = params.max_seed;
+        Probe {
+            key: fmix64(subkeys[0].disp) | 1,
+            bits: 64 - max.saturating_sub(1).leading_zeros(),
+            max,
+        }
+    }
+
+    fn seed(&self, attempt: u64) -> u64 {
+        if self.key == 0 || attempt > self.max {
+            return attempt;
+        }
+        // a bijection on bits-bit numbers, walked until it lands below max,
+        // which it must do before coming back round to where it started
+        let mask = (1u64 << self.bits).wrapping_sub(1);
+        let shift = self.bits.div_ceil(2).max(1);
+        let mut x = attempt - 1;
+        loop {
+            x = (x ^ self.key).wrapping_mul(0x9e37_79b9_7f4a_7c15) & mask;
+            x ^= x >> shift;
+            x = x.wrapping_mul(self.key) & mask;
+            x ^= x >> shift;
+            if x < self.max {
+                return x + 1;
+            }
+        }
+    }
+}
+
+// Buckets holds the keys grouped by bucket in one flat array, rather than a
+// Vec per bucket: bucket b's keys are entries[offsets[b]..offsets[b + 1]], in
+// input order.
+#[cfg(feature = "std")]
+#[derive(Default)]
+struct Buckets {
+    entries: Vec<Entry>,
+    offsets: Vec<usize>,
+    // by_size's counts
+    starts: Vec<usize>,
+}
+
+#[cfg(feature = "std")]
+impl Buckets {
+    // Fill groups the keys with a counting sort on their buckets, reusing the
+    // arrays of the last grouping.
+    fn fill(&mut self, hashes: &[u64], disp: Option<&[u64]>, nbuckets: usize) {
+        // count each bucket's keys at its own index, then sum them up so
+        // offsets[b] is where bucket b ends
+        let offsets = &mut self.offsets;
+        offsets.clear();
+        offsets.resize(nbuckets + 1, 0);
+        for &hash in hashes {
+            offsets[reduce(hash, nbuckets)] += 1;
+        }
+        for b in 1..=nbuckets {
+            offsets[b] += offsets[b - 1];
+        }
+
+        // fill each bucket from its end, with the keys in reverse, which
+        // leaves them in input order and offsets[b] where bucket b starts
+        self.entries.clear();
+        self.entries.resize(hashes.len(), Entry { idx: 0, disp: 0 });
+        for (idx, &hash) in hashes.iter().enumerate().rev() {
+            let at = &mut offsets[reduce(hash, nbuckets)];
+            *at -= 1;
+            self.entries[*at] = Entry {
+                idx: idx as u32,
+                disp: disp.map_or(hash, |d| d[idx]),
+            };
+        }
+    }
+
+    fn get(&self, b: usize) -> &[Entry] {
+        &self.entries[self.offsets[b]..self.offsets[b + 1]]
+    }
+
+    // BySize lists the non-empty buckets by decreasing size, keeping equal
+    // sizes in bucket order as a stable sort would. Sizes are small, so a
+    // counting sort does it in linear time, and the empty buckets, most of
+    // them in a sparse table, are left out rather than sorted.
+    fn by_size(&mut self, order: &mut Vec<usize>) {
+        let Buckets {
+            offsets, starts, ..
+        } = self;
+        let nbuckets = offsets.len() - 1;
+        let size = |b: usize| offsets[b + 1] - offsets[b];
+        let max = (0..nbuckets).map(size).max().unwrap_or(0);
+        let start = reuse(starts, max + 1);
+        for b in 0..nbuckets {
+            start[size(b)] += 1;
+        }
+
+        // turn the counts into where the next bucket of each size goes
+        let mut n = 0;
+        for s in (1..=max).rev() {
+            let count = start[s];
+            start[s] = n;
+            n += count;
+        }
+
+        order.clear();
+        order.resize(n, 0);
+        for b in (0..nbuckets).filter(|&b| size(b) > 0) {
+            let at = &mut start[size(b)];
+            order[*at] = b;
+            *at += 1;
+        }
+    }
 }
 
+// Scratch is the working memory of a build: the keys' hashes, the buckets,
+// and the arrays the seed search fills. TableBuilder::build_reusing borrows
+// it, so a caller that rebuilds tables over and over can keep one around and
+// skip reallocating all of them each time. Recycling a retired table hands
+// its arrays over for the next table to be built in.
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub struct Scratch {
+    pub(crate) hashes: Vec<u64>,
+    pub(crate) disp: Vec<u64>,
+    buckets: Buckets,
+    // the non-empty buckets, largest first
+    order: Vec<usize>,
+    work: Work,
+}
+
+// Work holds the arrays place writes to.
+#[cfg(feature = "std")]
+#[derive(Default)]
+struct Work {
+    values: Vec<i32>,
+    seeds: Vec<i32>,
+    // the slots holding a key, as bits
+    taken: Vec<u64>,
+    claimed: Vec<u64>,
+    entries: Vec<(usize, u32)>,
+    queue: Vec<usize>,
+    batch: Vec<usize>,
+    blockers: Vec<usize>,
+    free: Vec<usize>,
+    // keys per slot in a k-perfect table, and a bucket's slots under trial
+    load: Vec<usize>,
+    slots: Vec<usize>,
+}
+
+#[cfg(feature = "std")]
+impl Scratch {
+    pub fn new() -> Scratch {
+        Scratch::default()
+    }
+
+    // WithCapacity returns scratch space with room for a build over n keys
+    // with params, and disp hashes if asked, so that a build that doesn't
+    // grow the table has no array to grow either. Per-bucket arrays get room
+    // for a bucket far larger than lambda makes likely.
+    fn with_capacity(n: usize, disp: bool, params: &Params) -> Scratch {
+        const BUCKET: usize = 64;
+        let nbuckets = params.buckets(n);
+        let size = params.slots(n);
+        let mut s = Scratch::default();
+        s.hashes.reserve(n);
+        if disp {
+            s.disp.reserve(n);
+        }
+        s.buckets.entries.reserve(n);
+        s.buckets.offsets.reserve(nbuckets + 1);
+        s.buckets.starts.reserve(BUCKET);
+        s.order.reserve(nbuckets);
+
+        let w = &mut s.work;
+        w.seeds.reserve(nbuckets);
+        w.queue.reserve(nbuckets);
+        w.batch.reserve(params.batch);
+        w.entries.reserve(BUCKET);
+        w.blockers.reserve(BUCKET);
+        if params.k > 1 {
+            w.load.reserve(size);
+            w.slots.reserve(BUCKET);
+        } else {
+            w.values.reserve(size);
+            w.taken.reserve(size.div_ceil(64));
+            w.claimed.reserve(size.div_ceil(64));
+            w.free.reserve(size);
+        }
+        s
+    }
+
+    // Recycle takes the arrays of a table that is no longer needed, for the
+    // next build to reuse.
+    pub fn recycle<S>(&mut self, t: Table<S>) {
+        self.work.values = t.values;
+        self.work.seeds = t.seeds;
+    }
+}
+
+// reuse empties v and refills it with n default values, keeping its
+// allocation.
+#[cfg(feature = "std")]
+fn reuse<T: Clone + Default>(v: &mut Vec<T>, n: usize) -> &mut Vec<T> {
+    v.clear();
+    v.resize(n, T::default());
+    v
+}
+
+#[cfg(feature = "std")]
 impl Table {
-    pub fn new(keys: &[&str]) -> Table {
-        let size = (keys.len()).next_power_of_two();
-        let mut h: Vec<Vec<Entry>> = Vec::with_capacity(size);
-        for _ in 0..size {
-            h.push(Vec::new())
-        }
-
-        for (idx, k) in keys.iter().enumerate() {
-            let hash = calculate_hash(k);
-            let i = hash % (size as u64);
-            // idx+1 so we can identify empty entries in the table with 0
-            h[i as usize].push(Entry {
-                idx: (idx + 1) as i32,
-                hash,
-            });
+    // New builds a table over keys. Any type implementing Hash can be used as a
+    // key; the same type (or one that hashes identically) must be used to query.
+    //
+    // Tuples hash their fields in order with no separators beyond what each
+    // field writes itself, so a table built over (u32, String) keys can be
+    // queried with (u32, &str) without concatenating fields.
+    //
+    // Owned keys work directly: pass &Vec<String> (or any slice of String,
+    // Box<str>, Cow<str>, Arc<str>...) without first collecting a Vec<&str>.
+    // All of these hash exactly like the str they hold, so tables built from
+    // one form can be queried with any other.
+    pub fn new<K: Hash>(keys: &[K]) -> Table {
+        TableBuilder::new().build(keys)
+    }
+
+    // NewByKey builds a table keyed on a field of each record, e.g.
+    // Table::new_by_key(&records, |r| &r.name). Query results are indices into
+    // records.
+    pub fn new_by_key<T, K, F>(records: &[T], key: F) -> Table
+    where
+        K: Hash + ?Sized,
+        F: Fn(&T) -> &K,
+    {
+        TableBuilder::new().build_by_key(records, key)
+    }
+
+    // FromBytesKeys builds a table over binary keys (&[u8], Vec<u8>, arrays...).
+    // The raw bytes are hashed directly, so the table must be queried with
+    // query_bytes.
+    pub fn from_bytes_keys<K: AsRef<[u8]>>(keys: &[K]) -> Table {
+        let hasher = KeyHasher::default();
+        let hashes = hasher.hash_bytes_all(keys.iter().map(|k| k.as_ref()));
+        Table::build(&hashes, None, hasher, &Params::default())
+    }
+
+    // FromOsStrKeys builds a table over OsStr or Path keys by hashing their raw
+    // encoded bytes, so no lossy UTF-8 conversion is needed. Paths are not
+    // normalized: "a/b" and "a//b" are different keys. Query with query_os_str.
+    pub fn from_os_str_keys<K: AsRef<OsStr>>(keys: &[K]) -> Table {
+        let hasher = KeyHasher::default();
+        let hashes = hasher.hash_bytes_all(keys.iter().map(|k| k.as_ref().as_encoded_bytes()));
+        Table::build(&hashes, None, hasher, &Params::default())
+    }
+
+    // FromCStrKeys builds a table over NUL-terminated strings, hashing their
+    // contents without the NUL. Query with query_cstr (or query_bytes).
+    pub fn from_cstr_keys<K: AsRef<CStr>>(keys: &[K]) -> Table {
+        let hasher = KeyHasher::default();
+        let hashes = hasher.hash_bytes_all(keys.iter().map(|k| k.as_ref().to_bytes()));
+        Table::build(&hashes, None, hasher, &Params::default())
+    }
+
+    // Auto builds a table over byte keys with the backend Algorithm::choose
+    // picks for their count and pref; see MphFunction for how to query it.
+    pub fn auto<K: AsRef<[u8]>>(keys: &[K], pref: Preference) -> DynMph {
+        Algorithm::choose(keys.len(), pref).build(keys)
+    }
+
+    // FromHashes builds a table over precomputed 64-bit key digests, skipping
+    // hashing entirely. The digests must be distinct and well mixed (e.g. from
+    // xxh3 or a truncated SHA); query with query_prehashed.
+    pub fn from_hashes(hashes: &[u64]) -> Table {
+        Table::build(hashes, None, KeyHasher::default(), &Params::default())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: BuildHasher + Clone> Table<S> {
+    // WithHasher builds a table over keys hashed with hash_builder instead of
+    // the default SipHash. The hasher is stored in the table and used by query.
+    //
+    // For keys from untrusted input, use hash::SipState::random(): a secret key
+    // stops an attacker from crafting a key set that stalls construction.
+    pub fn with_hasher<K: Hash>(keys: &[K], hash_builder: S) -> Table<S> {
+        TableBuilder::new().with_hasher(hash_builder).build(keys)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: BuildHasher> Table<S> {
+    // Build places keys by hash. disp, if present, holds a separate
+    // displacement hash per key; otherwise the bucket hash doubles as one.
+    fn build(
+        hashes: &[u64],
+        disp: Option<&[u64]>,
+        hasher: KeyHasher<S>,
+        params: &Params,
+    ) -> Table<S> {
+        let scratch = &mut Scratch::new();
+        match Table::build_with_stats(hashes, disp, hasher, params, scratch, Run::default()) {
+            Ok((t, _)) => t,
+            Err(_) => unreachable!("nothing to stop the build"),
         }
+    }
 
-        h.sort_by(|a, b| b.len().cmp(&a.len()));
+    // BuildWithStats is build in the given scratch space, also reporting how
+    // the build went. A failed placement is retried on a grown table, up to
+    // MAX_RETRIES times. The build stops early with an error if run says to.
+    fn build_with_stats(
+        hashes: &[u64],
+        disp: Option<&[u64]>,
+        hasher: KeyHasher<S>,
+        params: &Params,
+        scratch: &mut Scratch,
+        mut run: Run,
+    ) -> Result<(Table<S>, BuildStats), BuildError> {
+        run.report(Progress::new(Phase::Sorting, hashes.len()));
+        let nbuckets = params.buckets(hashes.len());
+        let h = &mut scratch.buckets;
+        h.fill(hashes, disp, nbuckets);
+        h.by_size(&mut scratch.order);
 
-        let mut values = vec![0i32; size];
-        let mut seeds = vec![0i32; size];
+        let mut size = params.slots(hashes.len());
+        loop {
+            run.check()?;
+            if let Some((values, seeds, occupied)) = place(
+                hashes,
+                h,
+                &scratch.order,
+                size,
+                params,
+                &mut run,
+                &mut scratch.work,
+            )? {
+                let t = Table {
+                    values,
+                    seeds,
+                    hasher,
+                    len: hashes.len(),
+                    size,
+                    occupied,
+                    info: BuildInfo::new(),
+                };
+                return Ok((t, run.stats));
+            }
+            assert!(
+                run.stats.retries < MAX_RETRIES,
+                "no seed found; are the keys distinct?"
+            );
+            run.stats.retries += 1;
+            size = params.grow(size);
+        }
+    }
 
-        let mut hidx = 0;
+    pub fn len(&self) -> usize {
+        self.len
+    }
 
-        for idx in 0..h.len() {
-            hidx = idx;
-            if h[hidx].len() <= 1 {
-                break;
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // BitsPerKey reports the space used by the seeds and values (or the rank
+    // structure that replaces them).
+    pub fn bits_per_key(&self) -> f64 {
+        let bits = (self.seeds.len() + self.values.len()) * 32
+            + self.occupied.as_ref().map_or(0, RankBits::size_bits);
+        bits as f64 / self.len.max(1) as f64
+    }
+
+    // Slots returns the number of slots the table spreads its keys over.
+    // Queries of a table that isn't minimal return values below it.
+    pub fn slots(&self) -> usize {
+        self.size
+    }
+
+    // MemoryUsage returns the bytes the table holds on the heap: its arrays
+    // as allocated, which may be more than bits_per_key counts when a
+    // recycled array had room to spare, and its build info. The Table
+    // itself and anything the hash builder allocates are left out.
+    pub fn memory_usage(&self) -> usize {
+        (self.seeds.capacity() + self.values.capacity()) * mem::size_of::<i32>()
+            + self.occupied.as_ref().map_or(0, RankBits::heap_bytes)
+            + self.info.version.capacity()
+            + self.info.comment.capacity()
+    }
+
+    // Hasher returns the hash builder the table was built with.
+    pub fn hasher(&self) -> &S {
+        &self.hasher.build
+    }
+
+    // Seed returns the global seed mixed into key hashes (0 if none).
+    pub fn seed(&self) -> u64 {
+        self.hasher.seed
+    }
+
+    // Query looks up an entry in the table and return the index.
+    //
+    // As with HashMap::get, the key may be any borrowed form of the type the
+    // table was built with: Borrow requires both forms to hash identically, so
+    // a table built over String keys can be queried with a &str.
+    pub fn query<K: Hash + ?Sized>(&self, k: &K) -> usize {
+        let (hash, disp) = self.hasher.hash_pair(k);
+        self.lookup(hash, disp)
+    }
+
+    // QueryBatch looks up each of keys, putting its value at the same index
+    // in out. Rather than wait on memory key by key, it hashes a block of
+    // keys and prefetches their seeds, then reads the seeds and prefetches
+    // the values or occupancy bits they point to, then reads those; a table
+    // far bigger than the CPU's caches answers much faster this way than
+    // with query in a loop.
+    pub fn query_batch<K: Hash>(&self, keys: &[K], out: &mut [usize]) {
+        assert_eq!(keys.len(), out.len(), "keys and out differ in length");
+        let mut hashes = [(0u64, 0u64); QUERY_BATCH];
+        let mut located = [Located::Done(0); QUERY_BATCH];
+        for (keys, out) in keys.chunks(QUERY_BATCH).zip(out.chunks_mut(QUERY_BATCH)) {
+            let hashes = &mut hashes[..keys.len()];
+            for (h, k) in hashes.iter_mut().zip(keys) {
+                *h = self.hasher.hash_pair(k);
+                prefetch(&self.seeds, reduce(h.0, self.seeds.len()));
             }
+            for (l, &(hash, disp)) in located.iter_mut().zip(hashes.iter()) {
+                *l = self.locate(hash, disp);
+                match *l {
+                    Located::Done(_) => {}
+                    Located::Value(i) => prefetch(&self.values, i),
+                    Located::Rank(i) => self.occupied.as_ref().unwrap().prefetch(i),
+                }
+            }
+            for (v, &l) in out.iter_mut().zip(located.iter()) {
+                *v = self.resolve(l);
+            }
+        }
+    }
+
+    // QueryBytes looks up a binary key in a table built with from_bytes_keys.
+    pub fn query_bytes<K: AsRef<[u8]> + ?Sized>(&self, k: &K) -> usize {
+        let (hash, disp) = self.hasher.hash_bytes_pair(k.as_ref());
+        self.lookup(hash, disp)
+    }
+
+    // QueryOsStr looks up an OsStr or Path key in a table built with
+    // from_os_str_keys.
+    pub fn query_os_str<K: AsRef<OsStr> + ?Sized>(&self, k: &K) -> usize {
+        self.query_bytes(k.as_ref().as_encoded_bytes())
+    }
+
+    // QueryCStr looks up a NUL-terminated key in a table built with
+    // from_cstr_keys, without UTF-8 validation or copying.
+    pub fn query_cstr(&self, k: &CStr) -> usize {
+        self.query_bytes(k.to_bytes())
+    }
 
-            let subkeys = &h[hidx];
+    // QueryPrehashed looks up a digest in a table built with from_hashes.
+    pub fn query_prehashed(&self, hash: u64) -> usize {
+        self.lookup(hash, hash)
+    }
+
+    // HashKey hashes k as query does, for query_hash.
+    pub fn hash_key<K: Hash + ?Sized>(&self, k: &K) -> KeyHash {
+        let (hash, disp) = self.hasher.hash_pair(k);
+        KeyHash { hash, disp }
+    }
+
+    // QueryHash is query for a key already hashed by hash_key.
+    pub fn query_hash(&self, h: KeyHash) -> usize {
+        self.lookup(h.hash, h.disp)
+    }
+
+    // Prefetch hashes k and starts loading its seed into the cache without
+    // waiting for it, then returns the hash, for query_hash to finish the
+    // lookup later. Callers with their own pipeline can prefetch a key, do
+    // other work while the seed arrives, and query it then, as query_batch
+    // does for a block of keys. The value the seed points to can't be
+    // prefetched before the seed is read.
+    pub fn prefetch<K: Hash + ?Sized>(&self, k: &K) -> KeyHash {
+        let h = self.hash_key(k);
+        self.prefetch_hash(h);
+        h
+    }
+
+    // PrefetchHash is prefetch for a key already hashed by hash_key.
+    pub fn prefetch_hash(&self, h: KeyHash) {
+        prefetch(&self.seeds, reduce(h.hash, self.seeds.len()));
+    }
+
+    // QueryUnchecked is query without the bounds checks on the seeds and
+    // values. The indices a query computes are in range in any table this
+    // crate built or loaded, so the checks only cost time, which a tight
+    // loop of lookups may notice.
+    //
+    // # Safety
+    //
+    // The table must have been built, or loaded by from_bytes, read_from or
+    // load, which check the arrays. Tables deserialized with serde aren't
+    // checked, and if their arrays are inconsistent a query reads out of
+    // bounds.
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn query_unchecked<K: Hash + ?Sized>(&self, k: &K) -> usize {
+        let (hash, disp) = self.hasher.hash_pair(k);
+        let seed = *self.seeds.get_unchecked(reduce(hash, self.seeds.len()));
+        let direct = !seed as usize;
+        let slot = reduce(xorshift_mult64(disp.wrapping_add(seed as u64)), self.size);
+        let neg = (seed >> 31) as usize;
+        let i = (direct & neg) | (slot & !neg);
+        match &self.occupied {
+            Some(_) if seed < 0 => direct,
+            Some(bits) => bits.rank(slot),
+            None if self.values.is_empty() => i,
+            None => *self.values.get_unchecked(i) as usize,
+        }
+    }
+
+    fn lookup(&self, hash: u64, disp: u64) -> usize {
+        self.resolve(self.locate(hash, disp))
+    }
+
+    // Locate reads the seed for a key's hashes and returns what's left to
+    // find its value.
+    #[inline(always)]
+    fn locate(&self, hash: u64, disp: u64) -> Located {
+        let seed = self.seeds[reduce(hash, self.seeds.len())];
+        // A negative seed holds its singleton's slot, or without values its
+        // final value, as !seed. Rather than branch on the sign, which
+        // mispredicts about as often as buckets of one key and of several
+        // alternate, both slots are worked out and one kept by mask. Lookups
+        // in tables that fit in the cache run two to three times as fast.
+        let direct = !seed as usize;
+        let slot = reduce(xorshift_mult64(disp.wrapping_add(seed as u64)), self.size);
+        let neg = (seed >> 31) as usize;
+        let i = (direct & neg) | (slot & !neg);
+        match &self.occupied {
+            Some(_) if seed < 0 => Located::Done(direct),
+            Some(_) => Located::Rank(slot),
+            None if self.values.is_empty() => Located::Done(i),
+            None => Located::Value(i),
+        }
+    }
+
+    #[inline(always)]
+    fn resolve(&self, l: Located) -> usize {
+        match l {
+            Located::Done(v) => v,
+            Located::Value(i) => self.values[i] as usize,
+            Located::Rank(i) => self.occupied.as_ref().unwrap().rank(i),
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<S: BuildHasher + Sync> Table<S> {
+    // ParQuery returns the values of keys, in order, looked up on rayon's
+    // thread pool. Each task takes a run of PAR_QUERY_CHUNK keys and answers
+    // them with query_batch, so the threads overlap their cache misses as
+    // well as their hashing.
+    pub fn par_query<K: Hash + Sync>(&self, keys: &[K]) -> Vec<usize> {
+        use rayon::prelude::*;
+
+        let mut out = vec![0; keys.len()];
+        keys.par_chunks(PAR_QUERY_CHUNK)
+            .zip(out.par_chunks_mut(PAR_QUERY_CHUNK))
+            .for_each(|(keys, out)| self.query_batch(keys, out));
+        out
+    }
+}
+
+// Located is where a key's value is, once its seed has been read.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy)]
+enum Located {
+    // the value itself
+    Done(usize),
+    // at this index in values
+    Value(usize),
+    // the rank of this slot among the occupied ones
+    Rank(usize),
+}
 
-            let mut seed = 0u64;
-            let mut entries: HashMap<usize, i32> = HashMap::new();
+// QUERY_BATCH is how many keys query_batch has in flight: enough for their
+// cache misses to overlap, few enough that the hashes stay in registers or
+// the nearest cache.
+#[cfg(feature = "std")]
+const QUERY_BATCH: usize = 16;
 
-            'newseed: loop {
-                seed += 1;
+// PAR_QUERY_CHUNK is how many keys each of par_query's tasks looks up: enough
+// that scheduling the task costs little next to the lookups.
+#[cfg(feature = "rayon")]
+const PAR_QUERY_CHUNK: usize = 1 << 12;
+
+// prefetch hints that the cache line holding v[i] is about to be read.
+#[cfg(feature = "std")]
+#[inline(always)]
+fn prefetch<T>(v: &[T], i: usize) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+        if let Some(x) = v.get(i) {
+            // safe: a prefetch is only a hint, and x is a valid address
+            unsafe { _mm_prefetch::<_MM_HINT_T0>(x as *const T as *const i8) };
+        }
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = (v, i);
+}
+
+// Placed is a finished placement: the values, seeds and, for minimal tables,
+// ranked occupied slots.
+#[cfg(feature = "std")]
+type Placed = (Vec<i32>, Vec<i32>, Option<RankBits>);
+
+// Place assigns the seeds and values for a table of size slots, or returns
+// None if some bucket has no seed up to max_seed.
+#[cfg(feature = "std")]
+fn place(
+    hashes: &[u64],
+    h: &Buckets,
+    order: &[usize],
+    size: usize,
+    params: &Params,
+    run: &mut Run,
+    w: &mut Work,
+) -> Result<Option<Placed>, BuildError> {
+    let nbuckets = h.offsets.len() - 1;
+    if params.k > 1 {
+        return Ok(
+            place_shared(h, order, size, params, run, w)?.map(|seeds| (Vec::new(), seeds, None))
+        );
+    }
+
+    let mut values = mem::take(&mut w.values);
+    let mut seeds = mem::take(&mut w.seeds);
+    if params.huge_pages {
+        huge::reserve(&mut values, size);
+        huge::reserve(&mut seeds, nbuckets);
+    }
+    reuse(&mut values, size);
+    reuse(&mut seeds, nbuckets);
+
+    let taken = reuse(&mut w.taken, size.div_ceil(64));
+    let is_taken = |taken: &[u64], i: usize| taken[i / 64] & 1 << (i % 64) != 0;
+    let bucket_of = |v: i32| reduce(hashes[v as usize], nbuckets);
+
+    let multi = order.iter().take_while(|&&b| h.get(b).len() > 1).count();
+    let mut p = Progress {
+        buckets: order.len(),
+        ..Progress::new(Phase::Placement, hashes.len())
+    };
+    run.report(p);
+    let queue = reuse(&mut w.queue, 0);
+    queue.extend(order[..multi].iter().rev());
+    let mut evictions = hashes.len();
+
+    // the slots a seed under trial has claimed, as bits, and the keys
+    // claiming them; both are emptied after each trial by undoing the
+    // claims rather than clearing the bitmap
+    let claimed = reuse(&mut w.claimed, size.div_ceil(64));
+    let entries = reuse(&mut w.entries, 0);
+
+    let batch = &mut w.batch;
+    let blockers = &mut w.blockers;
+    while !queue.is_empty() {
+        // the next buckets to place. When there are several, their seeds
+        // are first guessed together, in parallel with rayon, against the
+        // slots taken so far. Placing a bucket then starts from its guess:
+        // the seeds before it already collide, and the other buckets in
+        // the batch only take more slots. The buckets are still placed in
+        // order, each on the first seed that fits, so the table doesn't
+        // depend on the batch size or on how the threads were scheduled.
+        batch.clear();
+        batch.extend((0..params.batch).map_while(|_| queue.pop()));
+        let guesses = if batch.len() > 1 {
+            guess_seeds(h, batch, taken, size, params)
+        } else {
+            Vec::new()
+        };
+
+        for (j, &bucket) in batch.iter().enumerate() {
+            run.check()?;
+            let subkeys = h.get(bucket);
+            let probe = Probe::new(params, subkeys);
+
+            let mut attempt = match guesses.get(j) {
+                Some(&Some(guess)) => guess - 1,
+                Some(None) => params.evict_after,
+                None => 0,
+            };
+
+            let seed = 'newseed: loop {
+                attempt += 1;
+                if attempt > params.max_seed {
+                    return Ok(None);
+                }
+                run.stats.seeds_tried += 1;
+                if attempt.is_multiple_of(CHECK_EVERY) {
+                    run.check()?;
+                }
+                let seed = probe.seed(attempt);
+                // past the threshold, a seed may claim slots other buckets
+                // hold, as long as the bucket doesn't collide with itself
+                let evict = attempt > params.evict_after && evictions > 0;
+                blockers.clear();
                 for k in subkeys.iter() {
-                    let i = (xorshift_mult64(k.hash + seed) as usize) % size;
-                    if entries.get(&i).is_none() && values[i] == 0 {
+                    let i = reduce(xorshift_mult64(k.disp.wrapping_add(seed)), size);
+                    let bit = 1 << (i % 64);
+                    if claimed[i / 64] & bit == 0 && (taken[i / 64] & bit == 0 || evict) {
                         // looks free, claim it
-                        entries.insert(i, k.idx);
+                        if taken[i / 64] & bit != 0 {
+                            blockers.push(bucket_of(values[i]));
+                        }
+                        claimed[i / 64] |= bit;
+                        entries.push((i, k.idx));
                         continue;
                     }
 
                     // found a collision, reset and try a new seed
-                    entries.clear();
+                    for (i, _) in entries.drain(..) {
+                        claimed[i / 64] &= !(1 << (i % 64));
+                    }
                     continue 'newseed;
                 }
 
                 // made it through; everything got placed
-                break;
+                break seed;
+            };
+
+            // cuckoo-style: kick out the buckets in the way and queue them to
+            // be placed again, before the rest of the batch, whose guesses
+            // they make stale
+            if !blockers.is_empty() {
+                queue.extend(batch[j + 1..].iter().rev());
+            }
+            blockers.sort_unstable();
+            blockers.dedup();
+            for &b in blockers.iter() {
+                let old = seeds[b] as u64;
+                for k in h.get(b) {
+                    let i = reduce(xorshift_mult64(k.disp.wrapping_add(old)), size);
+                    // free slots hold 0, as they would had the bucket never
+                    // been placed
+                    values[i] = 0;
+                    taken[i / 64] &= !(1 << (i % 64));
+                }
+                seeds[b] = 0;
+                queue.push(b);
+                p.buckets_placed -= 1;
+                p.keys_placed -= h.get(b).len();
+                evictions = evictions.saturating_sub(1);
+                run.stats.evictions += 1;
             }
 
             // mark subkey spaces as claimed
-            for (&k, &v) in entries.iter() {
-                values[k] = v
+            for (i, v) in entries.drain(..) {
+                values[i] = v as i32;
+                taken[i / 64] |= 1 << (i % 64);
+                claimed[i / 64] &= !(1 << (i % 64));
             }
 
             // and assign this seed value for every subkey
-            let i = (subkeys[0].hash as usize) % size;
-            seeds[i] = seed as i32;
+            seeds[bucket] = seed as i32;
+            p.buckets_placed += 1;
+            p.keys_placed += subkeys.len();
+            if p.buckets_placed.is_multiple_of(REPORT_EVERY) {
+                run.report(p);
+            }
+            if !blockers.is_empty() {
+                break;
+            }
         }
+    }
 
-        // find the unassigned entries in the table
-        let mut free: Vec<usize> = Vec::new();
-        for (i, v) in values.iter_mut().enumerate() {
-            if *v == 0 {
-                free.push(i);
-            } else {
-                // decrement idx as this is now the final value for the table
-                *v -= 1;
+    // with compress_seeds, singletons first look for a small seed landing in
+    // a free slot, in order, and only those that find none are placed
+    // directly below; until then a seed of 0 marks them
+    if params.compress_seeds {
+        for &b in &order[multi..] {
+            let k = &h.get(b)[0];
+            let slot = |seed: u64| reduce(xorshift_mult64(k.disp.wrapping_add(seed)), size);
+            let free = (1..=SMALL_SEEDS).find(|&seed| !is_taken(taken, slot(seed)));
+            if let Some(seed) = free {
+                let i = slot(seed);
+                values[i] = k.idx as i32;
+                taken[i / 64] |= 1 << (i % 64);
+                seeds[b] = seed as i32;
             }
         }
+    }
+
+    // find the unassigned entries in the table. Nothing in placement depends
+    // on hashing or allocation order: buckets go by size then index, and the
+    // singletons take these slots from the top down, so the same hashes and
+    // params always give the same table
+    let free = reuse(&mut w.free, 0);
+    free.extend((0..size).filter(|&i| !is_taken(taken, i)));
+
+    p.phase = Phase::PatchUp;
+    run.report(p);
+    for &i in &order[multi..] {
+        if seeds[i] != 0 {
+            // seeded above
+            continue;
+        }
+        let k = &h.get(i)[0];
+
+        // take a free slot
+        let dst = free.pop().unwrap();
+        values[dst] = k.idx as i32;
+        taken[dst / 64] |= 1 << (dst % 64);
+
+        // store offset in seed as a negative; -1 so even slot 0 is negative
+        seeds[i] = -(dst as i32 + 1);
+    }
+    p.buckets_placed = order.len();
+    p.keys_placed = hashes.len();
+    run.report(p);
 
-        while hidx < h.len() && !h[hidx].is_empty() {
-            let k = &h[hidx][0];
-            let i = (k.hash as usize) % size;
-            hidx += 1;
+    if !params.minimal {
+        return Ok(Some((values, seeds, None)));
+    }
+
+    // ranking the taken slots numbers the keys 0..n, so singletons can store
+    // their final value
+    let occupied = RankBits::new(taken.clone());
+    for seed in seeds.iter_mut().filter(|s| **s < 0) {
+        *seed = -(occupied.rank((-*seed - 1) as usize) as i32 + 1);
+    }
 
-            // take a free slot
-            let dst = free.pop().unwrap();
+    w.values = values;
+    Ok(Some((Vec::new(), seeds, Some(occupied))))
+}
 
-            // claim it; -1 because of the +1 at the start
-            values[dst] = k.idx - 1;
+// GuessSeeds finds, for each bucket in batch, the first attempt whose seed
+// places its keys in slots free in taken, without evicting; None if there is
+// none before evictions start.
+#[cfg(feature = "std")]
+fn guess_seeds(
+    h: &Buckets,
+    batch: &[usize],
+    taken: &[u64],
+    size: usize,
+    params: &Params,
+) -> Vec<Option<u64>> {
+    let guess = |&b: &usize| {
+        let keys = h.get(b);
+        let probe = Probe::new(params, keys);
+        let mut slots = Vec::with_capacity(keys.len());
+        (1..=params.evict_after).find(|&attempt| {
+            let seed = probe.seed(attempt);
+            slots.clear();
+            keys.iter().all(|k| {
+                let i = reduce(xorshift_mult64(k.disp.wrapping_add(seed)), size);
+                let free = taken[i / 64] & 1 << (i % 64) == 0 && !slots.contains(&i);
+                slots.push(i);
+                free
+            })
+        })
+    };
 
-            // store offset in seed as a negative; -1 so even slot 0 is negative
-            seeds[i] = -(dst as i32 + 1);
+    #[cfg(feature = "rayon")]
+    use rayon::prelude::*;
+    #[cfg(feature = "rayon")]
+    let guesses = batch.par_iter().map(guess).collect();
+    #[cfg(not(feature = "rayon"))]
+    let guesses = batch.iter().map(guess).collect();
+    guesses
+}
+
+// PlaceShared assigns seeds for a k-perfect table, where up to params.k keys
+// may share a slot, or returns None if some bucket has no seed up to
+// max_seed. order lists the non-empty buckets largest first.
+#[cfg(feature = "std")]
+fn place_shared(
+    h: &Buckets,
+    order: &[usize],
+    size: usize,
+    params: &Params,
+    run: &mut Run,
+    w: &mut Work,
+) -> Result<Option<Vec<i32>>, BuildError> {
+    let k = params.k;
+    let load = reuse(&mut w.load, size);
+    let mut seeds = mem::take(&mut w.seeds);
+    if params.huge_pages {
+        huge::reserve(&mut seeds, h.offsets.len() - 1);
+    }
+    reuse(&mut seeds, h.offsets.len() - 1);
+    let slots = &mut w.slots;
+
+    let mut p = Progress {
+        buckets: order.len(),
+        ..Progress::new(Phase::Placement, h.entries.len())
+    };
+    let mut next = 0;
+    for &i in order {
+        let subkeys = h.get(i);
+        if p.buckets_placed.is_multiple_of(REPORT_EVERY) {
+            run.report(p);
+        }
+        p.buckets_placed += 1;
+        p.keys_placed += subkeys.len();
+
+        if subkeys.len() == 1 {
+            // any slot with room will do
+            while load[next] == k {
+                next += 1;
+            }
+            load[next] += 1;
+            seeds[i] = -(next as i32 + 1);
+            continue;
         }
 
-        Table { values, seeds }
+        run.check()?;
+        let probe = Probe::new(params, subkeys);
+        let mut attempt = 0u64;
+        let seed = loop {
+            attempt += 1;
+            if attempt > params.max_seed {
+                return Ok(None);
+            }
+            run.stats.seeds_tried += 1;
+            if attempt.is_multiple_of(CHECK_EVERY) {
+                run.check()?;
+            }
+            let seed = probe.seed(attempt);
+            slots.clear();
+            let fits = subkeys.iter().all(|e| {
+                let s = reduce(xorshift_mult64(e.disp.wrapping_add(seed)), size);
+                slots.push(s);
+                load[s] + slots.iter().filter(|&&t| t == s).count() <= k
+            });
+            if fits {
+                break seed;
+            }
+        };
+
+        for &s in slots.iter() {
+            load[s] += 1;
+        }
+        seeds[i] = seed as i32;
     }
+    run.report(p);
+    Ok(Some(seeds))
+}
 
-    // Query looks up an entry in the table and return the index.
-    pub fn query(&self, k: &str) -> usize {
-        let size = self.values.len();
-        let hash = calculate_hash(&k.to_string()) as u64;
-        let i = hash & (size as u64 - 1);
-        let seed = self.seeds[i as usize];
-        if seed < 0 {
-            return self.values[(-seed - 1) as usize] as usize;
+// FromIterator builds a table from a stream of keys, buffering only their
+// hashes; see TableBuilder::build_iter.
+#[cfg(feature = "std")]
+impl<K: Hash, S: BuildHasher + Clone + Default> FromIterator<K> for Table<S> {
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Table<S> {
+        TableBuilder::new()
+            .with_hasher(S::default())
+            .build_iter(iter)
+    }
+}
+
+// U64Table is a table over integer keys. Keys are mixed directly with
+// xorshift_mult64, skipping the Hasher machinery on both build and query.
+#[cfg(feature = "std")]
+pub struct U64Table {
+    table: Table,
+}
+
+#[cfg(feature = "std")]
+impl U64Table {
+    pub fn new(keys: &[u64]) -> U64Table {
+        // xorshift_mult64 is a bijection, so distinct keys get distinct hashes
+        let hashes: Vec<u64> = keys.iter().map(|&k| xorshift_mult64(k)).collect();
+        U64Table {
+            table: Table::build(&hashes, None, KeyHasher::default(), &Params::default()),
+        }
+    }
+
+    // Query looks up an integer key in the table and returns the index.
+    pub fn query(&self, k: u64) -> usize {
+        let hash = xorshift_mult64(k);
+        self.table.lookup(hash, hash)
+    }
+}
+
+// ArrayTable is a table over fixed-size byte array keys such as IPv4/IPv6
+// addresses or UUIDs. Keys are read as little-endian u64 words and mixed with
+// fmix64, which is much cheaper than a general purpose Hasher.
+#[cfg(feature = "std")]
+pub struct ArrayTable<const N: usize> {
+    table: Table,
+}
+
+#[cfg(feature = "std")]
+impl<const N: usize> ArrayTable<N> {
+    pub fn new(keys: &[[u8; N]]) -> ArrayTable<N> {
+        let hashes: Vec<u64> = keys.iter().map(hash_array).collect();
+        ArrayTable {
+            table: Table::build(&hashes, None, KeyHasher::default(), &Params::default()),
         }
+    }
+
+    // Query looks up an array key in the table and returns the index.
+    pub fn query(&self, k: &[u8; N]) -> usize {
+        let hash = hash_array(k);
+        self.table.lookup(hash, hash)
+    }
+}
 
-        let i = xorshift_mult64(seed as u64 + hash) & (size as u64 - 1);
-        self.values[i as usize] as usize
+// hash_array folds the key one word at a time; keys of up to 8 bytes map
+// through a bijection and so can never collide. fmix64 rather than
+// xorshift_mult64 because addresses tend to differ only in a few bytes, and the
+// low bits picking the bucket need to depend on all of them.
+#[cfg(feature = "std")]
+fn hash_array<const N: usize>(k: &[u8; N]) -> u64 {
+    let mut h = 0u64;
+    for chunk in k.chunks(8) {
+        let mut word = [0u8; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        h = fmix64(h ^ u64::from_le_bytes(word));
     }
+    h
+}
+
+// reduce maps x onto 0..n: by masking when n is a power of two, which keeps
+// the low bits, and otherwise by fastrange's multiply-shift, which keeps the
+// high ones.
+fn reduce(x: u64, n: usize) -> usize {
+    if n.is_power_of_two() {
+        (x & (n as u64 - 1)) as usize
+    } else {
+        ((x as u128 * n as u128) >> 64) as usize
+    }
+}
+
+// fmix64 is the MurmurHash3 finalizer, a bijection with full avalanche.
+#[cfg(feature = "std")]
+fn fmix64(x: u64) -> u64 {
+    let mut x = x;
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    x ^= x >> 33;
+    x
 }
 
 fn xorshift_mult64(x: u64) -> u64 {
@@ -128,16 +1373,10 @@ fn xorshift_mult64(x: u64) -> u64 {
     x = x ^ (x >> 12); // a
     x ^= x << 25; // b
     x ^= x >> 27; // c
-    x.wrapping_mul(2_685_821_657_736_338_717 as u64)
-}
-
-fn calculate_hash<T: Hash>(t: &T) -> u64 {
-    let mut s = DefaultHasher::new();
-    t.hash(&mut s);
-    s.finish()
+    x.wrapping_mul(2_685_821_657_736_338_717_u64)
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use super::*;
 
@@ -151,4 +1390,542 @@ mod tests {
             assert_eq!(t.query(k), i);
         }
     }
+
+    #[test]
+    fn bytes_keys() {
+        let keys: Vec<&[u8]> = vec![b"\x00\x01", b"\xff\xfe\xfd", b"", b"\x80", b"foo"];
+
+        let t = Table::from_bytes_keys(&keys);
+
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query_bytes(k), i);
+        }
+    }
+
+    #[test]
+    fn os_str_keys() {
+        use std::path::{Path, PathBuf};
+
+        let keys: Vec<PathBuf> = vec![
+            PathBuf::from("/etc/passwd"),
+            PathBuf::from("src/lib.rs"),
+            PathBuf::from("src//lib.rs"),
+            PathBuf::from(""),
+        ];
+
+        let t = Table::from_os_str_keys(&keys);
+
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query_os_str(k), i);
+        }
+        assert_eq!(t.query_os_str(Path::new("/etc/passwd")), 0);
+        assert_eq!(t.query_os_str(OsStr::new("src/lib.rs")), 1);
+    }
+
+    #[test]
+    fn prehashed_keys() {
+        let hashes: Vec<u64> = (0..500u64)
+            .map(|i| xorshift_mult64(i ^ 0xdead_beef))
+            .collect();
+
+        let t = Table::from_hashes(&hashes);
+
+        for (i, &h) in hashes.iter().enumerate() {
+            assert_eq!(t.query_prehashed(h), i);
+        }
+    }
+
+    #[test]
+    fn owned_keys() {
+        let text = "alpha\nbeta\ngamma\ndelta\n";
+        let keys: Vec<String> = text.lines().map(String::from).collect();
+
+        let t = Table::new(&keys);
+
+        for (i, line) in text.lines().enumerate() {
+            assert_eq!(t.query(line), i);
+        }
+    }
+
+    #[test]
+    fn by_key() {
+        struct Record {
+            name: String,
+            age: u32,
+        }
+
+        let records: Vec<Record> = ["ann", "bob", "cat"]
+            .iter()
+            .enumerate()
+            .map(|(i, n)| Record {
+                name: n.to_string(),
+                age: i as u32 * 10,
+            })
+            .collect();
+
+        let t = Table::new_by_key(&records, |r| &r.name);
+
+        assert_eq!(records[t.query("bob")].age, 10);
+        assert_eq!(records[t.query("cat")].age, 20);
+
+        let t = Table::new_by_key(&records, |r| r.name.as_str());
+        assert_eq!(t.query("ann"), 0);
+    }
+
+    #[test]
+    fn string_forms() {
+        use std::borrow::Cow;
+        use std::sync::Arc;
+
+        let keys: Vec<Cow<str>> = vec![Cow::Borrowed("one"), Cow::Owned("two".to_string())];
+        let t = Table::new(&keys);
+        assert_eq!(t.query("one"), 0);
+        assert_eq!(t.query(&Arc::<str>::from("two")), 1);
+        assert_eq!(t.query(&Box::<str>::from("one")), 0);
+
+        let keys: Vec<Vec<u8>> = vec![vec![1, 2, 3], vec![4]];
+        let t = Table::from_bytes_keys(&keys);
+        assert_eq!(t.query_bytes(&[1, 2, 3]), 0);
+        assert_eq!(t.query_bytes(&vec![4]), 1);
+        assert_eq!(t.query_bytes("\u{4}"), 1);
+    }
+
+    #[test]
+    fn custom_hasher() {
+        use std::collections::hash_map::RandomState;
+
+        let keys = vec!["foo", "bar", "baz", "qux", "zot", "frob", "zork", "zeek"];
+
+        let t = Table::with_hasher(&keys, RandomState::new());
+
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+        }
+    }
+
+    #[test]
+    fn keyed_siphash() {
+        use hash::SipState;
+
+        let keys: Vec<String> = (0..1000).map(|i| format!("user{}", i)).collect();
+
+        let t = Table::with_hasher(&keys, SipState::random());
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+        }
+
+        let (k0, k1) = t.hasher().keys();
+        let u = Table::with_hasher(&keys, SipState::new_with_keys(k0, k1));
+        assert_eq!(u.seeds, t.seeds);
+        assert_eq!(u.values, t.values);
+    }
+
+    #[test]
+    fn from_iter() {
+        let t: Table = (0..1000).map(|i| format!("key-{}", i)).collect();
+
+        for i in 0..1000 {
+            assert_eq!(t.query(&format!("key-{}", i)), i);
+        }
+    }
+
+    #[test]
+    fn cstr_keys() {
+        use std::ffi::CString;
+
+        let keys: Vec<CString> = vec![
+            CString::new("foo").unwrap(),
+            CString::new(vec![0xffu8, 0xfe]).unwrap(),
+            CString::new("").unwrap(),
+        ];
+
+        let t = Table::from_cstr_keys(&keys);
+
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query_cstr(k), i);
+        }
+        assert_eq!(t.query_bytes(b"foo"), 0);
+    }
+
+    #[test]
+    fn u64_keys() {
+        let mut keys: Vec<u64> = (0..1000).collect();
+        keys.extend((1..1000).map(|i| i << 40));
+        keys.push(u64::MAX);
+
+        let t = U64Table::new(&keys);
+
+        for (i, &k) in keys.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+        }
+    }
+
+    #[test]
+    fn borrowed_query() {
+        let keys: Vec<String> = vec!["foo".to_string(), "bar".to_string(), "baz".to_string()];
+
+        let t = Table::new(&keys);
+
+        assert_eq!(t.query("foo"), 0);
+        assert_eq!(t.query("bar"), 1);
+        assert_eq!(t.query(&keys[2]), 2);
+
+        let keys: Vec<Vec<u8>> = vec![vec![1, 2], vec![3], vec![]];
+        let t = Table::new(&keys);
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query(k.as_slice()), i);
+        }
+    }
+
+    #[test]
+    fn tuple_keys() {
+        let keys: Vec<(u32, String)> = vec![
+            (1, "alice".to_string()),
+            (1, "bob".to_string()),
+            (2, "alice".to_string()),
+            (12, "".to_string()),
+            (1, "2alice".to_string()),
+        ];
+
+        let t = Table::new(&keys);
+
+        assert_eq!(t.query(&(1u32, "alice")), 0);
+        assert_eq!(t.query(&(1u32, "bob")), 1);
+        assert_eq!(t.query(&(2u32, "alice")), 2);
+        assert_eq!(t.query(&(12u32, "")), 3);
+        assert_eq!(t.query(&(1u32, "2alice")), 4);
+    }
+
+    #[test]
+    fn array_keys() {
+        let ips: Vec<[u8; 4]> = (0..1000u32)
+            .map(|i| (0x0a00_0000 | i).to_be_bytes())
+            .collect();
+        let t = ArrayTable::new(&ips);
+        for (i, k) in ips.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+        }
+
+        let uuids: Vec<[u8; 16]> = (0..1000u128).map(|i| (i << 64 | i).to_le_bytes()).collect();
+        let t = ArrayTable::new(&uuids);
+        for (i, k) in uuids.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+        }
+    }
+
+    #[test]
+    fn generic_keys() {
+        let keys: Vec<u32> = (0..100).map(|i| i * 7).collect();
+        let t = Table::new(&keys);
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+        }
+
+        let keys = vec![(1, 'a'), (1, 'b'), (2, 'a'), (3, 'c')];
+        let t = Table::new(&keys);
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+        }
+
+        #[derive(Hash)]
+        struct Point {
+            x: i64,
+            y: i64,
+        }
+
+        let keys: Vec<Point> = (0..20).map(|i| Point { x: i, y: -i }).collect();
+        let t = Table::new(&keys);
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+        }
+    }
+
+    #[test]
+    fn evictions() {
+        // evict on every bucket's second seed, so that placement leans on the
+        // cuckoo fallback throughout
+        let hashes: Vec<u64> = (0..5000u64).map(fmix64).collect();
+        let params = Params {
+            lambda: 4.0,
+            evict_after: 1,
+            ..Params::default()
+        };
+        let t: Table = Table::build(&hashes, None, KeyHasher::default(), &params);
+        for (i, &h) in hashes.iter().enumerate() {
+            assert_eq!(t.query_prehashed(h), i);
+        }
+    }
+
+    #[test]
+    fn growth() {
+        // with only two seeds to try per bucket, placement keeps failing
+        // until the table has grown roomy enough
+        let hashes: Vec<u64> = (0..1000u64).map(fmix64).collect();
+        for params in [
+            Params {
+                lambda: 4.0,
+                max_seed: 2,
+                ..Params::default()
+            },
+            Params {
+                k: 4,
+                fastrange: true,
+                max_seed: 2,
+                ..Params::default()
+            },
+        ] {
+            let (t, stats): (Table, _) = Table::build_with_stats(
+                &hashes,
+                None,
+                KeyHasher::default(),
+                &params,
+                &mut Scratch::new(),
+                Run::default(),
+            )
+            .unwrap();
+            assert!(stats.retries > 0);
+            let mut size = params.slots(hashes.len());
+            for _ in 0..stats.retries {
+                size = params.grow(size);
+            }
+            assert_eq!(t.size, size);
+
+            let mut load = vec![0; t.size];
+            for (i, &h) in hashes.iter().enumerate() {
+                let v = t.query_prehashed(h);
+                if params.k == 1 {
+                    assert_eq!(v, i);
+                }
+                load[v] += 1;
+            }
+            assert!(load.iter().all(|&l| l <= params.k));
+        }
+    }
+
+    #[test]
+    fn memory_usage() {
+        let keys: Vec<u64> = (0..10000).collect();
+        for b in [
+            TableBuilder::new(),
+            TableBuilder::new().minimal(true),
+            TableBuilder::new().k(4),
+        ] {
+            let t = b.build(&keys);
+            assert_eq!(t.len(), keys.len());
+            assert!(t.slots() >= keys.len() / 4);
+            let bytes = t.bits_per_key() * t.len() as f64 / 8.0;
+            assert!(t.memory_usage() as f64 >= bytes);
+            assert!((t.memory_usage() as f64) < bytes * 1.1 + 64.0);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "are the keys distinct?")]
+    fn duplicates() {
+        Table::from_hashes(&[1, 2, 3, 2]);
+    }
+
+    #[test]
+    fn batches() {
+        // guessing seeds a batch of buckets at a time, as build_par does,
+        // makes the same table as placing them one by one, evictions
+        // included, whatever the batch size and so the number of threads
+        let hashes: Vec<u64> = (0..5000u64).map(fmix64).collect();
+        for params in [
+            Params::default(),
+            Params {
+                lambda: 4.0,
+                minimal: true,
+                ..Params::default()
+            },
+            Params {
+                lambda: 4.0,
+                evict_after: 2,
+                ..Params::default()
+            },
+            Params {
+                lambda: 4.0,
+                shuffle: true,
+                ..Params::default()
+            },
+        ] {
+            let want: Table = Table::build(&hashes, None, KeyHasher::default(), &params);
+            for batch in [2, 3, 64, 1000] {
+                let batched = Params { batch, ..params };
+                let t: Table = Table::build(&hashes, None, KeyHasher::default(), &batched);
+                assert_eq!(t.seeds, want.seeds);
+                assert_eq!(t.values, want.values);
+            }
+        }
+    }
+
+    #[test]
+    fn query_hash() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("k{}", i)).collect();
+
+        for b in [
+            TableBuilder::new(),
+            TableBuilder::new().seed(9).second_hash(true).minimal(true),
+        ] {
+            let t = b.build(&keys);
+            let hashes: Vec<KeyHash> = keys.iter().map(|k| t.hash_key(k)).collect();
+            for (k, &h) in keys.iter().zip(&hashes) {
+                assert_eq!(t.query_hash(h), t.query(k));
+            }
+
+            // the hashes still work after a rebuild with the same options
+            let u = b.build(&keys[500..]);
+            assert_eq!(u.query_hash(hashes[507]), u.query(&keys[507]));
+        }
+    }
+
+    #[test]
+    fn prefetch() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("k{}", i)).collect();
+        let t = TableBuilder::new().second_hash(true).build(&keys);
+        let hashes: Vec<KeyHash> = keys.iter().map(|k| t.prefetch(k)).collect();
+        for (i, &h) in hashes.iter().enumerate() {
+            t.prefetch_hash(h);
+            assert_eq!(t.query_hash(h), i);
+        }
+    }
+
+    #[test]
+    fn query_unchecked() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("k{}", i)).collect();
+        let others: Vec<String> = (0..100).map(|i| format!("other{}", i)).collect();
+
+        for t in [
+            Table::new(&keys),
+            TableBuilder::new().minimal(true).build(&keys),
+            TableBuilder::new().k(4).fastrange(true).build(&keys),
+            Table::from_bytes(&Table::new(&keys).to_bytes()).unwrap(),
+        ] {
+            for k in keys.iter().chain(&others) {
+                // safe: the tables were built or loaded
+                assert_eq!(unsafe { t.query_unchecked(k) }, t.query(k));
+            }
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_query() {
+        let keys: Vec<String> = (0..10000).map(|i| format!("k{}", i)).collect();
+        for t in [
+            Table::new(&keys),
+            TableBuilder::new().minimal(true).build(&keys),
+        ] {
+            let want: Vec<usize> = keys.iter().map(|k| t.query(k)).collect();
+            assert_eq!(t.par_query(&keys), want);
+            assert_eq!(t.par_query(&keys[..5]), want[..5]);
+        }
+        assert!(Table::new(&keys).par_query::<String>(&[]).is_empty());
+    }
+
+    #[test]
+    fn query_batch() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("k{}", i)).collect();
+        let others: Vec<String> = (0..100).map(|i| format!("other{}", i)).collect();
+
+        for t in [
+            Table::new(&keys),
+            TableBuilder::new().minimal(true).lambda(4.0).build(&keys),
+            TableBuilder::new().k(4).second_hash(true).build(&keys),
+        ] {
+            // a length that isn't a multiple of the batch
+            for keys in [&keys[..], &keys[..37], &others[..]] {
+                let mut out = vec![0; keys.len()];
+                t.query_batch(keys, &mut out);
+                let want: Vec<usize> = keys.iter().map(|k| t.query(k)).collect();
+                assert_eq!(out, want);
+            }
+        }
+    }
+
+    #[test]
+    fn shuffle() {
+        // each max_seed's probe order is a permutation of 1..=max_seed
+        for max_seed in [1, 2, 3, 1000, 1 << 10] {
+            let params = Params {
+                shuffle: true,
+                max_seed,
+                ..Params::default()
+            };
+            for disp in [0, 7, u64::MAX] {
+                let probe = Probe::new(&params, &[Entry { idx: 0, disp }]);
+                let mut seeds: Vec<u64> = (1..=max_seed).map(|a| probe.seed(a)).collect();
+                seeds.sort_unstable();
+                assert!(seeds.iter().copied().eq(1..=max_seed));
+            }
+        }
+
+        let keys: Vec<String> = (0..10000).map(|i| format!("k{}", i)).collect();
+        for (b, max) in [
+            (TableBuilder::new().shuffle_seeds(true).lambda(5.0), 1),
+            (TableBuilder::new().shuffle_seeds(true).minimal(true), 1),
+            (TableBuilder::new().shuffle_seeds(true).k(4), 4),
+        ] {
+            let t = b.build(&keys);
+            let mut load = vec![0; t.size];
+            for k in &keys {
+                load[t.query(k)] += 1;
+            }
+            assert!(load.iter().all(|&l| l <= max));
+        }
+    }
+
+    #[test]
+    fn reproducible() {
+        // tables pinned by a fingerprint of their arrays, so a change to
+        // placement that alters what a given input builds doesn't go unnoticed
+        let fingerprint = |t: &Table| {
+            let words = t.seeds.iter().chain(&t.values).map(|&v| v as u64);
+            words.fold(0u64, |acc, v| fmix64(acc ^ v))
+        };
+        let hashes: Vec<u64> = (0..5000u64).map(fmix64).collect();
+        for (params, want) in [
+            (Params::default(), 2444234075079793120),
+            (
+                Params {
+                    lambda: 4.0,
+                    evict_after: 2,
+                    ..Params::default()
+                },
+                14721253457213154995,
+            ),
+        ] {
+            let t: Table = Table::build(&hashes, None, KeyHasher::default(), &params);
+            assert_eq!(fingerprint(&t), want);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("key{}", i)).collect();
+
+        for t in [
+            TableBuilder::new().seed(7).build(&keys),
+            TableBuilder::new().minimal(true).build(&keys),
+        ] {
+            let json = serde_json::to_string(&t).unwrap();
+            let u: Table = serde_json::from_str(&json).unwrap();
+            for k in &keys {
+                assert_eq!(u.query(k), t.query(k));
+            }
+        }
+
+        let t = TableBuilder::new()
+            .normalize(|s| s.trim().into())
+            .build(&keys);
+        assert!(serde_json::to_string(&t).is_err());
+
+        // str::to_lowercase folding needs the unicode-case feature
+        let t = TableBuilder::new().case_insensitive(true).build(&keys);
+        let json = serde_json::to_string(&t).unwrap();
+        let json = json.replace("[false,true,false]", "[false,true,true]");
+        let u = serde_json::from_str::<Table>(&json);
+        assert_eq!(u.is_ok(), cfg!(feature = "unicode-case"));
+    }
 }
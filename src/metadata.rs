@@ -0,0 +1,186 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::format::{FormatError, Reader};
+use crate::hash::{HashAlgo, StoredHasher};
+use crate::Table;
+
+// BuildInfo is what a table records about its own construction, stored in the
+// serialized metadata block:
+//
+//   builder   u8 length, then the crate version that built the table
+//   built_at  u64      seconds since the Unix epoch, 0 if not recorded
+//   comment   u32 length, then UTF-8 text, empty if none
+//
+// Fields added later go at the end, where older readers skip them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+pub(crate) struct BuildInfo {
+    // empty for tables loaded from before format version 3
+    pub(crate) version: String,
+    pub(crate) built_at: u64,
+    pub(crate) comment: String,
+}
+
+impl BuildInfo {
+    // New is the info of a table built by this crate now, without a time or
+    // comment.
+    pub(crate) fn new() -> BuildInfo {
+        BuildInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            ..BuildInfo::default()
+        }
+    }
+
+    pub(crate) fn encoded_len(&self) -> usize {
+        1 + self.version.len() + 8 + 4 + self.comment.len()
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.encoded_len());
+        buf.push(self.version.len() as u8);
+        buf.extend_from_slice(self.version.as_bytes());
+        buf.extend_from_slice(&self.built_at.to_le_bytes());
+        buf.extend_from_slice(&(self.comment.len() as u32).to_le_bytes());
+        buf.extend_from_slice(self.comment.as_bytes());
+        buf
+    }
+
+    // Decode reads a metadata block; an empty one, as in files from before
+    // format version 3, records nothing.
+    pub(crate) fn decode(block: &[u8]) -> Result<BuildInfo, FormatError> {
+        if block.is_empty() {
+            return Ok(BuildInfo::default());
+        }
+        let mut r = Reader { buf: block };
+        let n = r.int(1)? as usize;
+        let version = text(r.take(n)?)?;
+        let built_at = r.int(8)?;
+        let n = r.int(4)? as usize;
+        let comment = text(r.take(n)?)?;
+        Ok(BuildInfo {
+            version,
+            built_at,
+            comment,
+        })
+    }
+}
+
+fn text(b: &[u8]) -> Result<String, FormatError> {
+    String::from_utf8(b.to_vec()).map_err(|_| FormatError::Invalid("metadata text"))
+}
+
+// Metadata describes a table and how it was built, for tools that need to
+// tell what a table file is without the code or keys that made it.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct Metadata {
+    // keys in the table
+    pub len: usize,
+    // the hash function family, None for a StoredHasher from outside the crate
+    // whose id HashAlgo doesn't know; a keyed SipState reports Sip13
+    pub hash_algo: Option<HashAlgo>,
+    // keys per slot
+    pub load_factor: f64,
+    // the crate version that built the table, unknown for files written
+    // before format version 3
+    pub builder_version: Option<String>,
+    // when the table was built, if TableBuilder::record_time was set
+    pub built_at: Option<SystemTime>,
+    // the text set by TableBuilder::comment
+    pub comment: Option<String>,
+}
+
+impl<S: StoredHasher> Table<S> {
+    // Metadata returns the table's build metadata. It is saved with the table,
+    // so a table loaded from a file reports how that file was built.
+    pub fn metadata(&self) -> Metadata {
+        let info = &self.info;
+        let nonempty = |s: &String| (!s.is_empty()).then(|| s.clone());
+        Metadata {
+            len: self.len,
+            hash_algo: HashAlgo::from_id(self.hasher.build.algo_id()),
+            load_factor: self.len as f64 / self.size as f64,
+            builder_version: nonempty(&info.version),
+            built_at: (info.built_at != 0).then(|| UNIX_EPOCH + Duration::from_secs(info.built_at)),
+            comment: nonempty(&info.comment),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::{SipHasher13, SipState};
+    use crate::TableBuilder;
+    use std::hash::BuildHasher;
+
+    #[test]
+    fn metadata() {
+        let keys: Vec<String> = (0..100).map(|i| format!("k{}", i)).collect();
+
+        let m = Table::new(&keys).metadata();
+        assert_eq!(m.len, 100);
+        assert_eq!(m.hash_algo, Some(HashAlgo::Sip13));
+        assert_eq!(m.load_factor, 100.0 / 128.0);
+        assert_eq!(
+            m.builder_version.as_deref(),
+            Some(env!("CARGO_PKG_VERSION"))
+        );
+        assert_eq!(m.built_at, None);
+        assert_eq!(m.comment, None);
+
+        let before = SystemTime::now() - Duration::from_secs(1);
+        let t = TableBuilder::new()
+            .hash_algo(HashAlgo::Fx)
+            .record_time(true)
+            .comment("nightly keyword export")
+            .build(&keys);
+        let u = Table::<HashAlgo>::from_bytes(&t.to_bytes()).unwrap();
+        let m = u.metadata();
+        assert_eq!(m, t.metadata());
+        assert_eq!(m.hash_algo, Some(HashAlgo::Fx));
+        assert!(m.built_at.unwrap() >= before);
+        assert_eq!(m.comment.as_deref(), Some("nightly keyword export"));
+
+        // StoredHasher is open to other crates, whose ids HashAlgo can't name
+        #[derive(Clone)]
+        struct Custom(SipState);
+        impl BuildHasher for Custom {
+            type Hasher = SipHasher13;
+            fn build_hasher(&self) -> SipHasher13 {
+                self.0.build_hasher()
+            }
+        }
+        impl StoredHasher for Custom {
+            fn algo_id(&self) -> u8 {
+                200
+            }
+            fn params(&self) -> [u64; 2] {
+                [0, 0]
+            }
+            fn from_stored(id: u8, params: [u64; 2]) -> Option<Custom> {
+                (id == 200 && params == [0, 0]).then(|| Custom(SipState::default()))
+            }
+        }
+        let t = TableBuilder::new()
+            .with_hasher(Custom(SipState::default()))
+            .build(&keys);
+        assert_eq!(t.metadata().hash_algo, None);
+
+        assert_eq!(BuildInfo::decode(&[]), Ok(BuildInfo::default()));
+        let mut block = BuildInfo::new().encode();
+        block[1] = 0xff;
+        assert_eq!(
+            BuildInfo::decode(&block),
+            Err(FormatError::Invalid("metadata text"))
+        );
+        // fields from later versions are skipped
+        let mut block = BuildInfo::new().encode();
+        block.push(1);
+        assert_eq!(BuildInfo::decode(&block), Ok(BuildInfo::new()));
+    }
+}
@@ -0,0 +1,300 @@
+// memmap2 support: a table file queried in place from a memory map, and a
+// table with only its values array mapped.
+
+use std::convert::{TryFrom, TryInto};
+use std::fs::File;
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, Write};
+use std::path::Path;
+use std::slice;
+
+use memmap2::Mmap;
+
+use crate::hash::{DefaultState, StoredHasher};
+use crate::{FormatError, Table, TableRef};
+
+// SEEDS_MAGIC starts the seeds part written by Table::write_hybrid, which
+// goes on with the number of values split out, a little-endian u64, and
+// then the table without them as to_bytes writes it. VALUES_MAGIC starts the
+// values file, which goes on with the same count and then the values as
+// little-endian i32s.
+const SEEDS_MAGIC: &[u8; 4] = b"MPHS";
+const VALUES_MAGIC: &[u8; 4] = b"MPHV";
+const HYBRID_HEADER_LEN: usize = 4 + 8;
+
+// MappedTable is a table file memory-mapped by Table::open_mmap. Opening it
+// reads only the header; the arrays' pages are read in as queries touch them,
+// and stay in the page cache, shared by every process that maps the file.
+pub struct MappedTable<S = DefaultState> {
+    // table borrows from map, so it is declared, and dropped, first
+    table: TableRef<'static, S>,
+    _map: Mmap,
+}
+
+impl<S: StoredHasher> Table<S> {
+    // OpenMmap maps the table file at path, written by save or write_to, and
+    // checks it as TableRef::from_bytes_unverified does: the checksum isn't
+    // verified, since that would read every page of the file.
+    //
+    // # Safety
+    //
+    // The file must not be modified or truncated while it is mapped, or
+    // queries may return wrong values or crash the process. Replacing it with
+    // save is fine, since save renames a new file over the old one and the
+    // map keeps the old one.
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn open_mmap<P: AsRef<Path>>(path: P) -> io::Result<MappedTable<S>> {
+        let map = Mmap::map(&File::open(path)?)?;
+        // The map's bytes stay put when the Mmap moves and live until it is
+        // dropped, after table.
+        let bytes = slice::from_raw_parts(map.as_ptr(), map.len());
+        let table = TableRef::from_bytes_unverified(bytes)?;
+        Ok(MappedTable { table, _map: map })
+    }
+}
+
+impl<S: BuildHasher> MappedTable<S> {
+    // Query looks up a key as Table::query does.
+    pub fn query<K: Hash + ?Sized>(&self, k: &K) -> usize {
+        self.table.query(k)
+    }
+
+    // QueryBytes looks up a binary key as Table::query_bytes does.
+    pub fn query_bytes<K: AsRef<[u8]> + ?Sized>(&self, k: &K) -> usize {
+        self.table.query_bytes(k)
+    }
+
+    // QueryPrehashed looks up a digest as Table::query_prehashed does.
+    pub fn query_prehashed(&self, hash: u64) -> usize {
+        self.table.query_prehashed(hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    // AsTableRef borrows the mapped table.
+    pub fn as_table_ref(&self) -> &TableRef<'_, S> {
+        &self.table
+    }
+}
+
+// HybridTable is a table split by Table::write_hybrid and opened by
+// HybridTable::open: its seeds are held in memory and its values, four bytes
+// a slot and most of a large table, are read from a memory-mapped file as
+// queries need them. A query costs one read of the file's pages, from the
+// page cache or, when the table outgrows it, from disk, which suits very
+// large tables queried where a disk read's latency is tolerable.
+//
+// Minimal and k-perfect tables have no values, and open to a table wholly in
+// memory.
+pub struct HybridTable<S = DefaultState> {
+    // without values, so its queries return slots
+    seeds: Table<S>,
+    values: Mmap,
+    has_values: bool,
+}
+
+impl<S: StoredHasher> Table<S> {
+    // WriteHybrid writes the table in two parts for HybridTable::open: the
+    // table without its values to seeds, in the format to_bytes uses, and the
+    // values to values. Each part records how many values there are, so
+    // that open can tell a values file that doesn't go with the seeds.
+    pub fn write_hybrid<W: Write, V: Write>(&self, mut seeds: W, mut values: V) -> io::Result<()>
+    where
+        S: Clone,
+    {
+        let t = Table {
+            values: Vec::new(),
+            seeds: self.seeds.clone(),
+            hasher: self.hasher.clone(),
+            len: self.len,
+            size: self.size,
+            occupied: self.occupied.clone(),
+            info: self.info.clone(),
+        };
+        let n = (self.values.len() as u64).to_le_bytes();
+        seeds.write_all(SEEDS_MAGIC)?;
+        seeds.write_all(&n)?;
+        t.write_to(seeds)?;
+        values.write_all(VALUES_MAGIC)?;
+        values.write_all(&n)?;
+        for x in &self.values {
+            values.write_all(&x.to_le_bytes())?;
+        }
+        values.flush()
+    }
+}
+
+impl<S: StoredHasher> HybridTable<S> {
+    // Open loads seeds_bytes, the first part written by write_hybrid, as
+    // Table::from_bytes does, and maps the values file at values_path. The
+    // file's count must match the one in the seeds part, and its length the
+    // count, but the values in it aren't checked, which would read every
+    // page: a damaged file gives wrong answers, not a panic.
+    //
+    // # Safety
+    //
+    // The values file must not be modified or truncated while it is mapped,
+    // as for Table::open_mmap.
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn open<P: AsRef<Path>>(
+        seeds_bytes: &[u8],
+        values_path: P,
+    ) -> io::Result<HybridTable<S>> {
+        let (n, seeds) = hybrid_header(seeds_bytes, SEEDS_MAGIC)?;
+        let seeds = Table::from_bytes(seeds)?;
+        if !seeds.values.is_empty() {
+            return Err(FormatError::Invalid("values in the seeds part").into());
+        }
+        if n != 0 && (n != seeds.size || seeds.occupied.is_some()) {
+            return Err(FormatError::Invalid("values count").into());
+        }
+        let values = Mmap::map(&File::open(values_path)?)?;
+        let (count, rest) = hybrid_header(&values, VALUES_MAGIC)?;
+        if count != n || rest.len() != n * 4 {
+            return Err(FormatError::Invalid("values length").into());
+        }
+        Ok(HybridTable {
+            seeds,
+            values,
+            has_values: n != 0,
+        })
+    }
+}
+
+impl<S> HybridTable<S> {
+    pub fn len(&self) -> usize {
+        self.seeds.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seeds.len == 0
+    }
+
+    // Value returns the value of slot i, or i itself without values.
+    fn value(&self, i: usize) -> usize {
+        if !self.has_values {
+            return i;
+        }
+        let at = HYBRID_HEADER_LEN + i * 4;
+        i32::from_le_bytes(self.values[at..at + 4].try_into().unwrap()) as usize
+    }
+}
+
+impl<S: BuildHasher> HybridTable<S> {
+    // Query looks up a key as Table::query does.
+    pub fn query<K: Hash + ?Sized>(&self, k: &K) -> usize {
+        self.value(self.seeds.query(k))
+    }
+
+    // QueryBytes looks up a binary key as Table::query_bytes does.
+    pub fn query_bytes<K: AsRef<[u8]> + ?Sized>(&self, k: &K) -> usize {
+        self.value(self.seeds.query_bytes(k))
+    }
+
+    // QueryPrehashed looks up a digest as Table::query_prehashed does.
+    pub fn query_prehashed(&self, hash: u64) -> usize {
+        self.value(self.seeds.query_prehashed(hash))
+    }
+}
+
+// hybrid_header checks that b starts with magic and a count, and returns the
+// count and the bytes after it.
+fn hybrid_header<'a>(b: &'a [u8], magic: &[u8; 4]) -> Result<(usize, &'a [u8]), FormatError> {
+    if b.len() < HYBRID_HEADER_LEN {
+        return Err(FormatError::Truncated);
+    }
+    if !b.starts_with(magic) {
+        return Err(FormatError::BadMagic);
+    }
+    let n = u64::from_le_bytes(b[4..HYBRID_HEADER_LEN].try_into().unwrap());
+    let n = usize::try_from(n).map_err(|_| FormatError::Invalid("values count"))?;
+    Ok((n, &b[HYBRID_HEADER_LEN..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io;
+    use std::process;
+
+    use crate::hash::SipState;
+    use crate::{HybridTable, Table, TableBuilder};
+
+    #[test]
+    fn open_mmap() {
+        let dir = std::env::temp_dir().join(format!("mph-rs-mmap-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("t.mph");
+
+        let keys: Vec<String> = (0..1000).map(|i| format!("key{}", i)).collect();
+        Table::new(&keys).save(&path).unwrap();
+        let m = unsafe { Table::<SipState>::open_mmap(&path) }.unwrap();
+        assert_eq!(m.len(), keys.len());
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(m.query(k), i);
+        }
+
+        // saving over a mapped file leaves the map on the old table
+        Table::new(&keys[..10]).save(&path).unwrap();
+        assert_eq!(m.len(), keys.len());
+        assert_eq!(m.query("key500"), 500);
+        drop(m);
+
+        fs::write(&path, [0u8; 100]).unwrap();
+        let err = unsafe { Table::<SipState>::open_mmap(&path) }
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn hybrid() {
+        let dir = std::env::temp_dir().join(format!("mph-rs-hybrid-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("values");
+
+        let keys: Vec<String> = (0..10000).map(|i| format!("key{}", i)).collect();
+        for b in [TableBuilder::new(), TableBuilder::new().minimal(true)] {
+            let t: Table<SipState> = b.with_hasher(SipState::default()).build(&keys);
+            let mut seeds = Vec::new();
+            t.write_hybrid(&mut seeds, fs::File::create(&path).unwrap())
+                .unwrap();
+            let h = unsafe { HybridTable::<SipState>::open(&seeds, &path) }.unwrap();
+            assert_eq!(h.len(), keys.len());
+            assert!(seeds.len() < t.to_bytes().len() || t.values.is_empty());
+            for k in &keys {
+                assert_eq!(h.query(k), t.query(k));
+            }
+        }
+
+        // values files that don't go with the seeds: truncated, with only
+        // the magic, and from a minimal table, which keeps no values
+        let t = Table::<SipState>::from_hashes(&[1, 2, 3]);
+        let mut seeds = Vec::new();
+        t.write_hybrid(&mut seeds, io::sink()).unwrap();
+        let mut none = Vec::new();
+        TableBuilder::new()
+            .minimal(true)
+            .with_hasher(SipState::default())
+            .build(&["a", "b", "c"])
+            .write_hybrid(io::sink(), &mut none)
+            .unwrap();
+        for values in [&b"MPHV\x01\x00\x00"[..], b"MPHV", &none] {
+            fs::write(&path, values).unwrap();
+            let err = unsafe { HybridTable::<SipState>::open(&seeds, &path) }
+                .err()
+                .unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
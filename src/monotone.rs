@@ -0,0 +1,91 @@
+use std::hash::{BuildHasher, Hash};
+
+use crate::hash::DefaultState;
+use crate::packed::PackedInts;
+use crate::{Table, TableBuilder};
+
+// MonotoneTable is an order-preserving minimal perfect hash: built over keys
+// in sorted order, query returns a key's rank among them, so it can stand in
+// for a binary search over keys that are no longer stored.
+//
+// It pairs a minimal Table with the rank of each of its values, packed in
+// log2(n) bits, which with the seeds comes to about log2(n) + 10 bits per key
+// instead of Table's 32 bits per slot.
+pub struct MonotoneTable<S = DefaultState> {
+    table: Table<S>,
+    ranks: PackedInts,
+}
+
+impl MonotoneTable {
+    pub fn new<K: Hash + Ord>(keys: &[K]) -> MonotoneTable {
+        MonotoneTable::with_hasher(keys, DefaultState::default())
+    }
+}
+
+impl<S: BuildHasher + Clone> MonotoneTable<S> {
+    // WithHasher builds a table over keys hashed with hash_builder. The keys
+    // must be sorted and distinct.
+    pub fn with_hasher<K: Hash + Ord>(keys: &[K], hash_builder: S) -> MonotoneTable<S> {
+        assert!(
+            keys.windows(2).all(|w| w[0] < w[1]),
+            "monotone: keys must be sorted and distinct"
+        );
+
+        let table = TableBuilder::new()
+            .with_hasher(hash_builder)
+            .minimal(true)
+            .lambda(4.0)
+            .build(keys);
+
+        let mut ranks = vec![0u64; keys.len()];
+        for (i, k) in keys.iter().enumerate() {
+            ranks[table.query(k)] = i as u64;
+        }
+
+        MonotoneTable {
+            table,
+            ranks: PackedInts::new(&ranks),
+        }
+    }
+}
+
+impl<S: BuildHasher> MonotoneTable<S> {
+    // Query returns the rank of k among the keys. Keys that were not in the
+    // input get an arbitrary value.
+    pub fn query<K: Hash + ?Sized>(&self, k: &K) -> usize {
+        self.ranks.get(self.table.query(k)) as usize
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monotone() {
+        for &n in &[0, 1, 2, 100, 5000] {
+            let mut keys: Vec<String> = (0..n).map(|i| format!("key{}", i)).collect();
+            keys.sort();
+
+            let t = MonotoneTable::new(&keys);
+            assert_eq!(t.len(), n);
+            for (i, k) in keys.iter().enumerate() {
+                assert_eq!(t.query(k), i);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "sorted")]
+    fn unsorted() {
+        MonotoneTable::new(&["b", "a"]);
+    }
+}
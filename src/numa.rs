@@ -0,0 +1,202 @@
+// NUMA replicas of a table, on Linux. On a machine with several sockets each
+// has memory of its own, a NUMA node, and reaching another node's memory
+// takes longer; a table read by every socket but allocated on one makes all
+// the others wait on it.
+
+use std::fs;
+use std::hash::{BuildHasher, Hash};
+use std::os::raw::{c_int, c_ulong};
+use std::thread;
+
+use crate::hash::DefaultState;
+use crate::Table;
+
+extern "C" {
+    fn sched_getcpu() -> c_int;
+    fn sched_setaffinity(pid: c_int, size: usize, mask: *const c_ulong) -> c_int;
+}
+
+// CPU_SET_WORDS is the size of the kernel's usual cpu_set_t, 1024 CPUs.
+const CPU_SET_WORDS: usize = 1024 / 64;
+
+// NumaTable holds a copy of a table on each NUMA node, made by
+// Table::replicate_numa, and answers each query from the copy on the node of
+// the CPU it runs on. Each copy costs the table's memory again. On a machine
+// with one node there is one copy, and queries cost a sched_getcpu more than
+// the table's own, a few nanoseconds.
+pub struct NumaTable<S = DefaultState> {
+    replicas: Vec<Table<S>>,
+    // the replica to read on each CPU; CPUs not listed read the first
+    replica_of: Vec<u16>,
+}
+
+impl<S: Clone + Send + Sync> Table<S> {
+    // ReplicateNuma copies the table onto each of the machine's NUMA nodes.
+    // Each copy is made by a thread running on the node's CPUs, so that the
+    // kernel allocates it there.
+    pub fn replicate_numa(self) -> NumaTable<S> {
+        self.replicate_on(&nodes())
+    }
+
+    // ReplicateOn makes a copy for each of nodes, given by their CPUs.
+    fn replicate_on(self, nodes: &[Vec<usize>]) -> NumaTable<S> {
+        if nodes.len() <= 1 {
+            return NumaTable {
+                replicas: vec![self],
+                replica_of: Vec::new(),
+            };
+        }
+
+        let mut replica_of = Vec::new();
+        for (r, cpus) in nodes.iter().enumerate() {
+            for &cpu in cpus {
+                if cpu >= replica_of.len() {
+                    replica_of.resize(cpu + 1, 0);
+                }
+                replica_of[cpu] = r as u16;
+            }
+        }
+        let replicas = thread::scope(|s| {
+            let copies: Vec<_> = nodes
+                .iter()
+                .map(|cpus| {
+                    let t = &self;
+                    s.spawn(move || {
+                        pin(cpus);
+                        t.copy()
+                    })
+                })
+                .collect();
+            copies.into_iter().map(|c| c.join().unwrap()).collect()
+        });
+        NumaTable {
+            replicas,
+            replica_of,
+        }
+    }
+
+    fn copy(&self) -> Table<S> {
+        Table {
+            values: self.values.clone(),
+            seeds: self.seeds.clone(),
+            hasher: self.hasher.clone(),
+            len: self.len,
+            size: self.size,
+            occupied: self.occupied.clone(),
+            info: self.info.clone(),
+        }
+    }
+}
+
+impl<S> NumaTable<S> {
+    pub fn len(&self) -> usize {
+        self.replicas[0].len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.replicas[0].len == 0
+    }
+
+    // Replicas returns the number of copies, one per node.
+    pub fn replicas(&self) -> usize {
+        self.replicas.len()
+    }
+
+    // Local returns the copy on the node of the calling thread's CPU, for
+    // lookups other than query. A thread may move to another node at any
+    // time, so the copy is only the local one for as long as the thread
+    // stays put; on any copy, the answers are the same.
+    pub fn local(&self) -> &Table<S> {
+        // safe: sched_getcpu has no preconditions, and returns -1 on error
+        let cpu = unsafe { sched_getcpu() };
+        let r = self.replica_of.get(cpu as usize).copied().unwrap_or(0);
+        &self.replicas[r as usize]
+    }
+}
+
+impl<S: BuildHasher> NumaTable<S> {
+    // Query looks k up in the local copy.
+    pub fn query<K: Hash + ?Sized>(&self, k: &K) -> usize {
+        self.local().query(k)
+    }
+}
+
+// nodes returns the CPUs of each NUMA node with any, as sysfs lists them, or
+// nothing if it can't be read.
+fn nodes() -> Vec<Vec<usize>> {
+    let entries = match fs::read_dir("/sys/devices/system/node") {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut nodes = Vec::new();
+    for e in entries.flatten() {
+        let name = e.file_name();
+        let id = match name.to_str().and_then(|n| n.strip_prefix("node")) {
+            Some(id) => id.parse::<usize>(),
+            None => continue,
+        };
+        let cpus = fs::read_to_string(e.path().join("cpulist"));
+        if let (Ok(id), Ok(cpus)) = (id, cpus) {
+            let cpus = cpu_list(&cpus);
+            if !cpus.is_empty() {
+                nodes.push((id, cpus));
+            }
+        }
+    }
+    nodes.sort();
+    nodes.into_iter().map(|(_, cpus)| cpus).collect()
+}
+
+// cpu_list parses a kernel CPU list such as "0-3,8-11".
+fn cpu_list(s: &str) -> Vec<usize> {
+    let mut cpus = Vec::new();
+    for part in s.trim().split(',').filter(|p| !p.is_empty()) {
+        let (lo, hi) = part.split_once('-').unwrap_or((part, part));
+        if let (Ok(lo), Ok(hi)) = (lo.parse::<usize>(), hi.parse::<usize>()) {
+            cpus.extend(lo..=hi);
+        }
+    }
+    cpus
+}
+
+// pin restricts the calling thread to cpus. If that fails, say because the
+// process may not use them, the thread runs where it was.
+fn pin(cpus: &[usize]) {
+    let mut mask = [0 as c_ulong; CPU_SET_WORDS];
+    for &cpu in cpus.iter().filter(|&&c| c < CPU_SET_WORDS * 64) {
+        mask[cpu / 64] |= 1 << (cpu % 64);
+    }
+    // safe: mask is a cpu_set_t of the size passed, and pid 0 is the
+    // calling thread
+    unsafe { sched_setaffinity(0, std::mem::size_of_val(&mask), mask.as_ptr()) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cpu_list, nodes};
+    use crate::Table;
+
+    #[test]
+    fn numa() {
+        assert_eq!(cpu_list("0-3,8,10-11\n"), [0, 1, 2, 3, 8, 10, 11]);
+        assert!(cpu_list("").is_empty());
+
+        let keys: Vec<String> = (0..1000).map(|i| format!("k{}", i)).collect();
+        // two nodes sharing CPU 0, as far as the copies know
+        let t = Table::new(&keys).replicate_on(&[vec![0], vec![0, 1]]);
+        assert_eq!(t.replicas(), 2);
+        assert_eq!(t.replica_of, [1, 1]);
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.replicas[0].query(k), i);
+            assert_eq!(t.query(k), i);
+        }
+
+        let t = Table::new(&keys).replicate_numa();
+        assert_eq!(t.replicas(), nodes().len().max(1));
+        assert_eq!(t.len(), keys.len());
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(t.query(k), i);
+            assert_eq!(t.local().query(k), i);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+// PackedInts stores unsigned integers in a fixed number of bits each, the
+// width being the fewest bits that hold the largest value.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PackedInts {
+    words: Vec<u64>,
+    width: u32,
+    len: usize,
+}
+
+impl PackedInts {
+    pub(crate) fn new(values: &[u64]) -> PackedInts {
+        let max = values.iter().copied().max().unwrap_or(0);
+        let width = 64 - max.leading_zeros();
+        let mut words = vec![0u64; (values.len() * width as usize).div_ceil(64)];
+        for (i, &v) in values.iter().enumerate().filter(|_| width > 0) {
+            let bit = i * width as usize;
+            let (w, shift) = (bit / 64, bit % 64);
+            words[w] |= v << shift;
+            if shift + width as usize > 64 {
+                words[w + 1] |= v >> (64 - shift);
+            }
+        }
+        PackedInts {
+            words,
+            width,
+            len: values.len(),
+        }
+    }
+
+    pub(crate) fn get(&self, i: usize) -> u64 {
+        if self.width == 0 {
+            return 0;
+        }
+        let width = self.width as usize;
+        let bit = i * width;
+        let (w, shift) = (bit / 64, bit % 64);
+        let mut v = self.words[w] >> shift;
+        if shift + width > 64 {
+            v |= self.words[w + 1] << (64 - shift);
+        }
+        if width < 64 {
+            v &= (1 << width) - 1;
+        }
+        v
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    // SizeBits is the space taken by the packed values.
+    pub(crate) fn size_bits(&self) -> usize {
+        self.words.len() * 64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed() {
+        for &max in &[0u64, 1, 7, 1000, u32::MAX as u64, u64::MAX] {
+            let values: Vec<u64> = (0..300u64)
+                .map(|i| max / 300 * i % (max / 2 + 1))
+                .chain(Some(max))
+                .collect();
+            let p = PackedInts::new(&values);
+            assert_eq!(p.len(), values.len());
+            assert_eq!(p.width, 64 - max.leading_zeros());
+            for (i, &v) in values.iter().enumerate() {
+                assert_eq!(p.get(i), v);
+            }
+        }
+    }
+}
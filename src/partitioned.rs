@@ -0,0 +1,220 @@
+use std::hash::{BuildHasher, Hash};
+
+use crate::fmix64;
+use crate::hash::{DefaultState, KeyHasher};
+use crate::Table;
+
+// PartitionedTable is a table split into independent sub-tables, built by
+// TableBuilder::build_partitioned or build_external. Keys go to a partition
+// by the top bits of their hash, and each partition is an ordinary Table over
+// its own keys, so a build only ever holds one partition's buckets and
+// scratch arrays at a time, and with rayon the partitions are built in
+// parallel.
+//
+// Query adds the partition's offset to the sub-table's answer. As with
+// pthash::Table, that is a unique value in 0..n rather than the key's input
+// index; k-perfect sub-tables answer with slots, so their offsets count slots.
+pub struct PartitionedTable<S = DefaultState> {
+    hasher: KeyHasher<S>,
+    // log2 of the number of partitions
+    bits: u32,
+    parts: Vec<Table<S>>,
+    // partition p's values start at offsets[p]
+    offsets: Vec<usize>,
+    len: usize,
+}
+
+// Shards holds the keys' hashes grouped by partition: partition p's are
+// hashes[bounds[p]..bounds[p + 1]], in input order. The hashes are remixed,
+// since the top bits that chose the partition are the same for all of them
+// and fastrange would put them in the same few buckets.
+pub(crate) struct Shards {
+    bits: u32,
+    hashes: Vec<u64>,
+    // displacement hashes, empty unless second_hash is set
+    disp: Vec<u64>,
+    bounds: Vec<usize>,
+}
+
+impl Shards {
+    // New splits the hash pairs into nparts partitions, rounded up to a power
+    // of two.
+    pub(crate) fn new(pairs: &[(u64, u64)], nparts: usize, second_hash: bool) -> Shards {
+        assert!(nparts > 0, "need at least one partition");
+        let bits = nparts.next_power_of_two().trailing_zeros();
+        let n = 1 << bits;
+
+        let mut bounds = vec![0usize; n + 1];
+        for &(h, _) in pairs {
+            bounds[partition(h, bits) + 1] += 1;
+        }
+        for p in 0..n {
+            bounds[p + 1] += bounds[p];
+        }
+
+        let mut next = bounds.clone();
+        let mut hashes = vec![0u64; pairs.len()];
+        let mut disp = vec![0u64; if second_hash { pairs.len() } else { 0 }];
+        for &(h, d) in pairs {
+            let at = &mut next[partition(h, bits)];
+            hashes[*at] = fmix64(h);
+            if second_hash {
+                disp[*at] = d;
+            }
+            *at += 1;
+        }
+        Shards {
+            bits,
+            hashes,
+            disp,
+            bounds,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.bounds.len() - 1
+    }
+
+    // Bits returns log2 of the number of partitions.
+    pub(crate) fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    // Get returns partition p's hashes and, with second_hash, their
+    // displacements, as Table::build takes them.
+    pub(crate) fn get(&self, p: usize) -> (&[u64], Option<&[u64]>) {
+        let r = self.bounds[p]..self.bounds[p + 1];
+        let disp = if self.disp.is_empty() {
+            None
+        } else {
+            Some(&self.disp[r.clone()])
+        };
+        (&self.hashes[r], disp)
+    }
+}
+
+impl<S> PartitionedTable<S> {
+    // New assembles the table from the sub-tables of 2^bits partitions.
+    pub(crate) fn new(
+        hasher: KeyHasher<S>,
+        bits: u32,
+        parts: Vec<Table<S>>,
+    ) -> PartitionedTable<S> {
+        let mut offsets = Vec::with_capacity(parts.len());
+        let mut next = 0;
+        for t in &parts {
+            offsets.push(next);
+            // a k-perfect table has neither values nor ranks and answers
+            // with its slot
+            let k_perfect = t.values.is_empty() && t.occupied.is_none();
+            next += if k_perfect { t.size } else { t.len };
+        }
+        PartitionedTable {
+            hasher,
+            bits,
+            len: parts.iter().map(|t| t.len).sum(),
+            parts,
+            offsets,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Partitions returns the number of sub-tables.
+    pub fn partitions(&self) -> usize {
+        self.parts.len()
+    }
+}
+
+impl<S: BuildHasher> PartitionedTable<S> {
+    // Query returns the value assigned to k. Keys that were not in the input
+    // get an arbitrary value.
+    pub fn query<K: Hash + ?Sized>(&self, k: &K) -> usize {
+        let (h, d) = self.hasher.hash_pair(k);
+        let p = partition(h, self.bits);
+        let h = fmix64(h);
+        let d = if self.hasher.second_hash { d } else { h };
+        self.offsets[p] + self.parts[p].lookup(h, d)
+    }
+
+    // BitsPerKey reports the space used by all the sub-tables.
+    pub fn bits_per_key(&self) -> f64 {
+        let bits: f64 = self
+            .parts
+            .iter()
+            .map(|t| t.bits_per_key() * t.len.max(1) as f64)
+            .sum();
+        bits / self.len.max(1) as f64
+    }
+}
+
+// partition maps a hash to its partition by its top bits.
+pub(crate) fn partition(h: u64, bits: u32) -> usize {
+    if bits == 0 {
+        0
+    } else {
+        (h >> (64 - bits)) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::TableBuilder;
+
+    #[test]
+    fn partitioned() {
+        let keys: Vec<String> = (0..10000).map(|i| format!("k{}", i)).collect();
+
+        for b in [
+            TableBuilder::new(),
+            TableBuilder::new().minimal(true).lambda(4.0),
+            TableBuilder::new().second_hash(true).fastrange(true),
+        ] {
+            let t = b.build_partitioned(&keys, 6);
+            assert_eq!(t.partitions(), 8);
+            assert_eq!(t.len(), keys.len());
+
+            let mut seen = vec![false; keys.len()];
+            for k in &keys {
+                let v = t.query(k);
+                assert!(!seen[v], "duplicate value {}", v);
+                seen[v] = true;
+            }
+        }
+
+        let t = TableBuilder::new().k(4).build_partitioned(&keys, 4);
+        let mut load = vec![0; t.offsets[3] + t.parts[3].size];
+        for k in &keys {
+            load[t.query(k)] += 1;
+        }
+        assert!(load.iter().all(|&l| l <= 4));
+
+        let t = TableBuilder::new().build_partitioned(&keys[..3], 16);
+        assert_eq!(t.partitions(), 16);
+        let mut v: Vec<usize> = keys[..3].iter().map(|k| t.query(k)).collect();
+        v.sort_unstable();
+        assert_eq!(v, [0, 1, 2]);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn build_partitioned_par() {
+        let keys: Vec<String> = (0..20000).map(|i| format!("k{}", i)).collect();
+
+        let b = TableBuilder::new().minimal(true);
+        let want = b.build_partitioned(&keys, 8);
+        let t = b.build_partitioned_par(&keys, 8);
+        for (u, w) in t.parts.iter().zip(&want.parts) {
+            assert_eq!(u.seeds, w.seeds);
+        }
+        for k in &keys {
+            assert_eq!(t.query(k), want.query(k));
+        }
+    }
+}
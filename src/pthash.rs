@@ -0,0 +1,320 @@
+use std::hash::{BuildHasher, Hash};
+
+use crate::fmix64;
+use crate::hash::{DefaultState, KeyHasher};
+use crate::packed::PackedInts;
+
+// Table is a PTHash minimal perfect hash. Keys are first split into
+// partitions of about partition_size keys, each built on its own. Within a
+// partition the keys are grouped into buckets, and each bucket stores a small
+// pilot: the key's slot is its hash mixed with its bucket's pilot, reduced
+// onto slots 0..m. Buckets are placed largest first, and a pilot is the first
+// one that puts all of the bucket's keys on free slots.
+//
+// Buckets are skewed: 60% of the keys go to the first 30% of the buckets.
+// These dense buckets are placed early, while most slots are free, and the
+// many small buckets left fill the rest cheaply. Their pilots differ in size
+// too, so the front and back buckets keep their pilots in separate packed
+// arrays, each just wide enough for its own largest pilot.
+//
+// With alpha < 1 a partition has a few more slots than keys; keys that land
+// past the end are sent to one of the free slots below it, so values stay in
+// 0..n. A query is a hash, a pilot read and usually nothing else. As with
+// BdzTable, query returns a unique value in 0..n rather than the key's input
+// index.
+pub struct Table<S = DefaultState> {
+    hasher: KeyHasher<S>,
+    partitions: Vec<Partition>,
+    len: usize,
+}
+
+struct Partition {
+    // keys in the partitions before this one
+    offset: usize,
+    len: usize,
+    // slots; at least len
+    m: usize,
+    buckets: usize,
+    front: PackedInts,
+    back: PackedInts,
+    // free slot below len for each slot at or past it
+    free: PackedInts,
+}
+
+// Builder configures a pthash Table.
+#[derive(Clone, Debug)]
+pub struct Builder<S = DefaultState> {
+    build: S,
+    c: f64,
+    alpha: f64,
+    partition_size: usize,
+}
+
+// The share of keys sent to the share of buckets at the front.
+const DENSE_KEYS: f64 = 0.6;
+const DENSE_BUCKETS: f64 = 0.3;
+
+// MAX_PILOT bounds the pilot search; reaching it means the input has
+// duplicate keys.
+const MAX_PILOT: u64 = 1 << 32;
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder {
+            build: DefaultState::default(),
+            c: 7.0,
+            alpha: 0.98,
+            partition_size: 1 << 20,
+        }
+    }
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        Builder::default()
+    }
+}
+
+impl<S: BuildHasher + Clone> Builder<S> {
+    // C sets the bucket count to c * n / log2(n) per partition (default 7).
+    // Fewer buckets mean fewer pilots to store but longer pilot searches.
+    pub fn c(mut self, c: f64) -> Builder<S> {
+        assert!(c > 0.0, "c must be positive");
+        self.c = c;
+        self
+    }
+
+    // Alpha sets the load factor, keys per slot, in (0, 1] (default 0.98).
+    // Extra slots make the last buckets much faster to place; the remapping
+    // of slots past n that they need costs little space.
+    pub fn alpha(mut self, alpha: f64) -> Builder<S> {
+        assert!(alpha > 0.0 && alpha <= 1.0, "alpha must be in (0, 1]");
+        self.alpha = alpha;
+        self
+    }
+
+    // PartitionSize sets the average number of keys per partition (default
+    // 2^20). Smaller partitions stay in cache while they're built.
+    pub fn partition_size(mut self, partition_size: usize) -> Builder<S> {
+        assert!(partition_size > 0, "partition size must be positive");
+        self.partition_size = partition_size;
+        self
+    }
+
+    // WithHasher switches the hash function to the one built by hash_builder.
+    pub fn with_hasher<T: BuildHasher + Clone>(self, hash_builder: T) -> Builder<T> {
+        Builder {
+            build: hash_builder,
+            c: self.c,
+            alpha: self.alpha,
+            partition_size: self.partition_size,
+        }
+    }
+
+    // Build constructs a table over keys.
+    pub fn build<K: Hash>(&self, keys: &[K]) -> Table<S> {
+        let hasher = KeyHasher::new(self.build.clone());
+        let nparts = keys.len().div_ceil(self.partition_size).max(1);
+
+        let mut hashes: Vec<u64> = keys.iter().map(|k| hasher.hash(k)).collect();
+        hashes.sort_unstable_by_key(|&h| (reduce(h, nparts), h));
+        for w in hashes.windows(2) {
+            assert_ne!(w[0], w[1], "pthash: duplicate keys");
+        }
+
+        let mut partitions = Vec::with_capacity(nparts);
+        let mut i = 0;
+        for p in 0..nparts {
+            let mut j = i;
+            while j < hashes.len() && reduce(hashes[j], nparts) == p {
+                j += 1;
+            }
+            partitions.push(self.partition(&hashes[i..j], i));
+            i = j;
+        }
+
+        Table {
+            hasher,
+            partitions,
+            len: keys.len(),
+        }
+    }
+
+    fn partition(&self, hashes: &[u64], offset: usize) -> Partition {
+        let n = hashes.len();
+        let m = ((n as f64 / self.alpha).ceil() as usize).max(n).max(1);
+        let log_n = (n.max(2) as f64).log2();
+        let buckets = ((self.c * n as f64 / log_n).ceil() as usize).max(1);
+
+        let mut order: Vec<(usize, u64)> =
+            hashes.iter().map(|&h| (bucket(h, buckets), h)).collect();
+        order.sort_unstable();
+
+        // group the hashes by bucket and visit the largest buckets first
+        let mut groups: Vec<&[(usize, u64)]> = order.chunk_by(|a, b| a.0 == b.0).collect();
+        groups.sort_by_key(|g| std::cmp::Reverse(g.len()));
+
+        let mut taken = vec![false; m];
+        let mut pilots = vec![0u64; buckets];
+        let mut slots = Vec::new();
+        for g in groups {
+            let b = g[0].0;
+            let mut pilot = 0;
+            loop {
+                assert!(
+                    pilot < MAX_PILOT,
+                    "pthash: no pilot found; are the keys distinct?"
+                );
+                slots.clear();
+                let ok = g.iter().all(|&(_, h)| {
+                    let s = slot(h, pilot, m);
+                    let free = !taken[s] && !slots.contains(&s);
+                    slots.push(s);
+                    free
+                });
+                if ok {
+                    break;
+                }
+                pilot += 1;
+            }
+            for &s in &slots {
+                taken[s] = true;
+            }
+            pilots[b] = pilot;
+        }
+
+        // pair each taken slot past n with a free one below it
+        let mut holes = (0..n).filter(|&s| !taken[s]);
+        let free: Vec<u64> = (n..m)
+            .map(|s| {
+                if taken[s] {
+                    holes.next().unwrap() as u64
+                } else {
+                    0
+                }
+            })
+            .collect();
+
+        let nfront = front_buckets(buckets);
+        Partition {
+            offset,
+            len: n,
+            m,
+            buckets,
+            front: PackedInts::new(&pilots[..nfront]),
+            back: PackedInts::new(&pilots[nfront..]),
+            free: PackedInts::new(&free),
+        }
+    }
+}
+
+impl Table {
+    pub fn new<K: Hash>(keys: &[K]) -> Table {
+        Builder::new().build(keys)
+    }
+}
+
+impl<S: BuildHasher> Table<S> {
+    // Query returns the value in 0..len() assigned to k. Keys that were not in
+    // the input get an arbitrary value.
+    pub fn query<K: Hash + ?Sized>(&self, k: &K) -> usize {
+        let h = self.hasher.hash(k);
+        let p = &self.partitions[reduce(h, self.partitions.len())];
+
+        let b = bucket(h, p.buckets);
+        let nfront = p.front.len();
+        let pilot = if b < nfront {
+            p.front.get(b)
+        } else {
+            p.back.get(b - nfront)
+        };
+
+        let s = slot(h, pilot, p.m);
+        if s < p.len {
+            p.offset + s
+        } else {
+            p.offset + p.free.get(s - p.len) as usize
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // BitsPerKey reports the space used by the pilots and the free slot
+    // remapping.
+    pub fn bits_per_key(&self) -> f64 {
+        let bits: usize = self
+            .partitions
+            .iter()
+            .map(|p| p.front.size_bits() + p.back.size_bits() + p.free.size_bits() + 4 * 64)
+            .sum();
+        bits as f64 / self.len.max(1) as f64
+    }
+}
+
+fn front_buckets(buckets: usize) -> usize {
+    ((DENSE_BUCKETS * buckets as f64).ceil() as usize).min(buckets)
+}
+
+// Bucket picks h's bucket, sending DENSE_KEYS of the keys to the front
+// buckets.
+fn bucket(h: u64, buckets: usize) -> usize {
+    let nfront = front_buckets(buckets);
+    let x = fmix64(h ^ 0x2545_f491_4f6c_dd1d);
+    if (x as f64) < DENSE_KEYS * u64::MAX as f64 || nfront == buckets {
+        reduce(x.rotate_left(32), nfront)
+    } else {
+        nfront + reduce(x.rotate_left(32), buckets - nfront)
+    }
+}
+
+fn slot(h: u64, pilot: u64, m: usize) -> usize {
+    reduce(
+        fmix64(h ^ fmix64(pilot.wrapping_mul(0x9e37_79b9_7f4a_7c15))),
+        m,
+    )
+}
+
+fn reduce(x: u64, n: usize) -> usize {
+    ((x as u128 * n as u128) >> 64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pthash() {
+        for &(alpha, part) in &[(1.0, 1 << 20), (0.98, 1 << 20), (0.9, 1000), (0.98, 1)] {
+            let keys: Vec<String> = (0..5000).map(|i| format!("key{}", i)).collect();
+
+            let t = Builder::new()
+                .alpha(alpha)
+                .partition_size(part)
+                .build(&keys);
+            assert_eq!(t.len(), keys.len());
+
+            let mut seen = vec![false; keys.len()];
+            for k in &keys {
+                let v = t.query(k);
+                assert!(!seen[v], "duplicate value {}", v);
+                seen[v] = true;
+            }
+        }
+
+        let t = Table::new(&Vec::<u32>::new());
+        assert!(t.is_empty());
+    }
+
+    #[test]
+    fn bits_per_key() {
+        let keys: Vec<u64> = (0..100_000).collect();
+        let t = Builder::new().build(&keys);
+        assert!(t.bits_per_key() < 4.5, "{}", t.bits_per_key());
+    }
+}
@@ -0,0 +1,155 @@
+// RankBits is a bit vector that answers rank queries (the number of set bits
+// before a position) in constant time, with a 32-bit count stored for every
+// BLOCK_WORDS words.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)
+)]
+pub(crate) struct RankBits {
+    bits: Vec<u64>,
+    ranks: Vec<u32>,
+}
+
+pub(crate) const BLOCK_WORDS: usize = 8;
+
+#[cfg(feature = "std")]
+impl RankBits {
+    pub(crate) fn new(bits: Vec<u64>) -> RankBits {
+        let mut ranks = Vec::with_capacity(bits.len() / BLOCK_WORDS + 1);
+        let mut rank = 0;
+        for block in bits.chunks(BLOCK_WORDS) {
+            ranks.push(rank);
+            rank += block.iter().map(|w| w.count_ones()).sum::<u32>();
+        }
+        ranks.push(rank);
+        RankBits { bits, ranks }
+    }
+
+    pub(crate) fn get(&self, pos: usize) -> bool {
+        self.bits[pos / 64] & (1 << (pos % 64)) != 0
+    }
+
+    // Rank counts the set bits before pos.
+    pub(crate) fn rank(&self, pos: usize) -> usize {
+        rank_by(pos, |b| self.ranks[b], |w| self.bits[w])
+    }
+
+    // Select returns the position of the set bit of rank k, which must be
+    // below ones(): a binary search of the rank samples, then a scan of the
+    // block's words.
+    pub(crate) fn select(&self, k: usize) -> usize {
+        let block = self.ranks.partition_point(|&r| r as usize <= k) - 1;
+        let mut k = k - self.ranks[block] as usize;
+        let mut w = block * BLOCK_WORDS;
+        while k >= self.bits[w].count_ones() as usize {
+            k -= self.bits[w].count_ones() as usize;
+            w += 1;
+        }
+        let mut word = self.bits[w];
+        for _ in 0..k {
+            word &= word - 1;
+        }
+        w * 64 + word.trailing_zeros() as usize
+    }
+
+    // Prefetch hints that rank(pos) is about to be called.
+    pub(crate) fn prefetch(&self, pos: usize) {
+        let w = pos / 64;
+        let block = w / BLOCK_WORDS;
+        crate::prefetch(&self.ranks, block);
+        crate::prefetch(&self.bits, block * BLOCK_WORDS);
+        crate::prefetch(&self.bits, w);
+    }
+
+    pub(crate) fn words(&self) -> &[u64] {
+        &self.bits
+    }
+
+    pub(crate) fn ranks(&self) -> &[u32] {
+        &self.ranks
+    }
+
+    // Ones returns the total number of set bits.
+    pub(crate) fn ones(&self) -> usize {
+        *self.ranks.last().unwrap() as usize
+    }
+
+    // SizeBits is the space taken by the bits and rank samples.
+    pub(crate) fn size_bits(&self) -> usize {
+        self.bits.len() * 64 + self.ranks.len() * 32
+    }
+
+    // HeapBytes is the memory allocated for the bits and rank samples.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.bits.capacity() * 8 + self.ranks.capacity() * 4
+    }
+}
+
+// rank_by counts the set bits before pos in a bit vector laid out like
+// RankBits, reading its rank samples and words through sample and word, so that
+// encoded and archived copies can share it.
+pub(crate) fn rank_by<R, W>(pos: usize, sample: R, word: W) -> usize
+where
+    R: Fn(usize) -> u32,
+    W: Fn(usize) -> u64,
+{
+    let w = pos / 64;
+    let block = w / BLOCK_WORDS;
+    let mut rank = sample(block) as usize;
+    for i in block * BLOCK_WORDS..w {
+        rank += word(i).count_ones() as usize;
+    }
+    let bit = pos % 64;
+    if bit != 0 {
+        rank += (word(w) << (64 - bit)).count_ones() as usize;
+    }
+    rank
+}
+
+#[cfg(feature = "rkyv")]
+impl ArchivedRankBits {
+    pub(crate) fn rank(&self, pos: usize) -> usize {
+        rank_by(
+            pos,
+            |b| self.ranks[b].to_native(),
+            |w| self.bits[w].to_native(),
+        )
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rank() {
+        let mut bits = vec![0u64; 40];
+        let set: Vec<usize> = (0..40 * 64).filter(|i| i % 3 == 0 || i % 7 == 0).collect();
+        for &i in &set {
+            bits[i / 64] |= 1 << (i % 64);
+        }
+
+        let r = RankBits::new(bits);
+        assert_eq!(r.ones(), set.len());
+        let mut want = 0;
+        for pos in 0..40 * 64 {
+            assert_eq!(r.rank(pos), want, "rank({})", pos);
+            if r.get(pos) {
+                want += 1;
+            }
+        }
+        for (k, &pos) in set.iter().enumerate() {
+            assert_eq!(r.select(k), pos, "select({})", k);
+        }
+
+        // runs of empty blocks
+        let mut bits = vec![0u64; 64];
+        bits[3] = 1 << 63;
+        bits[40] = 1;
+        let r = RankBits::new(bits);
+        assert_eq!((r.select(0), r.select(1)), (255, 40 * 64));
+    }
+}
@@ -0,0 +1,421 @@
+use std::hash::{BuildHasher, Hash};
+
+use crate::fmix64;
+use crate::hash::{DefaultState, KeyHasher};
+
+// Table is a RecSplit minimal perfect hash, aimed at read-mostly tables where
+// space matters more than query speed. Keys are spread over buckets of about
+// bucket_size keys; each bucket is split recursively, by seeds found through
+// brute force, until the parts are at most leaf_size keys, and each leaf
+// stores a seed that maps its keys one-to-one onto its slots. As in the
+// RecSplit paper, the splits near the leaves aggregate: a node of at most a
+// few leaves' worth of keys splits straight into leaves, one of at most a few
+// of those into parts of that size, and only larger ones split in two. The
+// seeds are small and Golomb-Rice coded, at about 1.7 bits per key; with the
+// bucket index, 64 bits a bucket, the structure comes to 2.3 bits per key
+// with the defaults and 1.75 with buckets of 2000 keys.
+//
+// A query decodes the seeds on the path to its leaf, skipping the subtrees it
+// doesn't enter, so it costs more than Table's two array reads. As with
+// BdzTable, query returns a unique value in 0..n rather than the key's input
+// index.
+pub struct Table<S = DefaultState> {
+    hasher: KeyHasher<S>,
+    shape: Shape,
+    // keys before each bucket; the last entry is the total
+    starts: Vec<u32>,
+    // bit offset of each bucket's seeds in codes
+    offsets: Vec<u32>,
+    codes: Vec<u64>,
+    // Golomb-Rice parameter for a node of each size
+    rice: Vec<u8>,
+}
+
+// Builder configures a recsplit Table.
+#[derive(Clone, Debug)]
+pub struct Builder<S = DefaultState> {
+    build: S,
+    leaf_size: usize,
+    bucket_size: usize,
+}
+
+const MAX_LEAF_SIZE: usize = 16;
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder {
+            build: DefaultState::default(),
+            leaf_size: 8,
+            bucket_size: 100,
+        }
+    }
+}
+
+impl Builder {
+    pub fn new() -> Builder {
+        Builder::default()
+    }
+}
+
+impl<S: BuildHasher + Clone> Builder<S> {
+    // LeafSize sets the largest part mapped by a single bijection seed
+    // (default 8, at most 16). Larger leaves save space but the search for a
+    // bijection grows exponentially with their size.
+    pub fn leaf_size(mut self, leaf_size: usize) -> Builder<S> {
+        assert!(
+            (1..=MAX_LEAF_SIZE).contains(&leaf_size),
+            "leaf size must be between 1 and 16"
+        );
+        self.leaf_size = leaf_size;
+        self
+    }
+
+    // BucketSize sets the average number of keys per bucket (default 100).
+    // Larger buckets spread the per-bucket bookkeeping over more keys but make
+    // queries decode more seeds.
+    pub fn bucket_size(mut self, bucket_size: usize) -> Builder<S> {
+        assert!(bucket_size > 0, "bucket size must be positive");
+        self.bucket_size = bucket_size;
+        self
+    }
+
+    // WithHasher switches the hash function to the one built by hash_builder.
+    pub fn with_hasher<T: BuildHasher + Clone>(self, hash_builder: T) -> Builder<T> {
+        Builder {
+            build: hash_builder,
+            leaf_size: self.leaf_size,
+            bucket_size: self.bucket_size,
+        }
+    }
+
+    // Build constructs a table over keys.
+    pub fn build<K: Hash>(&self, keys: &[K]) -> Table<S> {
+        let hasher = KeyHasher::new(self.build.clone());
+        let nbuckets = keys.len().div_ceil(self.bucket_size).max(1);
+
+        let mut hashes: Vec<u64> = keys.iter().map(|k| hasher.hash(k)).collect();
+        hashes.sort_unstable_by_key(|&h| (reduce(h, nbuckets), h));
+        for w in hashes.windows(2) {
+            assert_ne!(w[0], w[1], "recsplit: duplicate keys");
+        }
+
+        let mut starts = Vec::with_capacity(nbuckets + 1);
+        let mut offsets = Vec::with_capacity(nbuckets);
+        let mut w = BitWriter::default();
+        let mut rice = Vec::new();
+        let shape = Shape::new(self.leaf_size);
+
+        let mut i = 0;
+        for b in 0..nbuckets {
+            let mut j = i;
+            while j < hashes.len() && reduce(hashes[j], nbuckets) == b {
+                j += 1;
+            }
+
+            let m = j - i;
+            while rice.len() <= m {
+                rice.push(rice_param(rice.len(), shape));
+            }
+
+            starts.push(i as u32);
+            offsets.push(w.len as u32);
+            split(&mut hashes[i..j], 0, shape, &rice, &mut w);
+            i = j;
+        }
+        starts.push(hashes.len() as u32);
+
+        Table {
+            hasher,
+            shape,
+            starts,
+            offsets,
+            codes: w.words,
+            rice,
+        }
+    }
+}
+
+impl Table {
+    pub fn new<K: Hash>(keys: &[K]) -> Table {
+        Builder::new().build(keys)
+    }
+}
+
+impl<S: BuildHasher> Table<S> {
+    // Query returns the value in 0..len() assigned to k. Keys that were not in
+    // the input get an arbitrary value.
+    pub fn query<K: Hash + ?Sized>(&self, k: &K) -> usize {
+        let h = self.hasher.hash(k);
+        let nbuckets = self.offsets.len();
+        let b = reduce(h, nbuckets);
+
+        let mut base = self.starts[b] as usize;
+        let mut m = self.starts[b + 1] as usize - base;
+        let mut r = BitReader {
+            words: &self.codes,
+            pos: self.offsets[b] as usize,
+        };
+
+        let mut depth = 0;
+        while m > 1 {
+            let x = r.read_rice(self.rice[m]);
+            let v = reduce(remix(h, x, depth), m);
+            if m <= self.shape.leaf {
+                return base + v;
+            }
+
+            // the parts before v's are all full
+            let unit = self.shape.unit(m);
+            for _ in 0..v / unit {
+                self.skip(&mut r, unit);
+            }
+            base += v / unit * unit;
+            m = (m - v / unit * unit).min(unit);
+            depth += 1;
+        }
+        base
+    }
+
+    pub fn len(&self) -> usize {
+        *self.starts.last().unwrap() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // BitsPerKey reports the space used by the seeds and the bucket index.
+    pub fn bits_per_key(&self) -> f64 {
+        let bits = self.codes.len() * 64 + (self.starts.len() + self.offsets.len()) * 32;
+        bits as f64 / self.len().max(1) as f64
+    }
+
+    // Skip reads past the seeds of a subtree of m keys.
+    fn skip(&self, r: &mut BitReader, m: usize) {
+        if m <= 1 {
+            return;
+        }
+        r.read_rice(self.rice[m]);
+        if m > self.shape.leaf {
+            let unit = self.shape.unit(m);
+            for start in (0..m).step_by(unit) {
+                self.skip(r, unit.min(m - start));
+            }
+        }
+    }
+}
+
+// Shape holds the node sizes at which splits aggregate, derived from the leaf
+// size as in the RecSplit paper: a node of at most lower keys splits into
+// parts of leaf keys, one of at most upper into parts of lower, and a larger
+// one into two, the first a multiple of upper.
+#[derive(Clone, Copy, Debug)]
+struct Shape {
+    leaf: usize,
+    lower: usize,
+    upper: usize,
+}
+
+impl Shape {
+    fn new(leaf: usize) -> Shape {
+        let l = leaf as f64;
+        let lower = leaf * ((0.35 * l + 0.5).ceil() as usize).max(2);
+        let upper = lower * ((0.21 * l + 0.9).ceil() as usize).max(2);
+        Shape { leaf, lower, upper }
+    }
+
+    // Unit is the size of every part of a node of m > leaf keys but the last,
+    // which holds the rest.
+    fn unit(&self, m: usize) -> usize {
+        if m > self.upper {
+            self.upper * (m / 2).div_ceil(self.upper)
+        } else if m > self.lower {
+            self.lower
+        } else {
+            self.leaf
+        }
+    }
+}
+
+// Split finds and writes the seeds for a node holding hashes, then recurses
+// into its parts in depth-first order, the same order query reads them.
+fn split(hashes: &mut [u64], depth: u64, shape: Shape, rice: &[u8], w: &mut BitWriter) {
+    let m = hashes.len();
+    if m <= 1 {
+        return;
+    }
+
+    if m <= shape.leaf {
+        // bijection: every key lands on its own slot
+        let mut x = 0;
+        loop {
+            let mut used = 0u32;
+            let ok = hashes.iter().all(|&h| {
+                let bit = 1 << reduce(remix(h, x, depth), m);
+                let free = used & bit == 0;
+                used |= bit;
+                free
+            });
+            if ok {
+                break;
+            }
+            x += 1;
+        }
+        w.write_rice(x, rice[m]);
+        return;
+    }
+
+    // a seed works when each part gets as many keys as it has slots
+    let unit = shape.unit(m);
+    let mut counts = vec![0; m.div_ceil(unit)];
+    let mut x = 0;
+    loop {
+        counts.iter_mut().for_each(|c| *c = 0);
+        for &h in hashes.iter() {
+            counts[reduce(remix(h, x, depth), m) / unit] += 1;
+        }
+        let (last, full) = counts.split_last().unwrap();
+        if full.iter().all(|&c| c == unit) && *last == m - full.len() * unit {
+            break;
+        }
+        x += 1;
+    }
+    w.write_rice(x, rice[m]);
+
+    hashes.sort_unstable_by_key(|&h| reduce(remix(h, x, depth), m) / unit);
+    for part in hashes.chunks_mut(unit) {
+        split(part, depth + 1, shape, rice, w);
+    }
+}
+
+fn remix(h: u64, x: u64, depth: u64) -> u64 {
+    fmix64(h ^ (x << 8 | depth).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+}
+
+fn reduce(x: u64, n: usize) -> usize {
+    ((x as u128 * n as u128) >> 64) as usize
+}
+
+// rice_param picks the Golomb-Rice parameter for the seed of a node of m keys.
+// Seeds are geometric with success probability p, the chance a random seed
+// works, for which Kiely's approximation of the best parameter is
+// ceil(log2(ln(phi - 1) / ln(1 - p))).
+fn rice_param(m: usize, shape: Shape) -> u8 {
+    if m <= 1 {
+        return 0;
+    }
+
+    // the multinomial chance that parts of these sizes get exactly their
+    // share; a leaf's parts are its single slots
+    let unit = if m <= shape.leaf { 1 } else { shape.unit(m) };
+    let ln_fact = |n: usize| (2..=n).map(|i| (i as f64).ln()).sum::<f64>();
+    let mf = m as f64;
+    let ln_p = ln_fact(m)
+        + (0..m)
+            .step_by(unit)
+            .map(|start| {
+                let s = unit.min(m - start);
+                let sf = s as f64;
+                sf * (sf / mf).ln() - ln_fact(s)
+            })
+            .sum::<f64>();
+
+    let p = ln_p.exp();
+    let q = (-0.481_211_825_059_603_4f64 / (-p).ln_1p()).log2().ceil();
+    q.clamp(0.0, 63.0) as u8
+}
+
+#[derive(Default)]
+struct BitWriter {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitWriter {
+    fn write_bit(&mut self, bit: bool) {
+        if self.len.is_multiple_of(64) {
+            self.words.push(0);
+        }
+        if bit {
+            self.words[self.len / 64] |= 1 << (self.len % 64);
+        }
+        self.len += 1;
+    }
+
+    // WriteRice writes x as the unary quotient x >> k followed by its low k
+    // bits.
+    fn write_rice(&mut self, x: u64, k: u8) {
+        for _ in 0..x >> k {
+            self.write_bit(true);
+        }
+        self.write_bit(false);
+        for i in 0..k {
+            self.write_bit(x >> i & 1 != 0);
+        }
+    }
+}
+
+struct BitReader<'a> {
+    words: &'a [u64],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn read_bit(&mut self) -> bool {
+        let bit = self.words[self.pos / 64] >> (self.pos % 64) & 1 != 0;
+        self.pos += 1;
+        bit
+    }
+
+    fn read_rice(&mut self, k: u8) -> u64 {
+        let mut q = 0;
+        while self.read_bit() {
+            q += 1;
+        }
+        let mut low = 0;
+        for i in 0..k {
+            if self.read_bit() {
+                low |= 1 << i;
+            }
+        }
+        q << k | low
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recsplit() {
+        for &(leaf, bucket) in &[(1, 1), (4, 10), (8, 100), (12, 500)] {
+            let keys: Vec<String> = (0..5000).map(|i| format!("key{}", i)).collect();
+
+            let t = Builder::new()
+                .leaf_size(leaf)
+                .bucket_size(bucket)
+                .build(&keys);
+            assert_eq!(t.len(), keys.len());
+
+            let mut seen = vec![false; keys.len()];
+            for k in &keys {
+                let v = t.query(k);
+                assert!(!seen[v], "duplicate value {}", v);
+                seen[v] = true;
+            }
+        }
+
+        let t = Table::new(&Vec::<u32>::new());
+        assert!(t.is_empty());
+    }
+
+    #[test]
+    fn bits_per_key() {
+        let keys: Vec<u64> = (0..20_000).collect();
+        let t = Builder::new().build(&keys);
+        assert!(t.bits_per_key() < 2.35, "{}", t.bits_per_key());
+
+        let t = Builder::new().bucket_size(2000).build(&keys);
+        assert!(t.bits_per_key() < 1.75, "{}", t.bits_per_key());
+    }
+}
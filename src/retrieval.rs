@@ -0,0 +1,286 @@
+use std::convert::TryFrom;
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+
+use crate::bdz::{edge, peel, C, MAX_ATTEMPTS};
+use crate::fmix64;
+use crate::hash::{DefaultState, KeyHasher};
+use crate::packed::PackedInts;
+
+// Retrieval is a static function: it maps each of a fixed set of keys to a
+// b-bit value without storing the keys, nor a value array indexed by an MPH.
+// It is built like BdzTable, by peeling a random 3-hypergraph with an edge per
+// key; each vertex then gets a b-bit value such that the three values of a
+// key's edge xor to the key's value. That takes 1.23 vertices per key, so
+// about 1.23 * b bits per key, where b is the width of the largest value.
+//
+// Keys that were not in the input get an arbitrary b-bit value, which may not
+// be a V at all when V doesn't take every b-bit value, as with an enum.
+pub struct Retrieval<V, S = DefaultState> {
+    hasher: KeyHasher<S>,
+    seed: u64,
+    // vertices per part; the graph has 3*r vertices
+    r: usize,
+    g: PackedInts,
+    len: usize,
+    values: PhantomData<V>,
+}
+
+impl<V: Copy + Into<u64> + TryFrom<u64>> Retrieval<V> {
+    pub fn new<K: Hash>(keys: &[K], values: &[V]) -> Retrieval<V> {
+        Retrieval::with_hasher(keys, values, DefaultState::default())
+    }
+}
+
+impl<V: Copy + Into<u64> + TryFrom<u64>, S: BuildHasher + Clone> Retrieval<V, S> {
+    // WithHasher builds a function mapping keys[i] to values[i], hashing keys
+    // with hash_builder.
+    pub fn with_hasher<K: Hash>(keys: &[K], values: &[V], hash_builder: S) -> Retrieval<V, S> {
+        assert_eq!(keys.len(), values.len(), "retrieval: one value per key");
+        Retrieval::solve(keys, hash_builder, |id, _| values[id].into())
+    }
+}
+
+impl<V, S: BuildHasher + Clone> Retrieval<V, S> {
+    // Solve builds the function mapping keys[id] to value(id, hash of the key).
+    fn solve<K: Hash, F>(keys: &[K], hash_builder: S, value: F) -> Retrieval<V, S>
+    where
+        F: Fn(usize, u64) -> u64,
+    {
+        let mut hasher = KeyHasher::new(hash_builder);
+
+        let n = keys.len();
+        let r = ((C * n as f64 / 3.0).ceil() as usize).max(2);
+
+        let mut hashes: Vec<u64> = keys.iter().map(|k| hasher.hash(k)).collect();
+
+        for seed in 1..=MAX_ATTEMPTS {
+            if let Some((edges, peeled)) = peel(&hashes, seed, r) {
+                // in reverse peeling order each edge's peeled vertex is still
+                // free to absorb whatever its value needs
+                let mut g = vec![0u64; 3 * r];
+                for &(id, v) in peeled.iter().rev() {
+                    let e = &edges[id];
+                    g[v] = value(id, hashes[id]) ^ g[e[0]] ^ g[e[1]] ^ g[e[2]];
+                }
+
+                return Retrieval {
+                    hasher,
+                    seed,
+                    r,
+                    g: PackedInts::new(&g),
+                    len: n,
+                    values: PhantomData,
+                };
+            }
+
+            // as in BdzTable, rehash in case two keys share a hash
+            if seed % 8 == 0 {
+                hasher.seed = seed;
+                hashes = keys.iter().map(|k| hasher.hash(k)).collect();
+            }
+        }
+
+        panic!("retrieval: no peelable graph found; are the keys distinct?");
+    }
+}
+
+impl<V, S: BuildHasher> Retrieval<V, S> {
+    // Lookup returns the xor of the values of hash h's vertices.
+    fn lookup(&self, h: u64) -> u64 {
+        let e = edge(h, self.seed, self.r);
+        self.g.get(e[0]) ^ self.g.get(e[1]) ^ self.g.get(e[2])
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // BitsPerKey reports the space used by the vertex values.
+    pub fn bits_per_key(&self) -> f64 {
+        self.g.size_bits() as f64 / self.len.max(1) as f64
+    }
+}
+
+impl<V: TryFrom<u64>, S: BuildHasher> Retrieval<V, S> {
+    // Get returns the value stored for k. A key that was not in the input
+    // gets an arbitrary value, or None if those bits aren't a V.
+    pub fn get<K: Hash + ?Sized>(&self, k: &K) -> Option<V> {
+        V::try_from(self.lookup(self.hasher.hash(k))).ok()
+    }
+}
+
+// ApproxMap is a Bloomier filter: a Retrieval that also stores an r-bit
+// fingerprint of each key next to its value. Member keys always get their
+// value back; any other key is turned away unless its fingerprint happens to
+// match, which it does with probability 2^-r. That costs about 1.23 * (b + r)
+// bits per key, with still no keys stored.
+pub struct ApproxMap<V, S = DefaultState> {
+    inner: Retrieval<u64, S>,
+    value_bits: u32,
+    fingerprint_bits: u32,
+    values: PhantomData<V>,
+}
+
+impl<V: Copy + Into<u64> + TryFrom<u64>> ApproxMap<V> {
+    pub fn new<K: Hash>(keys: &[K], values: &[V], fingerprint_bits: u32) -> ApproxMap<V> {
+        ApproxMap::with_hasher(keys, values, fingerprint_bits, DefaultState::default())
+    }
+}
+
+impl<V: Copy + Into<u64> + TryFrom<u64>, S: BuildHasher + Clone> ApproxMap<V, S> {
+    // WithHasher builds a map from keys[i] to values[i] with fingerprints of
+    // fingerprint_bits (1 to 32) bits, hashing keys with hash_builder.
+    pub fn with_hasher<K: Hash>(
+        keys: &[K],
+        values: &[V],
+        fingerprint_bits: u32,
+        hash_builder: S,
+    ) -> ApproxMap<V, S> {
+        assert_eq!(keys.len(), values.len(), "retrieval: one value per key");
+        assert!(
+            (1..=32).contains(&fingerprint_bits),
+            "fingerprint width must be between 1 and 32 bits"
+        );
+
+        let max = values.iter().map(|&v| v.into()).max().unwrap_or(0);
+        let value_bits = 64 - max.leading_zeros();
+        assert!(
+            value_bits + fingerprint_bits <= 64,
+            "values and fingerprints must fit in 64 bits"
+        );
+
+        let inner = Retrieval::solve(keys, hash_builder, |id, h| {
+            fingerprint(h, fingerprint_bits) << value_bits | values[id].into()
+        });
+        ApproxMap {
+            inner,
+            value_bits,
+            fingerprint_bits,
+            values: PhantomData,
+        }
+    }
+}
+
+impl<V: TryFrom<u64>, S: BuildHasher> ApproxMap<V, S> {
+    // Get returns the value stored for k, or None if k is rejected as not
+    // being one of the keys.
+    pub fn get<K: Hash + ?Sized>(&self, k: &K) -> Option<V> {
+        let h = self.inner.hasher.hash(k);
+        let x = self.inner.lookup(h);
+        if x >> self.value_bits != fingerprint(h, self.fingerprint_bits) {
+            return None;
+        }
+        V::try_from(x & ((1 << self.value_bits) - 1)).ok()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.len == 0
+    }
+
+    // BitsPerKey reports the space used by the values and fingerprints.
+    pub fn bits_per_key(&self) -> f64 {
+        self.inner.bits_per_key()
+    }
+}
+
+// Fingerprint takes bits bits of h that the graph's edges don't depend on.
+fn fingerprint(h: u64, bits: u32) -> u64 {
+    fmix64(h ^ 0x6a09_e667_f3bc_c909) >> (64 - bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retrieval() {
+        for &n in &[0, 1, 2, 100, 10_000] {
+            let keys: Vec<String> = (0..n).map(|i| format!("key{}", i)).collect();
+            let values: Vec<u8> = (0..n).map(|i| (i * 7 % 16) as u8).collect();
+
+            let t = Retrieval::new(&keys, &values);
+            assert_eq!(t.len(), n);
+            for (k, &v) in keys.iter().zip(&values) {
+                assert_eq!(t.get(k), Some(v));
+            }
+        }
+    }
+
+    // Suit takes three of the four 2-bit values, so keys outside the input
+    // may get none of them.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Suit {
+        Clubs,
+        Hearts,
+        Spades,
+    }
+
+    impl From<Suit> for u64 {
+        fn from(s: Suit) -> u64 {
+            s as u64
+        }
+    }
+
+    impl TryFrom<u64> for Suit {
+        type Error = ();
+
+        fn try_from(x: u64) -> Result<Suit, ()> {
+            match x {
+                0 => Ok(Suit::Clubs),
+                1 => Ok(Suit::Hearts),
+                2 => Ok(Suit::Spades),
+                _ => Err(()),
+            }
+        }
+    }
+
+    #[test]
+    fn partial_values() {
+        let keys: Vec<String> = (0..1000).map(|i| format!("key{}", i)).collect();
+        let suits = [Suit::Clubs, Suit::Hearts, Suit::Spades];
+        let values: Vec<Suit> = (0..1000).map(|i| suits[i % 3]).collect();
+
+        let t = Retrieval::new(&keys, &values);
+        for (k, &v) in keys.iter().zip(&values) {
+            assert_eq!(t.get(k), Some(v));
+        }
+        let missing = (0..1000)
+            .filter(|i| t.get(&format!("other{}", i)).is_none())
+            .count();
+        assert!(missing > 0);
+    }
+
+    #[test]
+    fn approx_map() {
+        let keys: Vec<String> = (0..10_000).map(|i| format!("key{}", i)).collect();
+        let values: Vec<u16> = (0..10_000).map(|i| (i % 1000) as u16).collect();
+
+        let t = ApproxMap::new(&keys, &values, 8);
+        for (k, &v) in keys.iter().zip(&values) {
+            assert_eq!(t.get(k), Some(v));
+        }
+
+        // about 1 in 256 strangers get through
+        let passed = (0..10_000)
+            .filter(|i| t.get(&format!("other{}", i)).is_some())
+            .count();
+        assert!(passed < 100, "{}", passed);
+    }
+
+    #[test]
+    fn bits_per_key() {
+        let keys: Vec<u64> = (0..100_000).collect();
+        let values: Vec<u8> = keys.iter().map(|&k| (k % 4) as u8).collect();
+        let t = Retrieval::new(&keys, &values);
+        assert!(t.bits_per_key() < 2.5, "{}", t.bits_per_key());
+    }
+}
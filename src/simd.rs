@@ -0,0 +1,233 @@
+// Bulk hashing of byte-string keys: on x86_64 CPUs with AVX2, SipHash-1-3
+// over four keys at once, each in its own 64-bit lane of the state vectors.
+// The hashes are the ones SipHasher13 computes key by key, which is what
+// other CPUs fall back to.
+//
+// The lanes advance together for as many words as the shortest key has, and
+// through the length blocks too if the keys are as many words long; longer
+// keys otherwise finish on their own. Keys of about the same length, as in
+// most key sets, spend nearly all their words in the vector loop.
+
+use std::hash::Hasher;
+
+use crate::hash::SipHasher13;
+
+// sip13 appends to out the SipHash-1-3 hash, keyed by k0 and k1, of each key
+// written after the eight bytes of prefix, if there is one.
+pub(crate) fn sip13<'a, I>(k0: u64, k1: u64, prefix: Option<u64>, keys: I, out: &mut Vec<u64>)
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            // safe: the CPU was just checked for AVX2
+            return unsafe { avx2::sip13(k0, k1, prefix, keys.into_iter(), out) };
+        }
+    }
+    out.extend(keys.into_iter().map(|k| scalar(k0, k1, prefix, k)));
+}
+
+fn scalar(k0: u64, k1: u64, prefix: Option<u64>, key: &[u8]) -> u64 {
+    let mut h = SipHasher13::new_with_keys(k0, k1);
+    if let Some(p) = prefix {
+        h.write(&p.to_le_bytes());
+    }
+    h.write(key);
+    h.finish()
+}
+
+// word returns the j'th word SipHash compresses for msg, written after extra
+// bytes of prefix: a full 8 bytes, or past them the length block, which
+// holds the length and the last bytes.
+#[inline(always)]
+fn word(msg: &[u8], extra: usize, j: usize) -> u64 {
+    let at = 8 * j;
+    let mut buf = [0u8; 8];
+    match msg.get(at..at + 8) {
+        Some(w) => {
+            buf.copy_from_slice(w);
+            u64::from_le_bytes(buf)
+        }
+        None => {
+            buf[..msg.len() - at].copy_from_slice(&msg[at..]);
+            u64::from_le_bytes(buf) | (((msg.len() + extra) as u64 & 0xff) << 56)
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx2 {
+    use std::arch::x86_64::*;
+
+    use super::{scalar, word};
+    use crate::hash::SipHasher13;
+
+    // State is SipHash's four state words, one lane per key.
+    struct State {
+        v0: __m256i,
+        v1: __m256i,
+        v2: __m256i,
+        v3: __m256i,
+    }
+
+    #[inline(always)]
+    unsafe fn rotl<const L: i32, const R: i32>(x: __m256i) -> __m256i {
+        _mm256_or_si256(_mm256_slli_epi64::<L>(x), _mm256_srli_epi64::<R>(x))
+    }
+
+    impl State {
+        #[inline(always)]
+        unsafe fn new(k0: u64, k1: u64) -> State {
+            State {
+                v0: _mm256_set1_epi64x((k0 ^ 0x736f_6d65_7073_6575) as i64),
+                v1: _mm256_set1_epi64x((k1 ^ 0x646f_7261_6e64_6f6d) as i64),
+                v2: _mm256_set1_epi64x((k0 ^ 0x6c79_6765_6e65_7261) as i64),
+                v3: _mm256_set1_epi64x((k1 ^ 0x7465_6462_7974_6573) as i64),
+            }
+        }
+
+        // Round is SipHasher13's round. AVX2 has no rotate, so rotating by 32
+        // swaps each lane's halves and by 16 shuffles its bytes, which beat
+        // the two shifts and an or the other amounts take.
+        #[inline(always)]
+        unsafe fn round(&mut self) {
+            let rot16 = _mm256_setr_epi8(
+                6, 7, 0, 1, 2, 3, 4, 5, 14, 15, 8, 9, 10, 11, 12, 13, 6, 7, 0, 1, 2, 3, 4, 5, 14,
+                15, 8, 9, 10, 11, 12, 13,
+            );
+            self.v0 = _mm256_add_epi64(self.v0, self.v1);
+            self.v1 = rotl::<13, 51>(self.v1);
+            self.v1 = _mm256_xor_si256(self.v1, self.v0);
+            self.v0 = _mm256_shuffle_epi32::<0b10_11_00_01>(self.v0);
+            self.v2 = _mm256_add_epi64(self.v2, self.v3);
+            self.v3 = _mm256_shuffle_epi8(self.v3, rot16);
+            self.v3 = _mm256_xor_si256(self.v3, self.v2);
+            self.v0 = _mm256_add_epi64(self.v0, self.v3);
+            self.v3 = rotl::<21, 43>(self.v3);
+            self.v3 = _mm256_xor_si256(self.v3, self.v0);
+            self.v2 = _mm256_add_epi64(self.v2, self.v1);
+            self.v1 = rotl::<17, 47>(self.v1);
+            self.v1 = _mm256_xor_si256(self.v1, self.v2);
+            self.v2 = _mm256_shuffle_epi32::<0b10_11_00_01>(self.v2);
+        }
+
+        #[inline(always)]
+        unsafe fn compress(&mut self, m: __m256i) {
+            self.v3 = _mm256_xor_si256(self.v3, m);
+            self.round();
+            self.v0 = _mm256_xor_si256(self.v0, m);
+        }
+
+        // Lanes returns the state words by word, then lane.
+        #[inline(always)]
+        unsafe fn lanes(&self) -> [[u64; 4]; 4] {
+            let mut v = [[0u64; 4]; 4];
+            for (v, x) in v.iter_mut().zip([self.v0, self.v1, self.v2, self.v3]) {
+                _mm256_storeu_si256(v.as_mut_ptr() as *mut __m256i, x);
+            }
+            v
+        }
+
+        #[inline(always)]
+        unsafe fn set_lanes(&mut self, v: &[[u64; 4]; 4]) {
+            let load = |v: &[u64; 4]| _mm256_loadu_si256(v.as_ptr() as *const __m256i);
+            self.v0 = load(&v[0]);
+            self.v1 = load(&v[1]);
+            self.v2 = load(&v[2]);
+            self.v3 = load(&v[3]);
+        }
+    }
+
+    // hash4 returns the hashes of four keys.
+    #[inline(always)]
+    unsafe fn hash4(k0: u64, k1: u64, prefix: Option<u64>, keys: [&[u8]; 4]) -> [u64; 4] {
+        let mut s = State::new(k0, k1);
+        let extra = if let Some(p) = prefix {
+            s.compress(_mm256_set1_epi64x(p as i64));
+            8
+        } else {
+            0
+        };
+
+        // full words in each key, before its length block
+        let words = keys.map(|k| k.len() / 8);
+        let common = *words.iter().min().unwrap();
+        for j in 0..common {
+            let w = |l: usize| {
+                let mut buf = [0u8; 8];
+                buf.copy_from_slice(&keys[l][8 * j..8 * j + 8]);
+                i64::from_le_bytes(buf)
+            };
+            s.compress(_mm256_set_epi64x(w(3), w(2), w(1), w(0)));
+        }
+        if words.iter().all(|&n| n == common) {
+            let w = |l: usize| word(keys[l], extra, common) as i64;
+            s.compress(_mm256_set_epi64x(w(3), w(2), w(1), w(0)));
+        } else {
+            let mut v = s.lanes();
+            for (l, &n) in words.iter().enumerate() {
+                let mut h = SipHasher13::from_state([v[0][l], v[1][l], v[2][l], v[3][l]]);
+                for j in common..=n {
+                    h.compress(word(keys[l], extra, j));
+                }
+                for (v, x) in v.iter_mut().zip(h.state()) {
+                    v[l] = x;
+                }
+            }
+            s.set_lanes(&v);
+        }
+
+        s.v2 = _mm256_xor_si256(s.v2, _mm256_set1_epi64x(0xff));
+        s.round();
+        s.round();
+        s.round();
+        let h = _mm256_xor_si256(_mm256_xor_si256(s.v0, s.v1), _mm256_xor_si256(s.v2, s.v3));
+        let mut out = [0u64; 4];
+        _mm256_storeu_si256(out.as_mut_ptr() as *mut __m256i, h);
+        out
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn sip13<'a, I>(
+        k0: u64,
+        k1: u64,
+        prefix: Option<u64>,
+        keys: I,
+        out: &mut Vec<u64>,
+    ) where
+        I: Iterator<Item = &'a [u8]>,
+    {
+        let mut block: [&[u8]; 4] = [&[]; 4];
+        let mut n = 0;
+        for k in keys {
+            block[n] = k;
+            n += 1;
+            if n == 4 {
+                out.extend(hash4(k0, k1, prefix, block));
+                n = 0;
+            }
+        }
+        out.extend(block[..n].iter().map(|k| scalar(k0, k1, prefix, k)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{scalar, sip13};
+
+    #[test]
+    fn matches_scalar() {
+        let keys: Vec<Vec<u8>> = (0..70u8).map(|n| (0..n).collect()).collect();
+        // runs of similar and very different lengths, and a partial block
+        let mut order: Vec<&[u8]> = keys.iter().map(|k| &k[..]).collect();
+        order.extend(keys.iter().rev().step_by(3).map(|k| &k[..]));
+
+        for (k0, k1, prefix) in [(0, 0, None), (1, 2, Some(7)), (!0, 3, Some(0))] {
+            let mut got = Vec::new();
+            sip13(k0, k1, prefix, order.iter().copied(), &mut got);
+            let want: Vec<u64> = order.iter().map(|k| scalar(k0, k1, prefix, k)).collect();
+            assert_eq!(got, want);
+        }
+    }
+}
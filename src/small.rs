@@ -0,0 +1,189 @@
+use std::hash::Hash;
+
+use crate::hash::{DefaultState, KeyHasher};
+use crate::MAX_RETRIES;
+
+// SmallTable is a table for fewer than 65,536 keys, as in keyword and
+// configuration tables, made with 32-bit arithmetic throughout. A key's
+// 64-bit hash is split in two halves, one picking its bucket and the other
+// mixed with the bucket's seed into a slot, and the seeds and values are
+// u16s. Buckets of one key search for a seed like the others rather than
+// take a slot directly, which leaves nothing in the seeds but seeds, and so
+// that search has room the table keeps a sixteenth of its slots spare. With
+// a bucket a key and no rounding up to powers of two, it takes 33 bits a
+// key to a Table's 64 to 128. Hashing the keys takes most of the time of
+// both builds and queries, so it builds a little faster than a Table and
+// queries about as fast.
+//
+// Query returns the key's index in the input, as Table::query does.
+pub struct SmallTable {
+    hasher: KeyHasher<DefaultState>,
+    seeds: Vec<u16>,
+    values: Vec<u16>,
+    len: usize,
+}
+
+impl SmallTable {
+    // New builds a table over keys, of which there must be fewer than 2^16.
+    // Should a placement fail, the keys are hashed again with another seed.
+    pub fn new<K: Hash>(keys: &[K]) -> SmallTable {
+        assert!(keys.len() < 1 << 16, "too many keys for a SmallTable");
+
+        let nbuckets = keys.len().max(1);
+        let size = keys.len() + keys.len() / 16 + 1;
+        let mut hasher = KeyHasher::new(DefaultState::default());
+        for retry in 0..=MAX_RETRIES {
+            hasher.seed = retry as u64;
+            let hashes = keys.iter().map(|k| hasher.hash(k));
+            if let Some((seeds, values)) = place(hashes, keys.len(), nbuckets, size) {
+                return SmallTable {
+                    hasher,
+                    seeds,
+                    values,
+                    len: keys.len(),
+                };
+            }
+        }
+        panic!("no seed found; are the keys distinct?")
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // BitsPerKey reports the space used by the seeds and values.
+    pub fn bits_per_key(&self) -> f64 {
+        ((self.seeds.len() + self.values.len()) * 16) as f64 / self.len.max(1) as f64
+    }
+
+    // Query looks up k and returns its index in the input. Keys that were
+    // not in the input get an arbitrary value below len, or 0 in an empty
+    // table.
+    pub fn query<K: Hash + ?Sized>(&self, k: &K) -> usize {
+        let hash = self.hasher.hash(k);
+        let seed = self.seeds[reduce32((hash >> 32) as u32, self.seeds.len())];
+        let slot = reduce32(
+            mix32((hash as u32).wrapping_add(seed as u32)),
+            self.values.len(),
+        );
+        self.values[slot] as usize
+    }
+}
+
+// place assigns the seeds and values for n keys with hashes, or returns None
+// if some bucket has no seed that fits in a u16. Buckets are placed largest
+// first, and equal sizes in bucket order.
+fn place<I>(hashes: I, n: usize, nbuckets: usize, size: usize) -> Option<(Vec<u16>, Vec<u16>)>
+where
+    I: Iterator<Item = u64>,
+{
+    // the keys' displacement halves, grouped by bucket with a counting sort
+    let mut bucket = Vec::with_capacity(n);
+    let mut disp = Vec::with_capacity(n);
+    let mut offsets = vec![0u32; nbuckets + 1];
+    for h in hashes {
+        let b = reduce32((h >> 32) as u32, nbuckets);
+        bucket.push(b as u32);
+        disp.push(h as u32);
+        offsets[b + 1] += 1;
+    }
+    let mut count = [0usize; 64];
+    for b in 0..nbuckets {
+        count[(offsets[b + 1] as usize).min(63)] += 1;
+        offsets[b + 1] += offsets[b];
+    }
+    let mut entries = vec![(0u16, 0u32); n];
+    let mut at = offsets.clone();
+    for (idx, (&b, &d)) in bucket.iter().zip(&disp).enumerate() {
+        entries[at[b as usize] as usize] = (idx as u16, d);
+        at[b as usize] += 1;
+    }
+    let keys = |b: usize| &entries[offsets[b] as usize..offsets[b + 1] as usize];
+
+    // largest first: sizes of 63 and over, should there be any, go first
+    let mut start = [0usize; 64];
+    let mut s = 0;
+    for len in (1..64).rev() {
+        start[len] = s;
+        s += count[len];
+    }
+    let mut order = vec![0u32; s];
+    for b in 0..nbuckets {
+        let len = keys(b).len().min(63);
+        if len > 0 {
+            order[start[len]] = b as u32;
+            start[len] += 1;
+        }
+    }
+
+    let mut seeds = vec![0u16; nbuckets];
+    let mut values = vec![0u16; size];
+    let mut taken = vec![0u64; size.div_ceil(64)];
+    let mut slots = Vec::new();
+    for &b in &order {
+        let subkeys = keys(b as usize);
+        let seed = (1..=u16::MAX).find(|&seed| {
+            slots.clear();
+            subkeys.iter().all(|&(_, d)| {
+                let i = reduce32(mix32(d.wrapping_add(seed as u32)), size);
+                let free = taken[i / 64] & 1 << (i % 64) == 0 && !slots.contains(&i);
+                slots.push(i);
+                free
+            })
+        })?;
+        for (&i, &(idx, _)) in slots.iter().zip(subkeys) {
+            taken[i / 64] |= 1 << (i % 64);
+            values[i] = idx;
+        }
+        seeds[b as usize] = seed;
+    }
+    Some((seeds, values))
+}
+
+// reduce32 maps x onto 0..n by multiplying and keeping the high half.
+#[inline(always)]
+fn reduce32(x: u32, n: usize) -> usize {
+    ((x as u64 * n as u64) >> 32) as usize
+}
+
+// mix32 is murmur3's finalizer.
+#[inline(always)]
+fn mix32(mut x: u32) -> u32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x85eb_ca6b);
+    x ^= x >> 13;
+    x = x.wrapping_mul(0xc2b2_ae35);
+    x ^ x >> 16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SmallTable;
+    use crate::Table;
+
+    #[test]
+    fn small_table() {
+        for n in [0, 1, 2, 100, 5000, (1 << 16) - 1] {
+            let keys: Vec<String> = (0..n).map(|i| format!("k{}", i)).collect();
+            let t = SmallTable::new(&keys);
+            assert_eq!(t.len(), n);
+            for (i, k) in keys.iter().enumerate() {
+                assert_eq!(t.query(k), i);
+            }
+            if n >= 100 {
+                assert!(t.bits_per_key() < Table::new(&keys).bits_per_key() / 1.8);
+            }
+        }
+        assert_eq!(SmallTable::new::<&str>(&[]).query("x"), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "are the keys distinct?")]
+    fn duplicates() {
+        SmallTable::new(&["a", "b", "a"]);
+    }
+}
@@ -0,0 +1,164 @@
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, RwLock};
+
+use crate::hash::DefaultState;
+use crate::{BuildError, Table, TableBuilder};
+
+// SwappableTable holds the current table of a service whose key set changes,
+// and replaces it while queries go on: store publishes a new table at once,
+// and rebuild_in_background builds one off the querying threads first.
+//
+// Readers query through a TableReader, which keeps its own handle on the
+// table and only checks, with one atomic load, whether a newer one has been
+// published since; the lock inside is taken once per swap, not per query. A
+// replaced table is freed when the last reader holding it moves on.
+pub struct SwappableTable<S = DefaultState> {
+    current: RwLock<Arc<Table<S>>>,
+    // bumped by each store, after the new table is in place
+    generation: AtomicU64,
+}
+
+// TableReader queries a SwappableTable from one thread, on the table most
+// recently published as of its last query.
+pub struct TableReader<'a, S = DefaultState> {
+    shared: &'a SwappableTable<S>,
+    table: Arc<Table<S>>,
+    generation: u64,
+}
+
+impl<S> SwappableTable<S> {
+    pub fn new(t: Table<S>) -> SwappableTable<S> {
+        SwappableTable {
+            current: RwLock::new(Arc::new(t)),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    // Load returns the current table.
+    pub fn load(&self) -> Arc<Table<S>> {
+        let current = self.current.read().unwrap_or_else(|err| err.into_inner());
+        Arc::clone(&current)
+    }
+
+    // Store publishes t in place of the current table. Queries already
+    // running finish on the old one.
+    pub fn store(&self, t: Table<S>) {
+        let t = Arc::new(t);
+        let mut current = self.current.write().unwrap_or_else(|err| err.into_inner());
+        *current = t;
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+
+    // Reader returns a reader starting on the current table.
+    pub fn reader(&self) -> TableReader<'_, S> {
+        let generation = self.generation.load(Ordering::Acquire);
+        TableReader {
+            shared: self,
+            table: self.load(),
+            generation,
+        }
+    }
+}
+
+impl<S: BuildHasher + Clone + Send + Sync + 'static> SwappableTable<S> {
+    // RebuildInBackground builds a table over keys with builder, by a job
+    // handed to spawn, and stores it once built. Spawn decides where the
+    // build runs: |job| { std::thread::spawn(job); } for a thread of its
+    // own, or a thread pool's spawn to share its threads. The receiver gets
+    // the build's outcome, a BuildError if it was cancelled or ran out of
+    // time, in which case the current table stays; dropping it is fine.
+    pub fn rebuild_in_background<K, F>(
+        self: &Arc<Self>,
+        builder: TableBuilder<S>,
+        keys: Vec<K>,
+        spawn: F,
+    ) -> Receiver<Result<(), BuildError>>
+    where
+        K: Hash + Send + 'static,
+        F: FnOnce(Box<dyn FnOnce() + Send>),
+    {
+        let (done, outcome) = mpsc::channel();
+        let shared = Arc::clone(self);
+        spawn(Box::new(move || {
+            let r = builder.try_build(&keys).map(|t| shared.store(t));
+            let _ = done.send(r);
+        }));
+        outcome
+    }
+}
+
+impl<S: BuildHasher> TableReader<'_, S> {
+    // Query returns the value for k in the current table, after switching
+    // to it if a new one was published since the last query.
+    pub fn query<K: Hash + ?Sized>(&mut self, k: &K) -> usize {
+        self.refresh();
+        self.table.query(k)
+    }
+
+    // Table returns the table this reader queries, after switching to the
+    // current one if a new one was published since the last query.
+    pub fn table(&mut self) -> &Table<S> {
+        self.refresh();
+        &self.table
+    }
+
+    fn refresh(&mut self) {
+        let generation = self.shared.generation.load(Ordering::Acquire);
+        if generation != self.generation {
+            self.table = self.shared.load();
+            self.generation = generation;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::SwappableTable;
+    use crate::{BuildError, CancellationToken, Table, TableBuilder};
+
+    #[test]
+    fn swappable_table() {
+        let old: Vec<String> = (0..1000).map(|i| format!("old{}", i)).collect();
+        let new: Vec<String> = (0..2000).map(|i| format!("new{}", i)).collect();
+
+        let shared = Arc::new(SwappableTable::new(Table::new(&old)));
+        let mut r = shared.reader();
+        assert_eq!(r.query("old7"), 7);
+        let before = shared.load();
+
+        let done = shared.rebuild_in_background(TableBuilder::new(), new.clone(), |job| {
+            thread::spawn(job);
+        });
+        assert_eq!(done.recv().unwrap(), Ok(()));
+        assert_eq!(r.query("new1999"), 1999);
+        assert_eq!(r.table().len(), new.len());
+
+        // the old table lives on while something holds it
+        assert_eq!(before.query("old7"), 7);
+        assert_eq!(Arc::strong_count(&before), 1);
+
+        // a failed rebuild leaves the table as it was
+        let token = CancellationToken::new();
+        token.cancel();
+        let b = TableBuilder::new().cancellation(token);
+        let done = shared.rebuild_in_background(b, old, |job| job());
+        assert_eq!(done.recv().unwrap(), Err(BuildError::Cancelled));
+        assert_eq!(r.query("new5"), 5);
+
+        // readers on other threads see the new table once it's stored
+        shared.store(Table::new(&new[..10]));
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut r = shared.reader();
+                    assert_eq!(r.table().len(), 10);
+                });
+            }
+        });
+    }
+}
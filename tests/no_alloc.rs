@@ -0,0 +1,49 @@
+// TableRef must neither allocate to load a table nor to query it. This test
+// swaps in a counting allocator, which is global to the test binary, so it
+// has a binary of its own.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use mph_rs::hash::SipState;
+use mph_rs::{TableBuilder, TableRef};
+
+// Counting counts this thread's allocations.
+struct Counting;
+
+thread_local! {
+    static ALLOCS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+#[test]
+fn no_alloc() {
+    let keys: Vec<String> = (0..1000).map(|i| format!("Key{}", i)).collect();
+    let t = TableBuilder::new()
+        .minimal(true)
+        .second_hash(true)
+        .build(&keys);
+    let bytes = t.to_bytes();
+
+    let before = ALLOCS.with(|n| n.get());
+    let r = TableRef::<SipState>::from_bytes(&bytes).unwrap();
+    let mut sum = 0;
+    for i in 0..1000 {
+        sum += r.query("Key1") + r.query_bytes(b"Key1");
+        sum += r.query_prehashed(i);
+    }
+    assert_eq!(ALLOCS.with(|n| n.get()), before, "{}", sum);
+}